@@ -1,8 +1,9 @@
 use std::{
     fmt::{self, Debug, Formatter, Result},
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     time::{SystemTime, UNIX_EPOCH},
 };
+use ring::digest::{digest, SHA256};
 
 use super::*;
 
@@ -51,6 +52,7 @@ impl Debug for Block {
 
 impl Block {
     // "constructor arguments should define the object's required state"
+    // Fails if the transactions have no merkle root (a transaction is duplicated).
     pub fn new(
         signature: Signature,
         slot: u64, // index
@@ -63,13 +65,15 @@ impl Block {
         working_stake: u64,
         total_stake: u64,
         block_height: u64,
-    ) -> Self {
-        Block {
+    ) -> std::result::Result<Self, String> {
+        let transaction_root = transaction_root(&transactions)
+            .ok_or_else(|| String::from("Invalid block - duplicated transaction"))?;
+        Ok(Block {
             signature,
             slot,
             parent_timestamp,
             timestamp,
-            transaction_root: Hash([0; 32]),
+            transaction_root,
             is_confirmed: false,
             prev_block_hash,
             rewards,
@@ -79,7 +83,7 @@ impl Block {
             working_stake,
             total_stake,
             block_height,
-        }
+        })
     }
 
     pub fn verify_tiny_pow(&self, difficulty: u64) -> bool {
@@ -125,6 +129,11 @@ impl Block {
         true
     }
 
+    // Verify that the transaction_root commits to exactly this transaction list
+    pub fn verify_transaction_root(&self) -> bool {
+        transaction_root(&self.transactions) == Some(self.transaction_root)
+    }
+
     // Verify the transactions
     // fn verify_transactions(&self) -> bool {
     //     // Verify that each transaction in the block is valid
@@ -138,6 +147,52 @@ impl Block {
     // }
 }
 
+// Leaf and inner nodes are hashed with different prefixes (domain separation), so an inner node
+// (the concatenation of two hashes) can never be reinterpreted as a leaf to forge a shorter tree with the same root.
+const MERKLE_LEAF_PREFIX: u8 = 0x00;
+const MERKLE_NODE_PREFIX: u8 = 0x01;
+
+// The root of the transaction merkle tree.
+// The last hash of an odd level is paired with itself, so [a, b, c] and [a, b, c, c] would share a root (CVE-2012-2459).
+// Every such list contains the same transaction twice, so a list with duplicated transactions has no root (None).
+pub fn transaction_root(transactions: &[Transaction]) -> Option<Hash> {
+    let txids = transactions
+        .iter()
+        .map(|transaction| transaction.finalize())
+        .collect::<Vec<Hash>>();
+
+    let mut seen = HashSet::new();
+    if txids.iter().any(|txid| !seen.insert(*txid)) {
+        return None
+    }
+    if txids.is_empty() {
+        return Some(Hash([0; 32]))
+    }
+
+    let mut level = txids
+        .iter()
+        .map(|txid| merkle_hash(MERKLE_LEAF_PREFIX, &[&txid.0]))
+        .collect::<Vec<Hash>>();
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            level.push(*level.last().unwrap());
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| merkle_hash(MERKLE_NODE_PREFIX, &[&pair[0].0, &pair[1].0]))
+            .collect();
+    }
+    Some(level[0])
+}
+
+fn merkle_hash(prefix: u8, parts: &[&[u8]]) -> Hash {
+    let mut bytes = vec![prefix];
+    for part in parts {
+        bytes.extend(*part);
+    }
+    Hash(digest(&SHA256, &bytes).as_ref().try_into().expect("Invalid bytes to hash"))
+}
+
 impl Default for Block {
     fn default() -> Self {
         Self {
@@ -38,6 +38,11 @@ impl Blockchain {
     }
 
     pub fn add_block(&mut self, block: &mut Block) -> Result<(), String> {
+        // The transaction_root is part of the block hash, so it must commit to exactly the transactions carried by the block
+        if !block.verify_transaction_root() {
+            return Err(String::from("Invalid block - transaction root doesn't match the transactions"));
+        }
+
         if self.blocks.is_empty() {
             // Special case for genesis block
             if block.prev_block_hash != Hash([0u8; 32]) {
@@ -125,7 +125,7 @@ impl Sys {
 
 
     // leader node's work. 동시에 여러 노드가 진행할 수 있음.
-    pub fn create_block(&mut self) -> Block {
+    pub fn create_block(&mut self) -> Result<Block, String> {
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
         let mut block = Block::new(
             Signature([0u8; 64]),
@@ -138,7 +138,7 @@ impl Sys {
             0,
             0,
             0
-        );
+        )?;
 
         // a tiny of PoW. Acts as a spam filter.
         // 단일 노드에서 너무 많은 블록이 생성되는 것을 방지하기 위한 스팸필터.
@@ -147,7 +147,7 @@ impl Sys {
             block.slot += 1;
         }
 
-        block
+        Ok(block)
     }
 
     pub fn update_chain(&mut self, block: Block, blockchain: &mut Blockchain) {
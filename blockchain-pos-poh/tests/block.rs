@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use blockchainlib::*;
+use blockchainlib::block::transaction_root;
+use blockchainlib::transaction::{Message, MessageHeader};
+
+// amount만 다른 tx. amount가 다르면 txid도 다르다.
+fn transfer(amount: u64) -> Transaction {
+    let sender = Pubkey::new([1; 32]);
+    let recipient = Pubkey::new([2; 32]);
+    let message = Message {
+        header: MessageHeader {
+            num_required_signatures: 1,
+            num_readonly_signed_accounts: 0,
+            num_readonly_unsigned_accounts: 0,
+        },
+        account_keys: vec![sender, recipient],
+        recent_blockhash: Hash([0; 32]),
+    };
+    Transaction::new(vec![Signature([0; 64])], sender, recipient, amount, message, 0, sender, Hash([0; 32]), vec![])
+}
+
+fn block(prev_block_hash: Hash, transactions: Vec<Transaction>) -> Result<Block, String> {
+    Block::new(Signature([0; 64]), 1, 0, 0, prev_block_hash, HashMap::new(), transactions, 0, 0, 1)
+}
+
+#[test]
+fn rejects_duplicated_transactions() {
+    let (a, b, c) = (transfer(1), transfer(2), transfer(3));
+    assert!(transaction_root(&[a.clone(), b.clone(), c.clone()]).is_some());
+
+    // 홀수 level의 마지막 hash를 복제한 [a, b, c, c]는 [a, b, c]와 root가 같아지므로 root를 만들지 않는다.
+    assert!(transaction_root(&[a.clone(), b.clone(), c.clone(), c.clone()]).is_none());
+    assert!(transaction_root(&[a.clone(), b.clone(), a.clone()]).is_none());
+    assert!(block(Hash([0; 32]), vec![a.clone(), b, a]).is_err());
+}
+
+#[test]
+fn rejects_a_wrong_transaction_root() {
+    let (a, b) = (transfer(1), transfer(2));
+    let mut blockchain = Blockchain::genesis();
+    // Blockchain::genesis()의 genesis block hash는 0이다.
+    let prev_block_hash = Hash([0; 32]);
+
+    // header의 transaction_root를 다른 tx 목록의 root로 바꿔치기한 block
+    let mut valid = block(prev_block_hash, vec![a.clone(), b]).unwrap();
+    assert!(valid.verify_transaction_root());
+    let mut json = serde_json::to_value(&valid).unwrap();
+    json["transaction_root"] = serde_json::to_value(transaction_root(&[a]).unwrap()).unwrap();
+    let mut forged: Block = serde_json::from_value(json).unwrap();
+    assert!(!forged.verify_transaction_root());
+
+    let height = blockchain.height;
+    assert!(blockchain.add_block(&mut forged).is_err());
+    assert_eq!(blockchain.height, height);
+
+    // root만 다를 뿐 나머지는 유효하다.
+    blockchain.add_block(&mut valid).unwrap();
+    assert_eq!(blockchain.height, height + 1);
+}
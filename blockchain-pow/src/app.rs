@@ -1,82 +1,389 @@
 use super::*;
+use std::{
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+use crate::blockchain::BlockValidationErr;
+use crate::coin_selection::{CoinSelector, Strategy};
+use crate::mempool::MempoolErr;
+use crate::storage::{BlockStore, StorageErr};
+use crate::wallet::{Wallet, WalletErr};
 
-pub fn run() {
-    // let mut input = String::new();
+// block과 UTXO set을 저장하는 기본 directory. --data-dir로 바꿀 수 있다.
+pub const DATA_DIR: &str = "blockchain_data";
+// data dir 아래에서 wallet file(<이름>.wallet)을 저장하는 directory
+pub const WALLET_DIR: &str = "wallets";
+// --wallet을 주지 않았을 때 사용하는 wallet
+pub const DEFAULT_WALLET: &str = "default";
 
-    println!("Enter Sender's addr: ");
-    let sender = input().inner;
+// custom Error type
+#[derive(Debug)]
+pub enum AppErr {
+    NotInitialized, // data dir에 chain이 없음. init을 먼저 실행해야 한다.
+    AlreadyInitialized,
+    UnknownWallet(String), // wallet file도 없고 유효한 address도 아님
+    BlockNotFound(String),
+    InvalidChain(String), // 저장된 chain이나 UTXO set이 검증을 통과하지 못함
+    Storage(StorageErr),
+    Wallet(WalletErr),
+    Mempool(MempoolErr),
+    Network(std::io::Error),
+}
 
-    println!("Enter Recipient's addr: ");
-    let recipient = input().inner;
+impl From<StorageErr> for AppErr {
+    fn from(e: StorageErr) -> Self {
+        match e {
+            StorageErr::Corrupted(reason) => AppErr::InvalidChain(reason),
+            StorageErr::Invalid(e) => AppErr::InvalidChain(format!("{:?}", e)),
+            e => AppErr::Storage(e),
+        }
+    }
+}
+
+impl From<WalletErr> for AppErr {
+    fn from(e: WalletErr) -> Self {
+        AppErr::Wallet(e)
+    }
+}
 
-    println!("Enter transfer amount: ");
-    let amount = input().to_u64().expect("please input correct number");
+impl From<MempoolErr> for AppErr {
+    fn from(e: MempoolErr) -> Self {
+        AppErr::Mempool(e)
+    }
+}
 
-    let difficulty = 0x000fffffffffffffffffffffffffffff;
+impl From<BlockValidationErr> for AppErr {
+    fn from(e: BlockValidationErr) -> Self {
+        AppErr::InvalidChain(format!("{:?}", e))
+    }
+}
+
+impl Display for AppErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AppErr::NotInitialized => write!(f, "no chain in the data dir, run `init` first"),
+            AppErr::AlreadyInitialized => write!(f, "the data dir already has a chain"),
+            AppErr::UnknownWallet(name) => write!(f, "{} is neither a wallet nor a valid address", name),
+            AppErr::BlockNotFound(block) => write!(f, "block {} not found", block),
+            AppErr::InvalidChain(reason) => write!(f, "invalid chain: {}", reason),
+            AppErr::Storage(e) => write!(f, "storage error: {:?}", e),
+            AppErr::Wallet(WalletErr::Transaction(BlockValidationErr::InsufficientInputValue)) => write!(f, "insufficient funds"),
+            AppErr::Wallet(e) => write!(f, "wallet error: {:?}", e),
+            AppErr::Mempool(e) => write!(f, "transaction rejected: {:?}", e),
+            AppErr::Network(e) => write!(f, "network error: {}", e),
+        }
+    }
+}
 
+// miner_addr에게 coinbase를 지급하는 genesis block을 채굴한다.
+fn genesis_block(miner_addr: &str) -> Result<Block, BlockValidationErr> {
     let mut genesis_block = Block::new(
         0,
         now(),
         vec![0; 32],
         vec![],
-        difficulty
+        blockchain::GENESIS_BITS,
     );
 
-    let satoshi_tx = Transaction {
-        inputs: vec![],
-        outputs: vec![
-            transaction::Output {
-                to_addr: "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa".to_owned(),
-                value: 50,
-            },
-        ],
-    };
+    let satoshi_tx = Transaction::coinbase(0, vec![
+        transaction::Output::new(miner_addr, blockchain::INITIAL_SUBSIDY),
+    ]);
 
     genesis_block.add_transaction(satoshi_tx);
 
-    let mut blockchain = Blockchain::new();
+    genesis_block.check_merkle_and_mining()?;
+    Ok(genesis_block)
+}
 
-    let mut utxo_set = UtxoSet::new();
+// data dir의 WALLET_DIR에 이름으로 저장된 wallet들. passphrase는 wallet file에 저장되지 않으므로 실행할 때마다 받는다.
+struct WalletDir {
+    dir: PathBuf,
+    passphrase: String,
+}
+
+impl WalletDir {
+    fn new(data_dir: &Path, passphrase: &str) -> Self {
+        WalletDir {
+            dir: data_dir.join(WALLET_DIR),
+            passphrase: passphrase.to_owned(),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.wallet", name))
+    }
 
-    genesis_block.check_merkle_and_mining().expect("Failed to execute mining");
+    fn exists(&self, name: &str) -> bool {
+        self.path(name).exists()
+    }
 
-    println!("Mined genesis Satoshi {:?}", &genesis_block);
+    fn load(&self, name: &str) -> Result<Wallet, AppErr> {
+        let path = self.path(name);
+        if !path.exists() {
+            return Err(AppErr::UnknownWallet(name.to_owned()))
+        }
+        Ok(Wallet::load(path, &self.passphrase)?)
+    }
 
-    blockchain.update_with_block(genesis_block, &mut utxo_set, &"genesis".to_string()).expect("Failed to add genesis block");
+    // wallet이 없으면 새로 만들어 저장한다. mnemonic은 terminal이나 log에 남지 않도록 출력하지 않는다.
+    fn load_or_create(&self, name: &str) -> Result<Wallet, AppErr> {
+        if self.exists(name) {
+            return self.load(name)
+        }
 
+        let wallet = Wallet::generate(&self.passphrase);
+        self.save(name, &wallet)?;
+        println!("Created {} wallet at {}. Back up its mnemonic with `show-mnemonic {}`", name, self.path(name).display(), name);
+        Ok(wallet)
+    }
 
-    // new_block(네트워크에서 tx를 받아 block을 생성할 때)
-    // 1. block을 생성하고 inputs, outputs들이 있는 tx들로 각각의 txid들을 생성해 하나의 블록에 하나의 merkle_root를 생성함.
-    let mut new_block = blockchain.spawn_block(difficulty, sender.to_owned(), recipient.to_owned(), amount, &utxo_set);
+    fn save(&self, name: &str, wallet: &Wallet) -> Result<(), AppErr> {
+        std::fs::create_dir_all(&self.dir).map_err(WalletErr::Io)?;
+        Ok(wallet.save(self.path(name))?)
+    }
+}
 
-    // 2. 채굴자가 다른 node로부터 갱신된 block을 받아 mining함(mining 수행 전에 txid들로 merkle_root를
-    // 자체적으로 계산해 보고 블록헤더에서 받은 merkle_root와 동일한지 체크하고 동일하면 mining, 다르다면 버린다.)
+// data dir에 저장된 chain, UTXO set, mempool과 wallet들.
+// 각 command는 App을 열어 작업한 뒤 바뀐 상태를 다시 저장하고 종료한다.
+pub struct App {
+    wallets: WalletDir,
+    store: BlockStore,
+    pub blockchain: Blockchain,
+    pub utxo_set: UtxoSet,
+    pub mempool: Mempool,
+}
 
-    // Integrity check with merkle root and mining
-    new_block.check_merkle_and_mining().expect("Failed to execute mining");
-    println!("Mined {:?}", &new_block);
+impl App {
+    // 이전 실행에서 저장한 chain과 UTXO set, mempool을 읽어 온다. data dir이 없으면 새로 만든다.
+    // coinbase_maturity는 consensus rule이므로 같은 chain에는 항상 같은 값을 사용해야 한다.
+    // passphrase는 wallet을 만들고 읽을 때 사용한다.
+    pub fn open<P: AsRef<Path>>(data_dir: P, coinbase_maturity: u32, passphrase: &str) -> Result<Self, AppErr> {
+        let data_dir = data_dir.as_ref();
+        let store = BlockStore::open(data_dir)?;
+        let mut blockchain = Blockchain::with_coinbase_maturity(coinbase_maturity);
+        let mut utxo_set = UtxoSet::new();
+        let mut mempool = Mempool::default();
+        store.load(&mut blockchain, &mut utxo_set)?;
+        store.load_mempool(&mut mempool, &blockchain, &utxo_set)?;
 
-    // mining이 성공적으로 완료된다면 네트워크로 보낸다. 네트워크는 완료된 블록을 blockchain에 추가하기 전에
-    // broadcast해 다른 node들(miner)에게도 merkle root를 추가적으로 검증하게 한다. 이 과정은 채굴이 아니다.
-    // 추가적 검증이 완료되면 blockchain에 추가시킨다. 그렇지만 이것으로 최종 blockchain이 결정되는 것은 아니다.
-    // btc에는 confirmation thresholds(확인 임계값) chain rule이 있는데, 그 위에 6개의 추가적인 block이 쌓일
-    // 때까지 최종 블록으로 간주하지 않는다. 총 7개의 blockchain에 추가된 block 중, 누적 PoW가 가장 많은,
-    // 가장 긴 chain(longest 또는 heaviest chain이라고 함) 하나가 Winner가 되어 네트워크의 유효한 block으로 간주된다.
-    // Winner로 선택되지 않은 나머지 6개의 block은 여전히 네트워크에 존재하고, 블록체인에도 같은 layer에 존재하지만
-    // 현재로서는 invalid상태이다. 즉 TX가 유효한 chain의 part로 인정되지 않는다. 그러나 추후에 새로운 layer에서
-    // 이 버려진 invalid block을 history의 일부로 포함하는 더 긴 chain이 구성되어
-    // 새로운 block으로서 또 다른 6개의 경쟁 block을 이길 경우, 이 invalid block은 다시 valid로 간주되고
-    // 새로운 chain에 포함된다.
-    // btc의 경우 여기서 한가지 overcompensate 될 여지가 남겨진다.
-    // 예를 들어, 만약 같은 tx들로 구성된 새로운 block들이 경쟁한다면? 하나의 강한 block만 유효하게 되고,
-    // 유효한 block과 같은 tx를 가진 invaild block이 blockchain의 같은 layer에 남게된다.
-    // 추후에 다른 layer에서 Winner block이, 이미 nonce가 밝혀진, 이전의 winner block과
-    // tx가 같은 invalid block을 history로 갖는다면 이 중복 block도 보상을 받고 layer에 추가 된다.(중복 Tx, nonce를 가진 block들이 존재)
-    // 그렇지만 이것을 막으면 채굴자들의 보상을 줄이게 된다.
+        Ok(App {
+            wallets: WalletDir::new(data_dir, passphrase),
+            store,
+            blockchain,
+            utxo_set,
+            mempool,
+        })
+    }
+
+    // genesis block을 채굴해 wallet에게 coinbase를 지급한다. wallet이 없으면 새로 만든다.
+    pub fn init(&mut self, wallet_name: &str) -> Result<Block, AppErr> {
+        if !self.blockchain.chain.is_empty() {
+            return Err(AppErr::AlreadyInitialized)
+        }
 
-    blockchain.update_with_block(new_block, &mut utxo_set, &sender).expect("Failed to add block");
+        let mut wallet = self.wallets.load_or_create(wallet_name)?;
+        let genesis_block = genesis_block(&wallet.new_address())?;
+        self.blockchain.update_with_block(genesis_block.clone(), &mut self.utxo_set)?;
+        self.store.sync(&self.blockchain, &self.utxo_set)?;
+        self.wallets.save(wallet_name, &wallet)?;
+        Ok(genesis_block)
+    }
+
+    // tip 위에 blocks개의 block을 채굴한다. mempool에서 fee rate가 높은 tx부터 담고, coinbase는 wallet에게 지급한다.
+    pub fn mine(&mut self, blocks: u32, wallet_name: &str) -> Result<Vec<Block>, AppErr> {
+        self.require_chain()?;
+        let mut wallet = self.wallets.load_or_create(wallet_name)?;
+        let miner = Miner::default();
+
+        let mut mined = vec![];
+        for _ in 0..blocks {
+            // 1. mempool에서 fee per byte가 높은 tx부터 골라 block template을 만든다. 각 tx의 txid로 merkle_root를 계산한다.
+            let mut block = self.blockchain.block_template(&self.mempool, wallet.new_address());
+
+            // 2. template의 header만 hashing하며 target을 만족하는 nonce를 찾는다.
+            miner.mine(&mut block);
+
+            // mining이 성공적으로 완료된다면 네트워크로 보낸다. 네트워크는 완료된 블록을 blockchain에 추가하기 전에
+            // broadcast해 다른 node들(miner)에게도 merkle root를 추가적으로 검증하게 한다. 이 과정은 채굴이 아니다.
+            // 추가적 검증이 완료되면 blockchain에 추가시킨다. 그렇지만 이것으로 최종 blockchain이 결정되는 것은 아니다.
+            // btc에는 confirmation thresholds(확인 임계값) chain rule이 있는데, 그 위에 6개의 추가적인 block이 쌓일
+            // 때까지 최종 블록으로 간주하지 않는다. 총 7개의 blockchain에 추가된 block 중, 누적 PoW가 가장 많은,
+            // 가장 긴 chain(longest 또는 heaviest chain이라고 함) 하나가 Winner가 되어 네트워크의 유효한 block으로 간주된다.
+            // Winner로 선택되지 않은 나머지 6개의 block은 여전히 네트워크에 존재하고, 블록체인에도 같은 layer에 존재하지만
+            // 현재로서는 invalid상태이다. 즉 TX가 유효한 chain의 part로 인정되지 않는다. 그러나 추후에 새로운 layer에서
+            // 이 버려진 invalid block을 history의 일부로 포함하는 더 긴 chain이 구성되어
+            // 새로운 block으로서 또 다른 6개의 경쟁 block을 이길 경우, 이 invalid block은 다시 valid로 간주되고
+            // 새로운 chain에 포함된다.
+            // btc의 경우 여기서 한가지 overcompensate 될 여지가 남겨진다.
+            // 예를 들어, 만약 같은 tx들로 구성된 새로운 block들이 경쟁한다면? 하나의 강한 block만 유효하게 되고,
+            // 유효한 block과 같은 tx를 가진 invaild block이 blockchain의 같은 layer에 남게된다.
+            // 추후에 다른 layer에서 Winner block이, 이미 nonce가 밝혀진, 이전의 winner block과
+            // tx가 같은 invalid block을 history로 갖는다면 이 중복 block도 보상을 받고 layer에 추가 된다.(중복 Tx, nonce를 가진 block들이 존재)
+            // 그렇지만 이것을 막으면 채굴자들의 보상을 줄이게 된다.
+            // (단일 node로 실행하는 이 command는 broadcast 없이 바로 chain에 추가한다. 여러 node는 `node` command 참고)
+            self.blockchain.update_with_block(block.clone(), &mut self.utxo_set)?;
+            self.mempool.remove_for_block(&block);
+            self.store.sync(&self.blockchain, &self.utxo_set)?;
+            mined.push(block);
+        }
+
+        self.store.save_mempool(&self.mempool)?;
+        self.wallets.save(wallet_name, &wallet)?;
+        Ok(mined)
+    }
 
-    for output in &blockchain.chain[1].transactions[1].outputs {
-        println!("{}, {}", output.to_addr, output.value)
+    // from wallet에서 to(wallet 이름 또는 address)에게 amount를 보내는 tx를 만들어 mempool에 넣는다.
+    // tx는 다음 mine에서 block에 포함된다. fee는 tx 하나에 fee, Input 하나마다 fee를 더 낸다.
+    pub fn send(&mut self, from: &str, to: &str, amount: u64, fee: u64) -> Result<Transaction, AppErr> {
+        self.require_chain()?;
+        let mut sender = self.wallets.load(from)?;
+        // 아직 block에 포함되지 않은 tx가 사용한 UTXO는 다시 고르지 않는다.
+        sender.sync(&self.utxo_set);
+        sender.sync_mempool(&self.mempool);
+
+        let recipient = if self.wallets.exists(to) {
+            let mut recipient = self.wallets.load(to)?;
+            let address = recipient.new_address();
+            self.wallets.save(to, &recipient)?;
+            address
+        } else if key::is_valid_address(to) {
+            to.to_owned()
+        } else {
+            return Err(AppErr::UnknownWallet(to.to_owned()))
+        };
+
+        let selector = CoinSelector::new(Strategy::BranchAndBound, fee, fee);
+        let transaction = sender.create_transaction(&self.blockchain, &recipient, amount, &selector)?;
+        self.mempool.add_transaction(transaction.clone(), &self.blockchain, &self.utxo_set)?;
+        self.store.save_mempool(&self.mempool)?;
+        // change address를 내어주었으므로 wallet도 다시 저장한다.
+        self.wallets.save(from, &sender)?;
+        Ok(transaction)
+    }
+
+    // wallet(또는 address)의 confirmed balance와, mempool의 tx가 사용 중이라 아직 보낼 수 없는 금액
+    pub fn balance(&self, name: &str) -> Result<(u64, u64), AppErr> {
+        self.require_chain()?;
+        if self.wallets.exists(name) {
+            let mut wallet = self.wallets.load(name)?;
+            wallet.sync(&self.utxo_set);
+            let confirmed = wallet.balance();
+            wallet.sync_mempool(&self.mempool);
+            Ok((confirmed, confirmed - wallet.balance()))
+        } else if key::is_valid_address(name) {
+            let pending = self.utxo_set
+                .list_unspent(name)
+                .iter()
+                .filter(|(outpoint, _)| self.mempool.spends(outpoint))
+                .map(|(_, utxo)| utxo.value)
+                .sum();
+            let confirmed = self.utxo_set
+                .get_balance(name)
+                .ok_or_else(|| AppErr::InvalidChain(format!("balance of {} overflows", name)))?;
+            Ok((confirmed, pending))
+        } else {
+            Err(AppErr::UnknownWallet(name.to_owned()))
+        }
+    }
+
+    // active chain에서 height 또는 hex hash로 block을 찾는다.
+    pub fn show_block(&self, block: &str) -> Result<Block, AppErr> {
+        self.require_chain()?;
+        let found = match block.parse::<u32>() {
+            Ok(height) => self.store.get_block_by_height(height)?,
+            Err(_) => match hex::decode(block) {
+                Ok(hash) => self.store.get_block(&hash)?,
+                Err(_) => None,
+            },
+        };
+        found.ok_or_else(|| AppErr::BlockNotFound(block.to_owned()))
+    }
+
+    // 저장된 block들을 genesis부터 빈 chain에 다시 연결하며 tx, script까지 모두 검증하고,
+    // 그 결과로 만들어진 UTXO set이 저장된 UTXO set과 같은지 확인한다. open()의 load는 header만 검증한다.
+    pub fn verify_chain(&self) -> Result<u32, AppErr> {
+        self.require_chain()?;
+        let mut blockchain = Blockchain::with_coinbase_maturity(self.blockchain.coinbase_maturity);
+        let mut utxo_set = UtxoSet::new();
+        for block in &self.blockchain.chain {
+            blockchain
+                .update_with_block(block.clone(), &mut utxo_set)
+                .map_err(|e| AppErr::InvalidChain(format!("block {}: {:?}", block.index, e)))?;
+        }
+
+        if utxo_set.len() != self.utxo_set.len() {
+            return Err(AppErr::InvalidChain(format!(
+                "stored UTXO set has {} UTXOs, replaying the chain gives {}", self.utxo_set.len(), utxo_set.len()
+            )))
+        }
+        for (outpoint, utxo) in utxo_set.iter() {
+            match self.utxo_set.get(outpoint) {
+                Some(stored) if codec::encode(stored) == codec::encode(utxo) => {},
+                _ => return Err(AppErr::InvalidChain(format!("stored UTXO {} does not match the chain", outpoint))),
+            }
+        }
+        Ok(blockchain.chain.len() as u32)
+    }
+
+    fn require_chain(&self) -> Result<(), AppErr> {
+        if self.blockchain.chain.is_empty() {
+            return Err(AppErr::NotInitialized)
+        }
+        Ok(())
+    }
+
+    // wallet의 mnemonic. mnemonic만으로 wallet의 모든 coin을 사용할 수 있으므로 show-mnemonic으로 요청할 때만 보여준다.
+    pub fn mnemonic(&self, name: &str) -> Result<String, AppErr> {
+        Ok(self.wallets.load(name)?.mnemonic())
+    }
+}
+
+// P2P node로 실행한다. listen_addr에서 peer를 받고 peers에 연결한 뒤, 다른 node들과 경쟁하며 끝없이 채굴한다.
+// node마다 data_dir 아래의 다른 directory에 chain을 저장하고, 채굴 보상은 data_dir의 wallet_name wallet이 받는다.
+// e.g. cargo run -- node --listen 127.0.0.1:8333
+//      cargo run -- node --listen 127.0.0.1:8334 --peer 127.0.0.1:8333 --wallet bob
+pub fn run_node<P: AsRef<Path>>(
+    data_dir: P,
+    coinbase_maturity: u32,
+    passphrase: &str,
+    listen_addr: &str,
+    peers: &[String],
+    wallet_name: &str,
+) -> Result<(), AppErr> {
+    let wallets = WalletDir::new(data_dir.as_ref(), passphrase);
+    let mut wallet = wallets.load_or_create(wallet_name)?;
+
+    let store = BlockStore::open(data_dir.as_ref().join(listen_addr.replace(':', "_")))?;
+    let mut blockchain = Blockchain::with_coinbase_maturity(coinbase_maturity);
+    let mut utxo_set = UtxoSet::new();
+    store.load(&mut blockchain, &mut utxo_set)?;
+
+    let node = network::Node::new(blockchain, utxo_set, Some(store), Miner::default());
+    let addr = node.listen(listen_addr).map_err(AppErr::Network)?;
+    println!("Listening on {}", addr);
+
+    for peer in peers {
+        if let Err(e) = node.connect(peer.as_str()) {
+            println!("Failed to connect to {}: {}", peer, e);
+        }
+    }
+
+    // 연결할 peer가 없는 첫 node만 genesis를 만든다. 나머지 node는 peer에게서 genesis부터 받아 온다.
+    if peers.is_empty() && node.tip().is_empty() {
+        node.submit_block(genesis_block(&wallet.receive_address())?)?;
+    }
+
+    // 보상을 받은 address는 sync하면 사용한 것으로 표시되어, 다음 block은 새 address로 받는다.
+    wallet.sync(&node.state().utxo_set);
+    loop {
+        match node.mine_block(wallet.receive_address()) {
+            Some(block) => {
+                println!("Mined block {} {}", block.index, hex::encode(&block.hash));
+                wallet.sync(&node.state().utxo_set);
+                wallets.save(wallet_name, &wallet)?;
+            },
+            // 아직 genesis를 받지 못했거나, 다른 node의 block으로 tip이 바뀌어 채굴을 멈춤
+            None => std::thread::sleep(std::time::Duration::from_millis(100)),
+        }
     }
 }
\ No newline at end of file
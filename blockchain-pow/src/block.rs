@@ -1,17 +1,61 @@
-use std::fmt::{ self, Debug, Formatter };
+use std::{
+    collections::HashSet,
+    fmt::{ self, Debug, Formatter },
+};
 use super::*;
 
-#[derive(Clone)]
-pub struct Block {
-    pub index: u32,
-    // pub version: [u8; 32], // Bitcoin doesn't have an index field, so instead it contains a field representing the version of the block: 'version: [u8; 32],'
-    pub timestamp: u128,
-    pub hash: Hash,
+pub const BLOCK_VERSION: u32 = 1;
+pub const HEADER_SIZE: usize = 4 + 32 + 32 + 16 + 4 + 4;
+pub const NONCE_OFFSET: usize = HEADER_SIZE - 4;
+
+// Block header. block hash는 header만으로 결정되고, tx는 merkle_root를 통해 hash에 반영된다.
+// header는 tx 수와 상관없이 HEADER_SIZE byte로 고정되므로, light client는 header만 받아 PoW와 chain 연결을 검증할 수 있고
+// miner는 header만 hashing하면 된다.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: u32, // Bitcoin doesn't have an index field, so instead it contains a field representing the version of the block
     pub prev_block_hash: Hash, // 이전 layer의 최종 block hash가 아닌, 이전 블록 중 가장 최근에 업데이트된 블록 해시.
     pub merkle_root: Hash,
-    pub nonce: u64,
+    pub timestamp: u128,
+    pub bits: u32, // 256bit target을 압축한 compact bits(nBits)
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    // bits를 풀어낸 256bit target. 유효하지 않은 bits라면 None.
+    pub fn target(&self) -> Option<U256> {
+        target::compact_to_target(self.bits)
+    }
+
+    // header의 hash가 header에 적힌 bits를 만족하는지. tx 없이 header만으로 확인할 수 있다.
+    pub fn check_proof_of_work(&self) -> bool {
+        check_difficulty(&self.hash(), self.bits)
+    }
+}
+
+// nonce는 마지막 4byte(NONCE_OFFSET..)에 위치해 miner가 header를 다시 직렬화하지 않고 nonce만 바꿔가며 hashing할 수 있다.
+impl Hashable for BlockHeader {
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+
+        bytes.extend(&u32_to_bytes(&self.version));
+        bytes.extend(&self.prev_block_hash);
+        bytes.extend(&self.merkle_root);
+        bytes.extend(&u128_to_bytes(&self.timestamp));
+        bytes.extend(&u32_to_bytes(&self.bits));
+        bytes.extend(&u32_to_bytes(&self.nonce));
+
+        bytes
+    }
+}
+
+// Block = header + transactions
+#[derive(Clone)]
+pub struct Block {
+    pub index: u32, // header에 포함되지 않는다. 부모 block의 index + 1과 같은지 검증한다.
+    pub header: BlockHeader,
+    pub hash: Hash, // header.hash()를 저장해 둔 값
     pub transactions: Vec<Transaction>,
-    pub difficulty: u128,
 }
 
 impl Debug for Block {
@@ -19,9 +63,9 @@ impl Debug for Block {
         write!(f, "Block[{}]: {} at: {} with: {} nonce: {}",
                &self.index,
                &hex::encode(&self.hash),
-               &self.timestamp,
+               &self.header.timestamp,
                &self.transactions.len(),
-               &self.nonce,
+               &self.header.nonce,
         )
     }
 }
@@ -32,41 +76,66 @@ impl Block {
         timestamp: u128,
         prev_block_hash: Hash,
         transactions: Vec<Transaction>,
-        difficulty: u128,
+        bits: u32,
     ) -> Self {
+        let merkle_root = merkle_root(&transactions.iter().map(|tx| tx.hash()).collect::<Vec<_>>());
         Block {
             index,
-            timestamp,
+            header: BlockHeader {
+                version: BLOCK_VERSION,
+                prev_block_hash,
+                merkle_root,
+                timestamp,
+                bits,
+                nonce: 0,
+            },
             hash: vec![0; 32],
-            prev_block_hash,
-            merkle_root: vec![0; 32],
-            nonce: 0,
             transactions,
-            difficulty,
         }
     }
 
     pub fn add_transaction(&mut self, transaction: Transaction) {
         self.transactions.push(transaction);
-        let new_tx_hashes = self.transactions.iter().map(|tx| tx.hash()).collect::<Vec<_>>();
-        self.merkle_root = merkle_root(&new_tx_hashes);
+        self.update_merkle_root();
     }
 
-    // O(N) N = 2.pow(64)
-    pub fn mine(&mut self) {
-        for nonce_attempt in 0..(u64::MAX) {
-            self.nonce = nonce_attempt;
-            let hash = self.hash();
-            if check_difficulty(&hash, self.difficulty) {
-                self.hash = hash;
-                return;
-            }
+    fn update_merkle_root(&mut self) {
+        self.header.merkle_root = merkle_root(&self.tx_hashes());
+    }
+
+    pub fn tx_hashes(&self) -> Vec<Hash> {
+        self.transactions.iter().map(|tx| tx.hash()).collect::<Vec<_>>()
+    }
+
+    // coinbase tx의 extra nonce를 바꾸고 merkle_root를 다시 계산한다.
+    // header의 nonce(u32)를 모두 시도해도 target을 만족하지 못했을 때 새로운 header를 만들기 위해 사용한다.
+    pub fn set_extra_nonce(&mut self, extra_nonce: u64) {
+        if let Some(coinbase) = self.transactions.first_mut() {
+            coinbase.set_extra_nonce(extra_nonce);
+            self.update_merkle_root();
         }
     }
 
+    pub fn extra_nonce(&self) -> u64 {
+        self.transactions.first().map_or(0, |coinbase| coinbase.extra_nonce())
+    }
+
+    // 모든 core를 사용해 nonce를 찾는다. O(N) N = 2.pow(32) * extra nonce
+    pub fn mine(&mut self) {
+        Miner::default().mine(self);
+    }
+
+    pub fn target(&self) -> Option<U256> {
+        self.header.target()
+    }
+
+    // tx_index번째 tx가 이 block에 포함되었음을 header의 merkle_root로 증명하는 proof
+    pub fn merkle_proof(&self, tx_index: usize) -> Option<MerkleProof> {
+        merkle_proof(&self.tx_hashes(), tx_index)
+    }
+
     pub fn check_merkle_and_mining(&mut self) -> Result<(), blockchain::BlockValidationErr> {
-        let tx_hashes = self.transactions.iter().map(|tx| tx.hash()).collect::<Vec<_>>();
-        if self.merkle_root == merkle_root(&tx_hashes) {
+        if self.header.merkle_root == merkle_root(&self.tx_hashes()) {
             self.mine();
         } else {
             return Err(blockchain::BlockValidationErr::InvalidMerkleRoot)
@@ -77,49 +146,131 @@ impl Block {
 
 impl Hashable for Block {
     fn bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-
-        bytes.extend(&u32_to_bytes(&self.index));
-        bytes.extend(&u128_to_bytes(&self.timestamp));
-        bytes.extend(&self.prev_block_hash);
-        bytes.extend(&self.merkle_root);
-        bytes.extend(&u64_to_bytes(&self.nonce));
-        bytes.extend(
-            self.transactions
-                .iter()
-                .flat_map(|transaction| transaction.bytes())
-                .collect::<Vec<u8>>());
-        bytes.extend(&u128_to_bytes(&self.difficulty));
+        self.header.bytes()
+    }
+}
 
-        bytes
+// hash 32byte 전체를 little-endian 256bit 숫자로 보고 bits가 나타내는 target 이하인지 확인한다.
+pub fn check_difficulty(hash: &Hash, bits: u32) -> bool {
+    match target::compact_to_target(bits) {
+        Some(target) if !target.is_zero() && hash.len() == 32 => U256::from_le_bytes(hash) <= target,
+        _ => false,
     }
 }
 
-pub fn check_difficulty(hash: &Hash, difficulty: u128) -> bool {
-    difficulty > difficulty_bytes_as_u128(&hash)
+// bits를 만족하는 block 하나의 work. 누적 work로 heaviest chain을 고르는 데 사용된다.
+pub fn block_work(bits: u32) -> U256 {
+    match target::compact_to_target(bits) {
+        Some(target) if !target.is_zero() => target::target_to_work(target),
+        _ => U256::ZERO,
+    }
 }
 
+// leaf와 inner node는 서로 다른 prefix를 붙여 hashing한다(domain separation).
+// prefix가 없으면 inner node(두 hash의 연결, 64byte)를 그대로 하나의 leaf로 해석할 수 있어,
+// 더 짧은 tree로 같은 root를 만드는 second preimage가 가능해진다.
+const MERKLE_LEAF_PREFIX: u8 = 0x00;
+const MERKLE_NODE_PREFIX: u8 = 0x01;
+
 pub fn merkle_root(hashes: &[Hash]) -> Hash {
-    let mut hashes = hashes.to_owned();
+    if hashes.is_empty() {
+        return vec![0; 32];
+    }
+    let mut hashes = merkle_leaves(hashes);
     while hashes.len() > 1 {
-        // 홀수일 경우 마지막 해시를 벡터에 추가
-        if hashes.len() % 2 == 1 {
-            hashes.push(hashes.last().unwrap().to_owned());
+        hashes = merkle_parent_level(hashes);
+    }
+    hashes[0].clone()
+}
+
+// 홀수 level의 마지막 hash를 복제하기 때문에 [a, b, c]와 [a, b, c, c]는 같은 root를 가진다(CVE-2012-2459).
+// 이렇게 같은 root를 만드는 tx 목록은 반드시 같은 txid를 두 번 이상 포함하므로, 중복된 txid가 있는 block은 거부한다.
+// merkle_root가 일치하는 유효한 block의 tx를 복제해 만든 invalid block이 원래 block의 hash를 무효화(invalidate)하지 못하게 한다.
+pub fn merkle_mutated(hashes: &[Hash]) -> bool {
+    let mut seen = HashSet::new();
+    hashes.iter().any(|hash| !seen.insert(hash))
+}
+
+fn merkle_leaves(hashes: &[Hash]) -> Vec<Hash> {
+    hashes
+        .iter()
+        .map(|hash| merkle_leaf(hash))
+        .collect()
+}
+
+fn merkle_leaf(txid: &[u8]) -> Hash {
+    let mut bytes = Vec::with_capacity(1 + txid.len());
+    bytes.push(MERKLE_LEAF_PREFIX);
+    bytes.extend(txid);
+    crypto_hash::digest(crypto_hash::Algorithm::SHA256, &bytes)
+}
+
+fn merkle_parent_level(mut hashes: Vec<Hash>) -> Vec<Hash> {
+    // 홀수일 경우 마지막 해시를 벡터에 추가
+    if hashes.len() % 2 == 1 {
+        hashes.push(hashes.last().unwrap().to_owned());
+    }
+    hashes
+        .chunks(2)
+        .map(|pair| merkle_hash_pair(&pair[0], &pair[1]))
+        .collect()
+}
+
+// 쌍을 이뤄주고, extending해서 하나 부모 노드로 만듬
+// Merkle 트리의 각 부모 노드는 두 자식 노드의 연결된 hash를 hashing하여 구성된다.
+fn merkle_hash_pair(left: &[u8], right: &[u8]) -> Hash {
+    let mut bytes = Vec::with_capacity(1 + left.len() + right.len());
+    bytes.push(MERKLE_NODE_PREFIX);
+    bytes.extend(left);
+    bytes.extend(right);
+    crypto_hash::digest(crypto_hash::Algorithm::SHA256, &bytes)
+}
+
+// Merkle inclusion proof.
+// leaf(txid)에서 root까지 올라가는 경로의 형제(sibling) 노드 hash들. 각 level에서 leaf가 왼쪽인지 오른쪽인지는
+// index의 bit로 알 수 있으므로 따로 저장하지 않는다. tx 수가 N일 때 proof의 크기는 O(log N)이고,
+// header(merkle_root)만 가진 light client(wallet)도 block 전체를 받지 않고 tx가 block에 포함되었는지 확인할 수 있다.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub branch: Vec<Hash>,
+}
+
+impl MerkleProof {
+    // txid와 branch로 root를 다시 계산해 header의 merkle_root와 비교한다.
+    pub fn verify(&self, txid: &[u8], merkle_root: &[u8]) -> bool {
+        // index >= 2^branch.len()이면 tree 밖을 가리킨다. 상위 bit가 무시되어 같은 proof가 여러 index로 통과하지 않도록 거부한다.
+        if self.index.checked_shr(self.branch.len() as u32).unwrap_or(0) != 0 {
+            return false
         }
-        let mut new_hashes = vec![];
-        for i in (0..hashes.len()).step_by(2) {
-            // 쌍을 이뤄주고, extending해서 하나 부모 노드로 만듬
-            let mut new_hash = Vec::new();
-            new_hash.extend(hashes[i].clone());
-            new_hash.extend(hashes[i+1].clone());
-
-            // Merkle 트리의 각 부모 노드는 두 자식 노드의 연결된 hash를 hashing하여 구성된다.
-            // extending된 쌍의 hash를 한번 더 hashing하여 부모 노드로 만들어준다.
-            new_hash = crypto_hash::digest(crypto_hash::Algorithm::SHA256, &new_hash);
-
-            new_hashes.push(new_hash);
+        let mut hash = merkle_leaf(txid);
+        let mut index = self.index;
+        for sibling in self.branch.iter() {
+            hash = if index.is_multiple_of(2) {
+                merkle_hash_pair(&hash, sibling)
+            } else {
+                merkle_hash_pair(sibling, &hash)
+            };
+            index /= 2;
         }
-        hashes = new_hashes;
+        hash == merkle_root
     }
-    hashes[0].clone()
+}
+
+// hashes[index]의 inclusion proof. index가 범위를 벗어나면 None.
+pub fn merkle_proof(hashes: &[Hash], index: usize) -> Option<MerkleProof> {
+    if index >= hashes.len() {
+        return None;
+    }
+    let mut branch = vec![];
+    let mut hashes = merkle_leaves(hashes);
+    let mut i = index;
+    while hashes.len() > 1 {
+        // 홀수 level의 마지막 노드는 자기 자신과 쌍을 이룬다.
+        let sibling = if i.is_multiple_of(2) { (i + 1).min(hashes.len() - 1) } else { i - 1 };
+        branch.push(hashes[sibling].clone());
+        hashes = merkle_parent_level(hashes);
+        i /= 2;
+    }
+    Some(MerkleProof { index, branch })
 }
\ No newline at end of file
@@ -1,145 +1,439 @@
 use super::*;
 use std::collections::{HashMap, HashSet};
+use crate::utxo::{Utxo, BlockUndo};
+use crate::transaction::OutPoint;
+use crate::coin_selection::CoinSelector;
+use crate::script::ScriptErr;
 
 // custom Error type
 #[derive(Debug)]
 pub enum BlockValidationErr {
     MismatchedIndex,
     InvalidHash,
-    AchronologicalTimestamp,
+    TimestampTooOld, // timestamp가 이전 block들의 median-time-past보다 크지 않음
+    TimestampTooFarInFuture, // timestamp가 node의 시계보다 max_future_drift 이상 앞섬
     MismatchedPreviousHash,
     InvalidGenesisBlockFormat,
     InvalidInput,
     InsufficientInputValue,
     InvalidCoinbaseTransaction,
     InvalidMerkleRoot,
+    DuplicateTransaction,
     UtxoSpentFailure,
+    InvalidScript(ScriptErr), // Input의 script_sig로 UTXO의 script_pubkey를 unlock하지 못함
+    InvalidDifficulty,
+    ImmatureCoinbaseSpend,
+    NonFinalTransaction, // tx의 lock_time이 아직 지나지 않음
+    SequenceLockNotSatisfied, // Input의 relative lock(sequence)이 아직 지나지 않음
+}
+
+// Difficulty retargeting.
+// btc는 2016 block마다 다음 2016 block이 2주(block당 10분)에 채굴되도록 difficulty를 조정한다.
+// 여기서는 RETARGET_INTERVAL block마다, 직전 구간에서 실제로 걸린 시간과 목표 시간을 비교해 difficulty(목표값)를 조정한다.
+pub const GENESIS_BITS: u32 = 0x1f0fffff; // 가장 쉬운 difficulty(pow limit). target = 0x000fffff00..00
+pub const RETARGET_INTERVAL: u32 = 10; // difficulty를 조정하는 block 간격
+pub const TARGET_BLOCK_TIME: u128 = 1_000; // block 하나를 채굴하는데 걸리길 기대하는 시간(ms)
+pub const MAX_ADJUSTMENT_FACTOR: u128 = 4; // 한 번의 조정으로 difficulty가 변할 수 있는 최대 배율
+
+// Block subsidy.
+// btc는 210,000 block마다 coinbase로 새로 발행되는 양(subsidy)이 절반으로 줄어든다(halving). 50 -> 25 -> 12.5 -> 6.25 ...
+// 채굴자는 subsidy에 더해 block에 담긴 tx의 fee(Input 총 가치 - Output 총 가치)를 coinbase로 가져갈 수 있다.
+pub const INITIAL_SUBSIDY: u64 = 50;
+pub const HALVING_INTERVAL: u32 = 210; // subsidy가 절반으로 줄어드는 block 간격
+
+// Coinbase maturity.
+// coinbase Output은 만들어진 block이 reorg로 chain에서 떨어져 나가면 함께 사라진다. 그 Output을 사용한 tx들까지 무효가 되지 않도록,
+// btc는 coinbase Output 위로 100개의 block이 쌓인 뒤에야 사용할 수 있게 한다. app.rs에서 설명한 confirmation과 같은 이유이다.
+pub const COINBASE_MATURITY: u32 = 100;
+
+// Median-time-past.
+// block의 timestamp는 채굴자가 정하므로 하나만 보면 조작될 수 있다. 최근 MEDIAN_TIME_SPAN개 block timestamp의 중앙값은
+// 채굴자 한 명이 크게 움직일 수 없으므로 time 기반 timelock은 이 값과 비교한다(btc BIP113).
+// block의 timestamp도 부모까지의 median-time-past보다 커야 한다. 채굴자들의 시계가 조금씩 달라 timestamp가
+// 부모보다 작아지는 것은 허용하면서, chain의 시간이 뒤로 가지는 않게 한다.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+// node의 시계보다 이만큼 이상 앞선 timestamp의 block은 받지 않는다(ms). btc와 같이 2시간.
+// 미래의 timestamp로 difficulty를 낮추거나 time lock을 앞당기는 것을 막는다.
+pub const MAX_FUTURE_BLOCK_TIME: u128 = 2 * 60 * 60 * 1_000;
+
+// block tree의 node. 같은 부모를 가진 경쟁 block(fork)도 모두 tree에 저장된다.
+pub struct BlockNode {
+    pub block: Block,
+    pub chain_work: U256, // genesis부터 이 block까지의 누적 work. 가장 큰 값을 가진 chain이 heaviest chain이다.
+}
+
+// header tree의 node. headers-first sync에서는 block을 받기 전에 header만 먼저 검증해 tree에 추가한다.
+pub struct HeaderNode {
+    pub header: BlockHeader,
+    pub height: u32,
 }
 
 pub struct Blockchain {
-    pub chain: Vec<Block>,
+    pub blocks: HashMap<Hash, BlockNode>, // hash로 찾는 block tree. active chain이 아닌 branch의 block도 포함한다.
+    pub headers: HashMap<Hash, HeaderNode>, // 검증된 header tree. blocks의 모든 block과, 아직 받지 못한 block의 header를 담는다.
+    pub chain: Vec<Block>, // 누적 work가 가장 큰 active chain
+    pub undo: Vec<BlockUndo>, // chain[i]를 utxo_set에 적용하며 생긴 undo 기록. chain과 같은 길이를 유지한다.
     pub tip: Hash, // self.chain.last().unwrap()과 같음. 그럼에도 넣은 이유는? 최신 유효 블록에 빠르게 엑세스하기 위함.
-}                  // chain.last()를 불러오기 위해 전체 chain을 메모리에 올리는 과정 생략.
+                   // chain.last()를 불러오기 위해 전체 chain을 메모리에 올리는 과정 생략.
+    pub coinbase_maturity: u32, // coinbase Output을 사용하기 위해 필요한 confirmation 수
+    pub max_future_drift: u128, // 받을 수 있는 block timestamp의 node 시계 기준 최대 허용 오차(ms)
+}
 
 impl Blockchain {
     pub fn new() -> Self {
+        Blockchain::with_coinbase_maturity(COINBASE_MATURITY)
+    }
+
+    pub fn with_coinbase_maturity(coinbase_maturity: u32) -> Self {
         Blockchain {
+            blocks: HashMap::new(),
+            headers: HashMap::new(),
             chain: vec![],
+            undo: vec![],
             tip: vec![],
+            coinbase_maturity,
+            max_future_drift: MAX_FUTURE_BLOCK_TIME,
         }
     }
+}
 
-    pub fn spawn_block(&self, difficulty: u128, sender: String, recipient: String, mut amount: u64, utxo_set: &UtxoSet) -> Block {
-        let fee = 0;
+impl Default for Blockchain {
+    fn default() -> Self {
+        Blockchain::new()
+    }
+}
 
-        let block_reward = 7; // 블록보상 6.25 + 추가적인 transaction fee
-        // println!("{:?}", val);
+impl Blockchain {
+
+    // sender의 UTXO로 recipient에게 amount를 보내는 tx를 만든다.
+    // 사용할 UTXO와 fee는 selector가 고르고, Input의 총 가치에서 Output의 총 가치를 뺀 나머지가 fee가 된다.
+    // 채굴자는 coinbase로 block subsidy와 fee를 가져간다.
+    pub fn create_transaction(&self, sender: &Privatekey, recipient: String, amount: u64, selector: &CoinSelector, utxo_set: &UtxoSet) -> Result<Transaction, BlockValidationErr> {
+        let spend_height = self.chain.last().map_or(0, |tip| tip.index + 1);
+        let selection = utxo_set.get_optimal_inputs(&sender.address(), amount, spend_height, self.coinbase_maturity, selector)?;
+
+        let inputs = selection.inputs
+            .iter()
+            .map(|(outpoint, utxo)| transaction::Input::new(utxo.to_output(), *outpoint))
+            .collect::<Vec<_>>();
+
+        let mut outputs = vec![
+            transaction::Output::new(&recipient, amount),
+        ];
+
+        if selection.change > 0 {
+            // change.
+            // fee를 제외한 나머지가 모두 채굴자에게 가지 않도록 본인에게 반환되는 Output 추가.
+            outputs.push(
+                transaction::Output::new(&sender.address(), selection.change),
+            )
+        };
 
-        let mut block = Block::new(
-            self.chain.last().unwrap().index + 1,
-            now(),
-            self.chain.last().unwrap().hash.clone(),
-            vec![],
-            difficulty
-        );
+        let mut transaction = Transaction::new(inputs, outputs);
+        // Input으로 사용하는 UTXO의 소유자(sender)가 tx에 서명한다.
+        transaction.sign(sender);
+
+        Ok(transaction)
+    }
+
+    // Block template.
+    // tip 위에 올릴 block을 만든다. mempool에서 fee per byte가 높은 tx부터 골라 담고,
+    // coinbase로 block subsidy와 고른 tx들의 fee를 miner_addr에게 지급한다. 채굴자는 이 block의 nonce만 찾으면 된다.
+    pub fn block_template(&self, mempool: &Mempool, miner_addr: Address) -> Block {
+        let prev_block = self.chain.last().unwrap();
+        let index = prev_block.index + 1;
+        let (transactions, total_fee) = mempool.select_transactions(mempool::DEFAULT_MAX_BLOCK_SIZE);
 
         // coinbase transaction
         // 블록을 생성한 광부. 마이닝 해서 블록체인에 붙이려고 시도한다.
         // 이 coinbase tx의 sender도 광부, recipient도 광부. coinbase address라고 불린다.
-        let coinbase_tx = Transaction {
-            inputs: vec![],
-            outputs: vec![
-                transaction::Output {
-                    to_addr: "coinbase_miner".to_owned(),
-                    value: block_reward,
-                },
-            ],
-        };
+        let coinbase_tx = Transaction::coinbase(index, vec![
+            transaction::Output::new(
+                &miner_addr,
+                block_subsidy(index) + total_fee, // 블록보상 + 추가적인 transaction fee
+            ),
+        ]);
+
+        let mut block_transactions = vec![coinbase_tx];
+        block_transactions.extend(transactions);
+
+        // timestamp는 부모까지의 median-time-past보다 커야 한다.
+        let timestamp = now().max(self.median_time_past(prev_block) + 1);
+
+        Block::new(
+            index,
+            timestamp,
+            prev_block.hash.clone(),
+            block_transactions,
+            self.next_bits(prev_block),
+        )
+    }
 
-        block.add_transaction(coinbase_tx);
+    // integrity test
+    // 새 block을 block tree에 추가한다. 부모 block이 현재 tip이면 바로 chain에 연결하고,
+    // 다른 branch에 붙은 block이라면 누적 work를 비교해 더 무거운(heaviest) chain으로 reorg한다.
+    pub fn update_with_block(&mut self, block: Block, utxo_set: &mut UtxoSet) -> Result<(), BlockValidationErr> {
+        // 이미 받은 block
+        if self.blocks.contains_key(&block.hash) {
+            return Ok(())
+        }
 
-        let inputs = utxo_set.get_optimal_inputs(amount).expect("Insufficient UTXO");
+        // node의 시계를 기준으로 하므로 block 자체의 유효성과는 별개이다. 시간이 지나면 받을 수 있으므로 invalidate하지 않으며,
+        // 이미 chain에 연결된 block을 저장소에서 다시 읽을 때(restore_block)는 확인하지 않는다.
+        if block.header.timestamp > now().saturating_add(self.max_future_drift) {
+            return Err(BlockValidationErr::TimestampTooFarInFuture)
+        }
 
-        let mut sub_amount = amount;
-        for (txid, idx, input_amount, script_pubkey) in inputs {
-            let txid_idx = format!("{}:{}", txid, idx);
-            let input_to_addr = script_pubkey.split(":").nth(1).unwrap();
-            if input_amount < amount {
-                sub_amount = input_amount + fee;
-                amount -= sub_amount;
-            }
+        let parent_work = self.check_block_header(&block)?;
 
-            let mut outputs = vec![
-                transaction::Output {
-                    to_addr: recipient.clone(),
-                    value: sub_amount,
-                }
-            ];
+        let hash = block.hash.clone();
+        let chain_work = parent_work.saturating_add(block::block_work(block.header.bits));
+        let extends_tip = if self.chain.is_empty() {
+            block.index == 0
+        } else {
+            block.header.prev_block_hash == self.tip
+        };
 
-            if input_amount > sub_amount {
-                // change.
-                // btc network에서 요구하는 대로 Input의 총 가치가 출력의 총 가치와 동일하도록 하기 위해
-                // 본인에게 반환되는 Output 추가.
-                outputs.push(
-                    transaction::Output {
-                        to_addr: sender.clone(),
-                        value: input_amount - sub_amount,
-                    },
-                )
-            };
+        self.insert_header(&block);
+        self.blocks.insert(hash.clone(), BlockNode { block: block.clone(), chain_work });
 
-            let mut inputs = Vec::new();
-            inputs.push((
-                transaction::Output {
-                    to_addr: input_to_addr.to_owned(),
-                    value: input_amount,
-                }, txid_idx
-            ));
+        if extends_tip {
+            if let Err(e) = self.connect_block(block, utxo_set) {
+                self.invalidate(&hash);
+                return Err(e)
+            }
+        } else if chain_work > self.tip_work() {
+            // 경쟁 branch가 현재 chain보다 누적 PoW가 많아졌으므로 그 branch로 reorg한다.
+            self.reorganize(&hash, utxo_set)?;
+        }
+        // 누적 work가 같거나 적은 branch는 tree에만 남겨두고(stale) 나중에 더 무거워지면 reorg 대상이 된다.
 
-            let transaction = Transaction {
-                inputs,
-                outputs,
-            };
+        Ok(())
+    }
 
-            block.add_transaction(transaction);
+    // 저장소에서 읽어 온 block을 tx 검증 없이 chain의 tip으로 연결한다.
+    // block의 tx는 이미 utxo_set(저장소에서 함께 읽어 온)에 반영되어 있으므로 header와 chain 연결만 다시 확인하고,
+    // 이후 reorg에서 되돌릴 수 있도록 저장해 둔 undo 기록을 함께 받는다.
+    pub fn restore_block(&mut self, block: Block, undo: BlockUndo) -> Result<(), BlockValidationErr> {
+        let parent_work = self.check_block_header(&block)?;
+        let extends_tip = if self.chain.is_empty() {
+            block.index == 0
+        } else {
+            block.header.prev_block_hash == self.tip
+        };
+        if !extends_tip {
+            return Err(BlockValidationErr::MismatchedPreviousHash)
         }
 
-        block.clone()
+        let chain_work = parent_work.saturating_add(block::block_work(block.header.bits));
+        self.insert_header(&block);
+        self.blocks.insert(block.hash.clone(), BlockNode { block: block.clone(), chain_work });
+        self.tip = block.hash.clone();
+        self.chain.push(block);
+        self.undo.push(undo);
+
+        Ok(())
     }
 
-    // integrity test
-    pub fn update_with_block(&mut self, block: Block, utxo_set: &mut UtxoSet, sender: &String) -> Result<(), BlockValidationErr> {
-        let i = self.chain.len();
-
-        // 1. index check
-        if block.index as usize != i {
-            println!("Index mismatch {} != {}", &block.index, &i);
-            return Err(BlockValidationErr::MismatchedIndex) // 2. Whether Block's hash fits stored difficulty value(+payload check)
-        } else if !block::check_difficulty(&block.hash(), block.difficulty) {
+    // block을 받기 전에 header만으로 확인할 수 있는 규칙(PoW, 부모 header와의 연결, timestamp, difficulty)을 검증하고
+    // header tree에 추가한 뒤 header의 hash를 반환한다. 이미 있는 header는 다시 검증하지 않는다.
+    // 검증된 header에 이어지는 block만 요청하므로, tx를 받기 전에 잘못된 chain을 걸러낼 수 있다.
+    pub fn add_header(&mut self, header: BlockHeader) -> Result<Hash, BlockValidationErr> {
+        let hash = header.hash();
+        if self.headers.contains_key(&hash) {
+            return Ok(hash)
+        }
+        if !header.check_proof_of_work() {
             return Err(BlockValidationErr::InvalidHash)
-        } else if i != 0 {
-            // Not genesis block
-            // 3. time elapsed or not
-            let prev_block = &self.chain[i-1];
-            // It is unlikely for a block to be mined within 1 millisecond.
-            // The timestamp is the same as the previous value,
-            // but most coins will pass the integrity check only
-            // if the block timestamp is greater than the previous block timestamp.
-            // 여기서는 빠르게 확인해 보는 것이 목적이기 때문에
-            // 난이도를 낮게 설정하면 실패할 수 있음.
-            if block.timestamp < prev_block.timestamp {
-                return Err(BlockValidationErr::AchronologicalTimestamp) // 4. Check that [block.prev_block_hash] and [previous block.hash] match
-            } else if block.prev_block_hash != prev_block.hash {
-                return Err(BlockValidationErr::MismatchedPreviousHash)
+        }
+        if header.timestamp > now().saturating_add(self.max_future_drift) {
+            return Err(BlockValidationErr::TimestampTooFarInFuture)
+        }
+
+        let height = if header.prev_block_hash == vec![0; 32] {
+            if !self.headers.is_empty() {
+                return Err(BlockValidationErr::InvalidGenesisBlockFormat)
+            } else if header.bits != GENESIS_BITS {
+                return Err(BlockValidationErr::InvalidDifficulty)
             }
-            self.tip = block.prev_block_hash.clone();
+            0
         } else {
-            // Genesis block
-            if block.prev_block_hash != vec![0; 32] {
+            let parent = self.headers.get(&header.prev_block_hash).ok_or(BlockValidationErr::MismatchedPreviousHash)?;
+            self.check_header_rules(&header, &parent.header, parent.height)?;
+            parent.height + 1
+        };
+
+        self.headers.insert(hash.clone(), HeaderNode { header, height });
+        Ok(hash)
+    }
+
+    fn insert_header(&mut self, block: &Block) {
+        self.headers
+            .entry(block.hash.clone())
+            .or_insert_with(|| HeaderNode { header: block.header.clone(), height: block.index });
+    }
+
+    // utxo_set 없이 확인할 수 있는 규칙(hash, PoW, merkle root, 부모 block과의 연결, difficulty)을 검증하고
+    // 부모 block까지의 누적 work를 반환한다.
+    fn check_block_header(&self, block: &Block) -> Result<U256, BlockValidationErr> {
+        // 2. Whether Block's hash fits stored difficulty value(+payload check)
+        if block.hash != block.hash() || !block::check_difficulty(&block.hash, block.header.bits) {
+            return Err(BlockValidationErr::InvalidHash)
+        }
+        // block hash는 header만 hashing하므로, tx가 header의 merkle_root와 일치하는지 따로 확인해야 한다.
+        let tx_hashes = block.tx_hashes();
+        if block.header.merkle_root != block::merkle_root(&tx_hashes) {
+            return Err(BlockValidationErr::InvalidMerkleRoot)
+        }
+        if block::merkle_mutated(&tx_hashes) {
+            return Err(BlockValidationErr::DuplicateTransaction)
+        }
+
+        if block.index == 0 {
+            // Genesis block. genesis는 하나뿐이므로 tree가 비어 있을 때만 받는다.
+            if block.header.prev_block_hash != vec![0; 32] || !self.blocks.is_empty() {
                 return Err(BlockValidationErr::InvalidGenesisBlockFormat)
+            } else if block.header.bits != GENESIS_BITS {
+                return Err(BlockValidationErr::InvalidDifficulty)
+            }
+            Ok(U256::ZERO)
+        } else {
+            // Not genesis block
+            // 4. Check that [block.prev_block_hash] is a known block(tip이 아니어도 tree에 있는 block이면 fork로 받는다)
+            let parent = self.blocks.get(&block.header.prev_block_hash).ok_or(BlockValidationErr::MismatchedPreviousHash)?;
+            // 1. index check
+            if block.index != parent.block.index + 1 {
+                return Err(BlockValidationErr::MismatchedIndex)
             }
+            // 3, 5. timestamp와 difficulty
+            self.check_header_rules(&block.header, &parent.block.header, parent.block.index)?;
+            Ok(parent.chain_work)
+        }
+    }
+
+    // 부모 header에 이어지는 header의 timestamp와 difficulty를 확인한다. block과 header 검증이 같은 규칙을 쓴다.
+    fn check_header_rules(&self, header: &BlockHeader, parent: &BlockHeader, parent_height: u32) -> Result<(), BlockValidationErr> {
+        // 채굴자마다 시계가 조금씩 다르므로 부모 block보다 timestamp가 작은 것은 허용하되,
+        // 부모까지 MEDIAN_TIME_SPAN개 block의 median-time-past보다는 커야 한다(btc와 동일).
+        if header.timestamp <= self.header_median_time_past(parent) {
+            return Err(BlockValidationErr::TimestampTooOld)
+        }
+        // block의 difficulty는 consensus rule(retargeting)로 계산한 값과 같아야 한다.
+        if header.bits != self.header_next_bits(parent, parent_height) {
+            return Err(BlockValidationErr::InvalidDifficulty)
+        }
+        Ok(())
+    }
+
+    // prev_block 다음 block이 가져야 할 difficulty(compact bits).
+    // RETARGET_INTERVAL의 배수 높이에서만 직전 구간의 timestamp로 difficulty를 다시 계산하고, 그 외에는 이전 값을 그대로 쓴다.
+    // fork된 branch에서도 계산할 수 있도록 active chain이 아닌 header tree를 따라 조상을 찾는다.
+    pub fn next_bits(&self, prev_block: &Block) -> u32 {
+        self.header_next_bits(&prev_block.header, prev_block.index)
+    }
+
+    // height 높이의 prev header 다음 header가 가져야 할 difficulty. 아직 block을 받지 못한 header에도 사용한다.
+    fn header_next_bits(&self, prev: &BlockHeader, prev_height: u32) -> u32 {
+        let height = prev_height + 1;
+        if !height.is_multiple_of(RETARGET_INTERVAL) {
+            return prev.bits
+        }
+
+        let mut first = prev;
+        for _ in 1..RETARGET_INTERVAL {
+            first = &self.headers[&first.prev_block_hash].header;
         }
 
+        let target_timespan = TARGET_BLOCK_TIME * RETARGET_INTERVAL as u128;
+        let actual_timespan = prev.timestamp
+            .saturating_sub(first.timestamp)
+            .clamp(target_timespan / MAX_ADJUSTMENT_FACTOR, target_timespan * MAX_ADJUSTMENT_FACTOR);
+
+        let prev_target = prev.target().expect("Connected header must have valid bits");
+        target::target_to_compact(retarget(prev_target, actual_timespan, target_timespan))
+    }
+
+    // block과 그 조상 최대 MEDIAN_TIME_SPAN개 block timestamp의 중앙값.
+    // next_bits와 같이 header tree를 따라 조상을 찾으므로 fork된 branch의 block에도 사용할 수 있다.
+    pub fn median_time_past(&self, block: &Block) -> u128 {
+        self.header_median_time_past(&block.header)
+    }
+
+    fn header_median_time_past(&self, header: &BlockHeader) -> u128 {
+        let mut timestamps = vec![header.timestamp];
+        let mut prev_hash = &header.prev_block_hash;
+        while timestamps.len() < MEDIAN_TIME_SPAN {
+            match self.headers.get(prev_hash) {
+                Some(node) => {
+                    timestamps.push(node.header.timestamp);
+                    prev_hash = &node.header.prev_block_hash;
+                },
+                None => break,
+            }
+        }
+        timestamps.sort();
+        timestamps[timestamps.len() / 2]
+    }
+
+    // active chain의 height 높이 block에서 생성된 UTXO의 relative time lock 기준 시각.
+    // btc와 같이 UTXO를 포함한 block의 부모 block까지의 median-time-past를 사용한다(genesis의 UTXO는 genesis 기준).
+    // 아직 chain에 없는 UTXO(mempool tx의 Output 등)는 tip 기준이다.
+    fn coin_median_time_past(&self, height: u32) -> u128 {
+        let parent = height.saturating_sub(1) as usize;
+        match self.chain.get(parent).or(self.chain.last()) {
+            Some(block) => self.median_time_past(block),
+            None => 0,
+        }
+    }
+
+    // tip의 누적 work
+    pub fn tip_work(&self) -> U256 {
+        self.blocks.get(&self.tip).map_or(U256::ZERO, |node| node.chain_work)
+    }
+
+    // Block locator.
+    // 다른 node가 우리 chain과 어디서 갈라졌는지 찾을 수 있도록 active chain의 hash를 tip부터 골라 담는다.
+    // 최근 10개는 모두 담고 그 뒤로는 간격을 두 배씩 늘려, chain이 길어도 O(log n)개로 fork 지점을 찾을 수 있다.
+    // genesis는 항상 마지막에 들어간다.
+    pub fn block_locator(&self) -> Vec<Hash> {
+        let mut locator = vec![];
+        let mut height = match self.chain.len().checked_sub(1) {
+            Some(height) => height,
+            None => return locator,
+        };
+        let mut step = 1;
+        loop {
+            locator.push(self.chain[height].hash.clone());
+            if height == 0 {
+                break
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step);
+        }
+        locator
+    }
+
+    // tip이 old_tip에서 현재 tip으로 바뀌면서 active chain에서 떨어져 나간 block들(old_tip부터)과
+    // 새로 연결된 block들(fork 지점 다음부터)을 반환한다. reorg가 아니었다면 떨어져 나간 block은 없다.
+    pub fn chain_changes(&self, old_tip: &Hash) -> (Vec<Block>, &[Block]) {
+        let mut disconnected = vec![];
+        let mut hash = old_tip.clone();
+        while let Some(node) = self.blocks.get(&hash) {
+            if self.is_active(&hash) {
+                break
+            }
+            disconnected.push(node.block.clone());
+            hash = node.block.header.prev_block_hash.clone();
+        }
+        let fork_height = self.blocks.get(&hash).map_or(0, |node| node.block.index as usize + 1);
+        (disconnected, &self.chain[fork_height.min(self.chain.len())..])
+    }
+
+    // block의 tx를 검증하고 utxo_set에 적용해 chain의 tip으로 연결한다.
+    // 검증에 실패하면 utxo_set과 chain은 변경되지 않는다.
+    fn connect_block(&mut self, block: Block, utxo_set: &mut UtxoSet) -> Result<(), BlockValidationErr> {
         if let Some((coinbase, transactions)) = block.transactions.split_first() {
             // 비트코인의 경우 transaction field(Vec<transaction>)에 항상 coinbase transaction이 포함되어 있다.
             // 왜? coinbase transaction은 블록을 블록체인에 추가하는 채굴자에게 "인센티브"를 주는 역할을 하기 때문에 항상 포함되어 있음.
@@ -148,79 +442,240 @@ impl Blockchain {
             if !coinbase.is_coinbase() {
                 return Err(BlockValidationErr::InvalidCoinbaseTransaction)
             }
-            // let mut block_spent: HashSet<Hash> = HashSet::new();
-            // let mut block_created: HashSet<Hash> = HashSet::new();
-            let mut total_fee = 0;
-
-            // get coinbase txid
-            // println!("Coinbase TxId: {}", coinbase_txid);
-
-            let hashed_coinbase_tx = coinbase.hash();
-            let coinbase_txid = &hex::encode(hashed_coinbase_tx);
-            for (output_index, output) in coinbase.outputs.iter().enumerate() {
-                let script_pubkey = format!("{}:{}", &output.to_addr, &output.to_addr); // coinbase_tx의 sender와 recipient는 모두 같은 광부임
-                utxo_set.add_utxo(coinbase_txid.clone(), output_index, output.value, script_pubkey.to_owned());
+            // coinbase에는 Input이 없으므로, 높이가 없다면 같은 Output을 가진 coinbase는 txid가 같아진다.
+            // 앞선 coinbase의 UTXO를 덮어쓰지 않도록 block 높이를 기록해야 한다(btc BIP34).
+            if coinbase.coinbase_height() != Some(block.index) {
+                return Err(BlockValidationErr::InvalidCoinbaseTransaction)
+            }
+
+            // 먼저 검증하고, 모든 검증을 통과한 block만 utxo_set에 적용한다(validate first, apply second).
+            // 검증 중에는 utxo_set을 수정하지 않고, 이 block에서 사용된 output(block_spent)과
+            // 생성된 output(block_created)만 따로 추적한다. block 안의 앞선 tx가 만든 output은 뒤의 tx가 사용할 수 있다.
+            let mut block_spent: HashSet<OutPoint> = HashSet::new();
+            let mut total_fee: u64 = 0;
+            let mut block_created: HashMap<OutPoint, Utxo> = HashMap::new();
+
+            for (vout, output) in coinbase.outputs.iter().enumerate() {
+                block_created.insert(coinbase.outpoint(vout as u32), Utxo::from_output(output, block.index, true));
             }
 
             for transaction in transactions {
-                // utxo set에 추가.
-                let txid = &hex::encode(transaction.hash());
-                for (output_index, output) in transaction.outputs.iter().enumerate() {
-                    let script_pubkey = format!("{}:{}", sender, &output.to_addr);
-                    utxo_set.add_utxo(txid.clone(), output_index, output.value, script_pubkey.to_owned());
+                // 같은 block 안에서 이미 사용된 output을 다시 사용하면 double-spending.
+                for input in transaction.inputs.iter() {
+                    if !block_spent.insert(input.outpoint) {
+                        return Err(BlockValidationErr::InvalidInput)
+                    }
                 }
 
-                // let input_hashes = transaction.input_hashes();
-                //
-                // if !(&input_hashes - &self.unspent_outputs).is_empty() ||
-                //     !(&input_hashes & &block_spent).is_empty() {
-                //     return Err(BlockValidationErr::InvalidInput)
-                // }
+                // utxo_set에도, 이 block의 앞선 tx에도 없는 output은 사용할 수 없다.
+                let fee = check_transaction(transaction, self, |outpoint| {
+                    block_created.get(outpoint).or_else(|| utxo_set.get(outpoint))
+                })?;
+                total_fee = total_fee
+                    .checked_add(fee)
+                    .ok_or(BlockValidationErr::InsufficientInputValue)?;
+
+                let txid = transaction.txid();
+                for (vout, output) in transaction.outputs.iter().enumerate() {
+                    block_created.insert(OutPoint::new(txid, vout as u32), Utxo::from_output(output, block.index, false));
+                }
+            }
 
-                let input_value = transaction.input_value();
-                let output_value = transaction.output_value();
+            // coinbase는 block subsidy와 이 block의 tx들이 낸 fee의 합보다 많이 가져갈 수 없다.
+            // 적게 가져가는 것은 허용되며, 가져가지 않은 만큼은 영원히 사라진다(btc와 동일).
+            let coinbase_value = coinbase.output_value().ok_or(BlockValidationErr::InvalidCoinbaseTransaction)?;
+            if coinbase_value > block_subsidy(block.index).saturating_add(total_fee) {
+                return Err(BlockValidationErr::InvalidCoinbaseTransaction)
+            }
 
-                if output_value > input_value {
-                    return Err(BlockValidationErr::InsufficientInputValue);
-                }
+        } else {
+            // coinbase가 없는 block은 받지 않는다.
+            return Err(BlockValidationErr::InvalidCoinbaseTransaction)
+        }
 
-                let fee = input_value - output_value;
+        // 검증을 모두 통과했으므로 utxo_set에 적용하고, disconnect_tip에서 되돌릴 수 있도록 undo 기록을 남긴다.
+        let undo = utxo_set.apply_block(&block)?;
 
-                total_fee += fee;
+        self.tip = block.hash.clone();
+        self.chain.push(block);
+        self.undo.push(undo);
 
-                // block_spent.extend(input_hashes);
-                // block_created.extend(transaction.output_hashes());
-                //
-                // // get txid
-                // let hashed_tx = transaction.hash();
-                // // let txid = &hex::encode(hashed_tx);
-                // // // println!("TxId: {}", txid);
+        Ok(())
+    }
 
-                // remove used UTXOs.
-                for (_, txid_index) in transaction.inputs.iter() {
-                    let mut parts = txid_index.split(":");
-                    utxo_set.spend(parts.next().unwrap().to_owned(), parts.next().unwrap().parse::<usize>().unwrap()).expect("Utxo does not exist");
+    // chain의 마지막 block을 떼어내고, 그 block의 undo 기록으로 utxo_set을 block 적용 전 상태로 되돌린다.
+    // 떼어낸 block을 반환하며, chain이 비어 있으면 None.
+    pub fn disconnect_tip(&mut self, utxo_set: &mut UtxoSet) -> Option<Block> {
+        let block = self.chain.pop()?;
+        let undo = self.undo.pop().expect("undo record must exist for every connected block");
+        utxo_set.undo_block(undo);
+
+        self.tip = match self.chain.last() {
+            Some(prev_block) => prev_block.hash.clone(),
+            None => vec![],
+        };
+
+        Some(block)
+    }
+
+    // 현재 chain을 new_tip으로 끝나는 branch로 교체한다.
+    // fork 지점까지 tip을 disconnect해 utxo_set을 되돌린 뒤 새 branch의 block을 순서대로 connect(replay)한다.
+    // 새 branch의 block이 검증에 실패하면 그 block과 자손을 tree에서 제거한다. 그때까지 연결된 새 branch의 앞부분(prefix)이
+    // 원래 chain보다 무거우면 그 prefix에 머물고, 그렇지 않으면 원래 chain을 복구한다. 어느 경우든 Err를 반환하므로
+    // 호출한 쪽은 Err여도 tip이 바뀌었는지 확인해야 한다.
+    fn reorganize(&mut self, new_tip: &Hash, utxo_set: &mut UtxoSet) -> Result<(), BlockValidationErr> {
+        let mut branch = vec![];
+        let mut hash = new_tip.clone();
+        while !self.is_active(&hash) {
+            let block = &self.blocks[&hash].block;
+            hash = block.header.prev_block_hash.clone();
+            branch.push(block.clone());
+        }
+        branch.reverse();
+
+        let fork_height = branch[0].index as usize;
+        let old_work = self.tip_work();
+        let mut disconnected = vec![];
+        while self.chain.len() > fork_height {
+            disconnected.extend(self.disconnect_tip(utxo_set));
+        }
+
+        for block in branch {
+            let hash = block.hash.clone();
+            if let Err(e) = self.connect_block(block, utxo_set) {
+                self.invalidate(&hash);
+                if self.tip_work() <= old_work {
+                    while self.chain.len() > fork_height {
+                        self.disconnect_tip(utxo_set);
+                    }
+                    for block in disconnected.into_iter().rev() {
+                        self.connect_block(block, utxo_set).expect("Failed to reconnect previously valid block");
+                    }
                 }
+                return Err(e)
             }
+        }
 
-            // if coinbase.output_value() < total_fee {
-            //      return Err(BlockValidationErr::InvalidCoinbaseTransaction)
-            // } else {
-            //     block_created.extend(coinbase.output_hashes());
-            // }
+        Ok(())
+    }
 
-            // // unspent_output인 것만 남기기
-            // self.unspent_outputs.retain(|output| !block_spent.contains(output));
-            //
-            // self.unspent_outputs.extend(block_created);
+    // hash가 현재 chain(active chain)에 포함된 block인지
+    pub fn is_active(&self, hash: &Hash) -> bool {
+        match self.blocks.get(hash) {
+            Some(node) => self.chain.get(node.block.index as usize).is_some_and(|block| &block.hash == hash),
+            None => false,
+        }
+    }
 
-            for utxo in &utxo_set.utxos {
-                println!("{:?}", utxo);
-            }
+    // 검증에 실패한 block과 그 자손 block을 tree에서 제거한다.
+    fn invalidate(&mut self, hash: &Hash) {
+        let mut stack = vec![hash.clone()];
+        while let Some(hash) = stack.pop() {
+            self.blocks.remove(&hash);
+            self.headers.remove(&hash);
+            // header tree는 blocks를 포함하므로 header tree에서 자손을 찾는다.
+            stack.extend(
+                self.headers
+                    .iter()
+                    .filter(|(_, node)| node.header.prev_block_hash == hash)
+                    .map(|(hash, _)| hash.clone())
+            );
         }
+    }
+}
 
-        self.chain.push(block);
+// coinbase가 아닌 tx 하나를 검증하고 fee(Input 총 가치 - Output 총 가치)를 반환한다.
+// tx는 blockchain의 active chain tip 위에 연결될 block에 포함된다고 보고 검증하며(coinbase maturity, timelock),
+// lookup은 Input이 참조하는 UTXO를 찾는다.
+// block 검증(connect_block)과 mempool이 같은 규칙으로 tx를 검증하기 위해 사용한다.
+pub fn check_transaction<'a>(
+    transaction: &Transaction,
+    blockchain: &Blockchain,
+    lookup: impl Fn(&OutPoint) -> Option<&'a Utxo>,
+) -> Result<u64, BlockValidationErr> {
+    // coinbase tx는 block의 첫 번째 tx 하나뿐이어야 한다.
+    if transaction.is_coinbase() || !transaction.coinbase_data.is_empty() {
+        return Err(BlockValidationErr::InvalidCoinbaseTransaction)
+    }
 
-        Ok(())
+    let spend_height = blockchain.chain.len() as u32;
+    let median_time_past = blockchain.chain.last().map_or(0, |tip| blockchain.median_time_past(tip));
+    if !transaction.is_final(spend_height, median_time_past) {
+        return Err(BlockValidationErr::NonFinalTransaction)
+    }
+
+    let mut spent = HashSet::new();
+    for input in transaction.inputs.iter() {
+        if !spent.insert(input.outpoint) {
+            return Err(BlockValidationErr::InvalidInput)
+        }
+
+        let utxo = lookup(&input.outpoint).ok_or(BlockValidationErr::InvalidInput)?;
+
+        // Input이 참조하는 UTXO의 locking script(script_pubkey)를 Input의 script_sig로 unlock할 수 있는지 확인한다.
+        // P2PKH라면 pubkey가 address로 hashing되는지, signature가 tx digest에 대해 유효한지 확인한다.
+        if utxo.value != input.prev_output.value || *utxo.script_pubkey() != input.prev_output.script_pubkey {
+            return Err(BlockValidationErr::InvalidInput)
+        }
+        transaction
+            .verify_input(input, utxo.script_pubkey())
+            .map_err(BlockValidationErr::InvalidScript)?;
+        if !utxo.is_mature(spend_height, blockchain.coinbase_maturity) {
+            return Err(BlockValidationErr::ImmatureCoinbaseSpend)
+        }
+
+        // relative lock: UTXO가 포함된 block 이후로 필요한 block 수(또는 시간)가 지났는지
+        let unlocked = match input.relative_lock() {
+            None => true,
+            Some((false, blocks)) => spend_height.saturating_sub(utxo.height) as u128 >= blocks,
+            Some((true, time)) => {
+                median_time_past.saturating_sub(blockchain.coin_median_time_past(utxo.height)) >= time
+            },
+        };
+        if !unlocked {
+            return Err(BlockValidationErr::SequenceLockNotSatisfied)
+        }
+    }
+
+    let input_value = transaction.input_value().ok_or(BlockValidationErr::InsufficientInputValue)?;
+    let output_value = transaction.output_value().ok_or(BlockValidationErr::InsufficientInputValue)?;
+
+    if output_value > input_value {
+        return Err(BlockValidationErr::InsufficientInputValue);
+    }
+
+    Ok(input_value - output_value)
+}
+
+// index 높이의 block이 coinbase로 발행할 수 있는 양. HALVING_INTERVAL block마다 절반이 된다.
+pub fn block_subsidy(index: u32) -> u64 {
+    let halvings = index / HALVING_INTERVAL;
+    if halvings >= u64::BITS {
+        return 0
+    }
+    INITIAL_SUBSIDY >> halvings
+}
+
+// target * actual_timespan / target_timespan.
+// 구간이 목표보다 빨리 채굴되었다면 target을 낮춰(더 어렵게), 늦게 채굴되었다면 높인다(더 쉽게). GENESIS_BITS보다 쉬워질 수는 없다.
+// pow limit(2^244) 근처의 target에 timespan(ms)을 곱하면 256bit를 넘을 수 있다.
+// 그때는 target = q * target_timespan + r로 나누어 q * actual + r * actual / target_timespan을 계산한다(결과는 같다).
+fn retarget(prev_target: U256, actual_timespan: u128, target_timespan: u128) -> U256 {
+    let pow_limit = target::compact_to_target(GENESIS_BITS).unwrap();
+    let (actual, timespan) = (actual_timespan as u64, target_timespan as u64);
+    let new_target = match prev_target.checked_mul_u64(actual) {
+        Some(product) => product.div_u64(timespan),
+        None => {
+            let (quotient, rem) = prev_target.div_rem_u64(timespan);
+            let rem_part = (rem as u128 * actual as u128 / timespan as u128) as u64;
+            quotient.saturating_mul_u64(actual).saturating_add(U256::from_u64(rem_part))
+        },
+    };
+    if new_target > pow_limit {
+        pow_limit
+    } else if new_target.is_zero() {
+        U256::ONE
+    } else {
+        new_target
     }
 }
\ No newline at end of file
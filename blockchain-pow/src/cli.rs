@@ -0,0 +1,158 @@
+use std::{path::PathBuf, process::ExitCode};
+use clap::{Parser, Subcommand};
+use crate::app::{self, App, AppErr};
+use crate::blockchain;
+
+// Exit codes. 잘못된 인자(usage error)는 clap이 2로 종료한다.
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_INVALID_CHAIN: u8 = 3; // 저장된 chain이나 UTXO set이 검증을 통과하지 못함
+
+#[derive(Parser)]
+#[command(name = "blockchain", about = "A proof-of-work blockchain stored in a local data dir")]
+pub struct Cli {
+    /// Directory holding the chain, UTXO set, mempool and wallets
+    #[arg(long, global = true, default_value = app::DATA_DIR)]
+    pub data_dir: PathBuf,
+
+    /// Blocks a coinbase output must wait before it can be spent. Every command on one chain must use the same value
+    #[arg(long, global = true, default_value_t = blockchain::COINBASE_MATURITY)]
+    pub coinbase_maturity: u32,
+
+    /// Passphrase of the wallets. It is not stored, so it must be given every time
+    #[arg(long, global = true, env = "BLOCKCHAIN_PASSPHRASE", hide_env_values = true, default_value = "")]
+    pub passphrase: String,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Mine the genesis block, paying its coinbase to a wallet
+    Init {
+        #[arg(long, default_value = app::DEFAULT_WALLET)]
+        wallet: String,
+    },
+    /// Mine blocks on top of the tip, including transactions from the mempool
+    Mine {
+        #[arg(long, default_value_t = 1)]
+        blocks: u32,
+        #[arg(long, default_value = app::DEFAULT_WALLET)]
+        wallet: String,
+    },
+    /// Send coins from a wallet to a wallet or an address. The transaction waits in the mempool for the next `mine`
+    Send {
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+        #[arg(long)]
+        amount: u64,
+        /// Fee per transaction and per input
+        #[arg(long, default_value_t = 1)]
+        fee: u64,
+    },
+    /// Show the balance of a wallet or an address
+    Balance {
+        #[arg(default_value = app::DEFAULT_WALLET)]
+        wallet: String,
+    },
+    /// Print the mnemonic of a wallet. Anyone who sees it can spend the wallet's coins
+    ShowMnemonic {
+        wallet: String,
+    },
+    /// Show a block of the active chain by height or hash
+    ShowBlock {
+        block: String,
+    },
+    /// Replay the stored chain with full validation and compare the result with the stored UTXO set
+    VerifyChain,
+    /// Run a P2P node that mines and relays blocks with its peers
+    Node {
+        #[arg(long)]
+        listen: String,
+        #[arg(long = "peer")]
+        peers: Vec<String>,
+        /// Wallet receiving the block rewards
+        #[arg(long, default_value = app::DEFAULT_WALLET)]
+        wallet: String,
+    },
+}
+
+pub fn run() -> ExitCode {
+    let cli = Cli::parse();
+    match execute(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            match e {
+                AppErr::InvalidChain(_) => ExitCode::from(EXIT_INVALID_CHAIN),
+                _ => ExitCode::from(EXIT_FAILURE),
+            }
+        },
+    }
+}
+
+fn execute(cli: Cli) -> Result<(), AppErr> {
+    if let Command::Node { listen, peers, wallet } = &cli.command {
+        return app::run_node(&cli.data_dir, cli.coinbase_maturity, &cli.passphrase, listen, peers, wallet)
+    }
+
+    let mut app = App::open(&cli.data_dir, cli.coinbase_maturity, &cli.passphrase)?;
+    match cli.command {
+        Command::Init { wallet } => {
+            let genesis_block = app.init(&wallet)?;
+            println!("Mined genesis Satoshi {:?}", genesis_block);
+        },
+        Command::Mine { blocks, wallet } => {
+            for block in app.mine(blocks, &wallet)? {
+                println!("Mined {:?}", block);
+            }
+        },
+        Command::Send { from, to, amount, fee } => {
+            let transaction = app.send(&from, &to, amount, fee)?;
+            println!("Added {} to the mempool", hex::encode(transaction.txid()));
+            for output in &transaction.outputs {
+                match output.address() {
+                    Some(address) => println!("  {}, {}", address, output.value),
+                    None => println!("  {}, {}", output.script_pubkey, output.value),
+                }
+            }
+        },
+        Command::Balance { wallet } => {
+            let (confirmed, pending) = app.balance(&wallet)?;
+            println!("{}: {}", wallet, confirmed - pending);
+            if pending > 0 {
+                println!("spent by unconfirmed transactions: {}", pending);
+            }
+        },
+        Command::ShowMnemonic { wallet } => {
+            println!("{}", app.mnemonic(&wallet)?);
+        },
+        Command::ShowBlock { block } => {
+            let block = app.show_block(&block)?;
+            println!("{:?}", block);
+            println!("prev: {}", hex::encode(&block.header.prev_block_hash));
+            println!("merkle root: {}", hex::encode(&block.header.merkle_root));
+            println!("bits: {:#010x}", block.header.bits);
+            for transaction in &block.transactions {
+                println!("tx {}", hex::encode(transaction.txid()));
+                for input in &transaction.inputs {
+                    println!("  in  {}, {}", input.outpoint, input.prev_output.value);
+                }
+                for output in &transaction.outputs {
+                    match output.address() {
+                        Some(address) => println!("  out {}, {}", address, output.value),
+                        None => println!("  out {}, {}", output.script_pubkey, output.value),
+                    }
+                }
+            }
+        },
+        Command::VerifyChain => {
+            let height = app.verify_chain()?;
+            println!("Verified {} blocks, tip: {}", height, hex::encode(&app.blockchain.tip));
+        },
+        Command::Node { .. } => unreachable!(),
+    }
+    Ok(())
+}
@@ -0,0 +1,369 @@
+use super::*;
+use crate::script::{Op, Script};
+use crate::transaction::{Input, OutPoint, Output};
+
+// Canonical encoding.
+// hashing(txid), 저장소, network에서 같은 직렬화를 사용한다. 하나의 값은 정확히 하나의 byte 열로만 encoding되어야
+// 서로 다른 tx가 같은 byte 열(같은 txid)을 갖지 않는다.
+// - 숫자는 고정 길이 little-endian
+// - 가변 길이 field(address, hash, signature, list)는 길이(u32)를 앞에 붙인다
+// - 최상위 값(encode/decode)의 맨 앞에는 FORMAT_VERSION을 붙여 형식이 바뀌어도 구분할 수 있게 한다
+// block header는 miner가 nonce 자리만 바꿔가며 hashing할 수 있도록 고정 길이(HEADER_SIZE) 형식을 그대로 사용한다.
+pub const FORMAT_VERSION: u8 = 1;
+
+// custom Error type
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeErr {
+    UnexpectedEnd,
+    TrailingBytes,
+    UnsupportedVersion(u8),
+    InvalidUtf8,
+    InvalidBool(u8),
+    InvalidOpcode(u8),
+}
+
+pub trait Encode {
+    fn encode_to(&self, e: &mut Encoder);
+}
+
+pub trait Decode: Sized {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr>;
+}
+
+// FORMAT_VERSION + value
+pub fn encode<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+    let mut e = Encoder::new();
+    e.u8(FORMAT_VERSION);
+    value.encode_to(&mut e);
+    e.into_bytes()
+}
+
+// encode의 역연산. 모르는 version이거나 값 뒤에 남는 byte가 있으면 거부한다.
+pub fn decode<T: Decode>(bytes: &[u8]) -> Result<T, DecodeErr> {
+    let mut d = Decoder::new(bytes);
+    let version = d.u8()?;
+    if version != FORMAT_VERSION {
+        return Err(DecodeErr::UnsupportedVersion(version))
+    }
+    let value = T::decode_from(&mut d)?;
+    d.finish()?;
+    Ok(value)
+}
+
+#[derive(Default)]
+pub struct Encoder(Vec<u8>);
+
+impl Encoder {
+    pub fn new() -> Self {
+        Encoder(vec![])
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.0.extend(u32_to_bytes(&v));
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.0.extend(u64_to_bytes(&v));
+    }
+
+    pub fn u128(&mut self, v: u128) {
+        self.0.extend(u128_to_bytes(&v));
+    }
+
+    // 길이 없이 그대로 쓴다. 고정 길이 field에만 사용한다.
+    pub fn raw(&mut self, v: &[u8]) {
+        self.0.extend(v);
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.u32(v.len() as u32);
+        self.0.extend(v);
+    }
+
+    pub fn string(&mut self, v: &str) {
+        self.bytes(v.as_bytes());
+    }
+
+    pub fn list<T: Encode>(&mut self, v: &[T]) {
+        self.u32(v.len() as u32);
+        for item in v {
+            item.encode_to(self);
+        }
+    }
+}
+
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Decoder { bytes, pos: 0 }
+    }
+
+    pub fn raw(&mut self, len: usize) -> Result<&'a [u8], DecodeErr> {
+        let end = self.pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(DecodeErr::UnexpectedEnd)?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DecodeErr> {
+        Ok(self.raw(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, DecodeErr> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            v => Err(DecodeErr::InvalidBool(v)),
+        }
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DecodeErr> {
+        Ok(u32::from_le_bytes(self.raw(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DecodeErr> {
+        Ok(u64::from_le_bytes(self.raw(8)?.try_into().unwrap()))
+    }
+
+    pub fn u128(&mut self) -> Result<u128, DecodeErr> {
+        Ok(u128::from_le_bytes(self.raw(16)?.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, DecodeErr> {
+        let len = self.u32()? as usize;
+        Ok(self.raw(len)?.to_vec())
+    }
+
+    pub fn string(&mut self) -> Result<String, DecodeErr> {
+        String::from_utf8(self.bytes()?).map_err(|_| DecodeErr::InvalidUtf8)
+    }
+
+    // 개수를 먼저 읽지만 미리 할당하지 않는다. 잘못된 개수로 큰 메모리를 할당하지 않도록,
+    // 실제 byte가 모자라면 UnexpectedEnd로 멈춘다.
+    pub fn list<T: Decode>(&mut self) -> Result<Vec<T>, DecodeErr> {
+        let len = self.u32()?;
+        (0..len).map(|_| T::decode_from(self)).collect()
+    }
+
+    pub fn finish(&self) -> Result<(), DecodeErr> {
+        if self.pos != self.bytes.len() {
+            return Err(DecodeErr::TrailingBytes)
+        }
+        Ok(())
+    }
+}
+
+// op 하나는 1 byte의 opcode로, Push만 opcode 뒤에 길이를 붙인 data가 온다.
+const OP_PUSH: u8 = 0x00;
+const OP_DUP: u8 = 0x01;
+const OP_DROP: u8 = 0x02;
+const OP_EQUAL: u8 = 0x03;
+const OP_EQUALVERIFY: u8 = 0x04;
+const OP_VERIFY: u8 = 0x05;
+const OP_SHA256: u8 = 0x06;
+const OP_HASH_ADDRESS: u8 = 0x07;
+const OP_CHECKSIG: u8 = 0x08;
+const OP_CHECKMULTISIG: u8 = 0x09;
+const OP_CHECKLOCKTIMEVERIFY: u8 = 0x0a;
+const OP_IF: u8 = 0x0b;
+const OP_ELSE: u8 = 0x0c;
+const OP_ENDIF: u8 = 0x0d;
+const OP_CHECKSEQUENCEVERIFY: u8 = 0x0e;
+
+impl Encode for Op {
+    fn encode_to(&self, e: &mut Encoder) {
+        match self {
+            Op::Push(data) => {
+                e.u8(OP_PUSH);
+                e.bytes(data);
+            },
+            Op::Dup => e.u8(OP_DUP),
+            Op::Drop => e.u8(OP_DROP),
+            Op::Equal => e.u8(OP_EQUAL),
+            Op::EqualVerify => e.u8(OP_EQUALVERIFY),
+            Op::Verify => e.u8(OP_VERIFY),
+            Op::Sha256 => e.u8(OP_SHA256),
+            Op::HashAddress => e.u8(OP_HASH_ADDRESS),
+            Op::CheckSig => e.u8(OP_CHECKSIG),
+            Op::CheckMultiSig => e.u8(OP_CHECKMULTISIG),
+            Op::CheckLockTimeVerify => e.u8(OP_CHECKLOCKTIMEVERIFY),
+            Op::CheckSequenceVerify => e.u8(OP_CHECKSEQUENCEVERIFY),
+            Op::If => e.u8(OP_IF),
+            Op::Else => e.u8(OP_ELSE),
+            Op::EndIf => e.u8(OP_ENDIF),
+        }
+    }
+}
+
+impl Decode for Op {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        Ok(match d.u8()? {
+            OP_PUSH => Op::Push(d.bytes()?),
+            OP_DUP => Op::Dup,
+            OP_DROP => Op::Drop,
+            OP_EQUAL => Op::Equal,
+            OP_EQUALVERIFY => Op::EqualVerify,
+            OP_VERIFY => Op::Verify,
+            OP_SHA256 => Op::Sha256,
+            OP_HASH_ADDRESS => Op::HashAddress,
+            OP_CHECKSIG => Op::CheckSig,
+            OP_CHECKMULTISIG => Op::CheckMultiSig,
+            OP_CHECKLOCKTIMEVERIFY => Op::CheckLockTimeVerify,
+            OP_CHECKSEQUENCEVERIFY => Op::CheckSequenceVerify,
+            OP_IF => Op::If,
+            OP_ELSE => Op::Else,
+            OP_ENDIF => Op::EndIf,
+            opcode => return Err(DecodeErr::InvalidOpcode(opcode)),
+        })
+    }
+}
+
+impl Encode for Script {
+    fn encode_to(&self, e: &mut Encoder) {
+        e.list(self.ops());
+    }
+}
+
+impl Decode for Script {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        Ok(Script::new(d.list()?))
+    }
+}
+
+impl Encode for Output {
+    fn encode_to(&self, e: &mut Encoder) {
+        e.u64(self.value);
+        self.script_pubkey.encode_to(e);
+    }
+}
+
+impl Decode for Output {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        let value = d.u64()?;
+        Ok(Output::with_script(Script::decode_from(d)?, value))
+    }
+}
+
+// txid는 항상 32 bytes이므로 길이 없이 쓴다.
+impl Encode for OutPoint {
+    fn encode_to(&self, e: &mut Encoder) {
+        e.raw(&self.txid);
+        e.u32(self.vout);
+    }
+}
+
+impl Decode for OutPoint {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        Ok(OutPoint {
+            txid: d.raw(32)?.try_into().unwrap(),
+            vout: d.u32()?,
+        })
+    }
+}
+
+impl Input {
+    // signature를 제외한 encoding. signature_hash 계산에 사용된다.
+    pub(crate) fn encode_unsigned_to(&self, e: &mut Encoder) {
+        self.prev_output.encode_to(e);
+        self.outpoint.encode_to(e);
+        e.u32(self.sequence);
+    }
+}
+
+impl Encode for Input {
+    fn encode_to(&self, e: &mut Encoder) {
+        self.encode_unsigned_to(e);
+        self.script_sig.encode_to(e);
+    }
+}
+
+impl Decode for Input {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        let prev_output = Output::decode_from(d)?;
+        let mut input = Input::new(prev_output, OutPoint::decode_from(d)?);
+        input.sequence = d.u32()?;
+        input.script_sig = Script::decode_from(d)?;
+        Ok(input)
+    }
+}
+
+impl Encode for Transaction {
+    fn encode_to(&self, e: &mut Encoder) {
+        e.list(&self.inputs);
+        e.list(&self.outputs);
+        e.u64(self.lock_time);
+        e.bytes(&self.coinbase_data);
+    }
+}
+
+impl Decode for Transaction {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        let inputs = d.list()?;
+        let outputs = d.list()?;
+        let mut transaction = Transaction::new(inputs, outputs);
+        transaction.lock_time = d.u64()?;
+        transaction.coinbase_data = d.bytes()?;
+        Ok(transaction)
+    }
+}
+
+impl Encode for BlockHeader {
+    fn encode_to(&self, e: &mut Encoder) {
+        e.raw(&self.bytes());
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        Ok(BlockHeader {
+            version: d.u32()?,
+            prev_block_hash: d.raw(32)?.to_vec(),
+            merkle_root: d.raw(32)?.to_vec(),
+            timestamp: d.u128()?,
+            bits: d.u32()?,
+            nonce: d.u32()?,
+        })
+    }
+}
+
+// block hash는 header에서 다시 계산할 수 있으므로 encoding하지 않는다.
+impl Encode for Block {
+    fn encode_to(&self, e: &mut Encoder) {
+        e.u32(self.index);
+        self.header.encode_to(e);
+        e.list(&self.transactions);
+    }
+}
+
+impl Decode for Block {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        let index = d.u32()?;
+        let header = BlockHeader::decode_from(d)?;
+        let transactions = d.list()?;
+        Ok(Block {
+            index,
+            hash: header.hash(),
+            header,
+            transactions,
+        })
+    }
+}
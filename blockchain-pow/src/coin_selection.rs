@@ -0,0 +1,299 @@
+use ring::rand::{SecureRandom, SystemRandom};
+use crate::blockchain::BlockValidationErr;
+use crate::transaction::OutPoint;
+use crate::utxo::Utxo;
+
+// change가 이 값보다 작으면 change Output을 만들지 않고 fee로 넘긴다.
+// 사용하는 데 드는 fee가 가치보다 큰(dust) Output을 만들지 않기 위함.
+pub const DUST_THRESHOLD: u64 = 3;
+pub const DEFAULT_BASE_FEE: u64 = 1; // Input 수와 관계없이 tx 하나가 내는 fee
+pub const DEFAULT_FEE_PER_INPUT: u64 = 1; // Input 하나가 늘어날 때마다 추가되는 fee
+
+const BNB_MAX_TRIES: usize = 100_000;
+const KNAPSACK_ITERATIONS: usize = 1_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    // 가치가 큰 UTXO부터 필요한 만큼 사용한다. Input 수가 가장 적지만 큰 UTXO가 잘게 쪼개져 change가 쌓인다.
+    LargestFirst,
+    // change 없이 target에 정확히(dust 이내로) 맞는 조합을 깊이 우선 탐색한다. 찾지 못하면 Knapsack으로 넘어간다.
+    BranchAndBound,
+    // 무작위로 UTXO를 넣고 빼며 target 이상이면서 가장 가까운 조합을 근사적으로 찾는다.
+    Knapsack,
+}
+
+// 선택된 UTXO(outpoint, UTXO)와 fee, change
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub inputs: Vec<(OutPoint, Utxo)>,
+    pub fee: u64,
+    pub change: u64, // 0이면 change Output을 만들지 않는다.
+}
+
+impl Selection {
+    // 합이 u64를 넘으면 None
+    pub fn input_value(&self) -> Option<u64> {
+        self.inputs.iter().try_fold(0u64, |sum, (_, utxo)| sum.checked_add(utxo.value))
+    }
+}
+
+// Coin selection.
+// 보낼 금액(target)과 fee를 채우는 UTXO 조합을 고른다. Input이 하나 늘 때마다 fee도 fee_per_input만큼 늘어나므로
+// UTXO의 가치 대신 effective value(가치 - fee_per_input)로 비교하고, 사용하는 데 드는 fee보다 가치가 작은 UTXO는 고르지 않는다.
+pub struct CoinSelector {
+    pub strategy: Strategy,
+    pub base_fee: u64,
+    pub fee_per_input: u64,
+    pub dust_threshold: u64,
+}
+
+impl CoinSelector {
+    pub fn new(strategy: Strategy, base_fee: u64, fee_per_input: u64) -> Self {
+        CoinSelector {
+            strategy,
+            base_fee,
+            fee_per_input,
+            dust_threshold: DUST_THRESHOLD,
+        }
+    }
+
+    pub fn select(&self, candidates: Vec<(OutPoint, Utxo)>, target_value: u64) -> Result<Selection, BlockValidationErr> {
+        let mut candidates = candidates
+            .into_iter()
+            .filter(|(_, utxo)| utxo.value > self.fee_per_input)
+            .collect::<Vec<_>>();
+        // 가치가 큰 순서. 같다면 outpoint 순서로 정렬해 결과가 HashMap 순서에 따라 달라지지 않게 한다.
+        candidates.sort_by(|(a_outpoint, a), (b_outpoint, b)| b.value.cmp(&a.value).then_with(|| a_outpoint.cmp(b_outpoint)));
+
+        let values = candidates
+            .iter()
+            .map(|(_, utxo)| utxo.value - self.fee_per_input)
+            .collect::<Vec<u64>>();
+        // need와 need + dust_threshold가 u64 안에 있어야 change를 계산할 수 있다.
+        let need = target_value
+            .checked_add(self.base_fee)
+            .ok_or(BlockValidationErr::InsufficientInputValue)?;
+        need.checked_add(self.dust_threshold).ok_or(BlockValidationErr::InsufficientInputValue)?;
+        // 합이 u64를 넘을 만큼 많다면 need보다 적을 수 없으므로 포화시켜 비교한다.
+        if values.iter().fold(0u64, |sum, &value| sum.saturating_add(value)) < need {
+            return Err(BlockValidationErr::InsufficientInputValue)
+        }
+
+        let selected = match self.strategy {
+            Strategy::LargestFirst => largest_first(&values, need),
+            Strategy::BranchAndBound => branch_and_bound(&values, need, self.dust_threshold)
+                .or_else(|| knapsack(&values, need, self.dust_threshold)),
+            Strategy::Knapsack => knapsack(&values, need, self.dust_threshold),
+        }
+        .ok_or(BlockValidationErr::InsufficientInputValue)?;
+
+        // 고른 Input의 합이 u64를 넘는 tx는 유효하지 않다(Transaction::input_value).
+        let effective_value = selected
+            .iter()
+            .try_fold(0u64, |sum, &i| sum.checked_add(values[i]))
+            .ok_or(BlockValidationErr::InsufficientInputValue)?;
+        let inputs = selected
+            .into_iter()
+            .map(|i| candidates[i].clone())
+            .collect::<Vec<_>>();
+        let input_value = inputs
+            .iter()
+            .try_fold(0u64, |sum, (_, utxo)| sum.checked_add(utxo.value))
+            .ok_or(BlockValidationErr::InsufficientInputValue)?;
+
+        // dust보다 작은 change는 Output으로 만들지 않고 fee에 더한다.
+        let excess = effective_value - need;
+        let change = if excess < self.dust_threshold { 0 } else { excess };
+
+        Ok(Selection {
+            inputs,
+            fee: input_value - target_value - change,
+            change,
+        })
+    }
+}
+
+impl Default for CoinSelector {
+    fn default() -> Self {
+        CoinSelector::new(Strategy::BranchAndBound, DEFAULT_BASE_FEE, DEFAULT_FEE_PER_INPUT)
+    }
+}
+
+// values는 내림차순으로 정렬되어 있다. 반환값은 선택한 values의 index.
+fn largest_first(values: &[u64], need: u64) -> Option<Vec<usize>> {
+    let mut total = 0;
+    let mut selected = vec![];
+    for (i, &value) in values.iter().enumerate() {
+        if total >= need {
+            break
+        }
+        total = value.saturating_add(total);
+        selected.push(i);
+    }
+    (total >= need).then_some(selected)
+}
+
+// need 이상 need + dust_threshold 미만인(change가 필요 없는) 조합 중 초과분이 가장 적은 조합.
+// 큰 값부터 "넣는다/뺀다"를 깊이 우선으로 탐색하고, 이미 범위를 넘었거나 남은 값을 모두 더해도 모자라면 가지를 친다.
+fn branch_and_bound(values: &[u64], need: u64, dust_threshold: u64) -> Option<Vec<usize>> {
+    struct Search<'a> {
+        values: &'a [u64],
+        remaining: Vec<u64>, // remaining[i] = values[i..]의 합
+        upper: u64,
+        need: u64,
+        tries: usize,
+        current: Vec<usize>,
+        best: Option<(u64, Vec<usize>)>,
+    }
+
+    impl Search<'_> {
+        fn search(&mut self, i: usize, total: u64) {
+            self.tries += 1;
+            if self.tries > BNB_MAX_TRIES || total > self.upper {
+                return
+            }
+            if total >= self.need {
+                if self.best.as_ref().is_none_or(|(best, _)| total < *best) {
+                    self.best = Some((total, self.current.clone()));
+                }
+                return
+            }
+            if i == self.values.len() || total.saturating_add(self.remaining[i]) < self.need {
+                return
+            }
+            self.current.push(i);
+            self.search(i + 1, total.saturating_add(self.values[i]));
+            self.current.pop();
+            self.search(i + 1, total);
+        }
+    }
+
+    let mut remaining = vec![0; values.len() + 1];
+    for i in (0..values.len()).rev() {
+        remaining[i] = values[i].saturating_add(remaining[i + 1]);
+    }
+
+    let mut search = Search {
+        values,
+        remaining,
+        upper: need.saturating_add(dust_threshold.saturating_sub(1)),
+        need,
+        tries: 0,
+        current: vec![],
+        best: None,
+    };
+    search.search(0, 0);
+    search.best.map(|(_, selected)| selected)
+}
+
+// btc core의 knapsack 방식.
+// need보다 작은 UTXO들로 무작위 부분집합을 여러 번 만들어 need 이상이면서 가장 가까운 합을 찾고,
+// need + dust_threshold 이상인 UTXO 중 가장 작은 것 하나와 비교해 더 작은 쪽을 사용한다.
+fn knapsack(values: &[u64], need: u64, dust_threshold: u64) -> Option<Vec<usize>> {
+    if let Some(i) = values.iter().position(|&value| value == need) {
+        return Some(vec![i])
+    }
+
+    let upper = need.saturating_add(dust_threshold);
+    let mut smallest_larger = None;
+    let mut lower = vec![];
+    for (i, &value) in values.iter().enumerate() {
+        if value < upper {
+            lower.push(i);
+        } else {
+            // 내림차순이므로 마지막으로 만나는 값이 가장 작다.
+            smallest_larger = Some(i);
+        }
+    }
+
+    let lower_total = lower.iter().fold(0u64, |sum, &i| sum.saturating_add(values[i]));
+    if lower_total == need {
+        return Some(lower)
+    }
+    if lower_total < need {
+        return smallest_larger.map(|i| vec![i])
+    }
+
+    let lower_values = lower.iter().map(|&i| values[i]).collect::<Vec<_>>();
+    let mut rng = XorShift::new();
+    let (mut best_total, mut best) = approximate_best_subset(&lower_values, lower_total, need, &mut rng);
+    // change가 dust가 되는 조합이라면 change를 만들 수 있을 만큼 큰 조합을 한 번 더 찾는다.
+    if best_total != need && best_total < upper {
+        let (total, subset) = approximate_best_subset(&lower_values, lower_total, upper, &mut rng);
+        if total >= upper {
+            best_total = total;
+            best = subset;
+        }
+    }
+
+    if let Some(i) = smallest_larger {
+        if best_total != need && values[i] <= best_total {
+            return Some(vec![i])
+        }
+    }
+
+    Some(
+        best.iter()
+            .enumerate()
+            .filter(|(_, &included)| included)
+            .map(|(j, _)| lower[j])
+            .collect()
+    )
+}
+
+// 두 번의 pass로 부분집합을 만든다. 첫 pass는 각 값을 무작위로 넣고, 두 번째 pass는 첫 pass에서 빠진 값을 넣는다.
+// target을 넘으면 지금까지의 최선과 비교한 뒤 마지막 값을 빼고 계속 탐색한다.
+fn approximate_best_subset(values: &[u64], total_lower: u64, target: u64, rng: &mut XorShift) -> (u64, Vec<bool>) {
+    let mut best = vec![true; values.len()];
+    let mut best_total = total_lower;
+
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_total == target {
+            break
+        }
+        let mut included = vec![false; values.len()];
+        let mut total = 0;
+        let mut reached_target = false;
+        for pass in 0..2 {
+            if reached_target {
+                break
+            }
+            for i in 0..values.len() {
+                let pick = if pass == 0 { rng.next_bool() } else { !included[i] };
+                if pick {
+                    total = values[i].saturating_add(total);
+                    included[i] = true;
+                    if total >= target {
+                        reached_target = true;
+                        if total < best_total {
+                            best_total = total;
+                            best = included.clone();
+                        }
+                        total -= values[i];
+                        included[i] = false;
+                    }
+                }
+            }
+        }
+    }
+
+    (best_total, best)
+}
+
+// knapsack의 무작위 선택에 사용하는 xorshift64. 암호학적 난수가 필요하지 않으므로 seed만 SystemRandom으로 만든다.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new() -> Self {
+        let mut seed = [0u8; 8];
+        SystemRandom::new().fill(&mut seed).expect("Failed to generate random seed");
+        XorShift(u64::from_le_bytes(seed) | 1)
+    }
+
+    fn next_bool(&mut self) -> bool {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 & 1 == 1
+    }
+}
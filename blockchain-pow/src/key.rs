@@ -0,0 +1,98 @@
+use super::*;
+use ring::{
+    rand::{SecureRandom, SystemRandom},
+    signature::{self, Ed25519KeyPair, KeyPair, UnparsedPublicKey},
+};
+
+// UTXO의 소유권은 Ed25519 keypair로 증명한다.
+// Output은 public key를 hashing한 address로 잠기고(locking), 그 Output을 Input으로 사용하려면
+// address로 hashing되는 public key와, 그 public key에 대응하는 private key로 만든 signature를 함께 제출해야 한다.
+// (P2PKH(pay-to-public-key-hash)와 같은 방식)
+pub struct Privatekey {
+    seed: [u8; 32],
+    keypair: Ed25519KeyPair,
+}
+
+impl Privatekey {
+    pub fn new() -> Self {
+        let mut seed = [0u8; 32];
+        SystemRandom::new().fill(&mut seed).expect("Failed to generate random seed");
+        Privatekey::from_seed(seed)
+    }
+
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        let keypair = Ed25519KeyPair::from_seed_unchecked(&seed).expect("Invalid Ed25519 seed");
+        Privatekey { seed, keypair }
+    }
+
+    pub fn seed(&self) -> [u8; 32] {
+        self.seed
+    }
+
+    pub fn pubkey(&self) -> Hash {
+        self.keypair.public_key().as_ref().to_vec()
+    }
+
+    pub fn address(&self) -> Address {
+        pubkey_to_address(&self.pubkey())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Hash {
+        self.keypair.sign(message).as_ref().to_vec()
+    }
+}
+
+impl Default for Privatekey {
+    fn default() -> Self {
+        Privatekey::new()
+    }
+}
+
+impl std::fmt::Debug for Privatekey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Privatekey")
+            .field(&self.address())
+            .finish()
+    }
+}
+
+// Address(Base58Check).
+// [ADDRESS_VERSION(1)][public key의 SHA-256 hash(32)][checksum(4)]를 base58로 encoding한다.
+// checksum은 앞의 33byte를 SHA-256으로 두 번 hashing한 값의 앞 4byte로, 잘못 입력한 address에 coin을 보내는 것을 막는다.
+pub const ADDRESS_VERSION: u8 = 0x00;
+const CHECKSUM_SIZE: usize = 4;
+
+pub fn pubkey_to_address(pubkey: &[u8]) -> Address {
+    let mut payload = vec![ADDRESS_VERSION];
+    payload.extend(crypto_hash::digest(crypto_hash::Algorithm::SHA256, pubkey));
+    payload.extend(checksum(&payload));
+    bs58::encode(payload).into_string()
+}
+
+// checksum이 맞는 address라면 public key hash를 반환한다.
+pub fn decode_address(address: &str) -> Option<Hash> {
+    let bytes = bs58::decode(address).into_vec().ok()?;
+    if bytes.len() != 1 + 32 + CHECKSUM_SIZE || bytes[0] != ADDRESS_VERSION {
+        return None
+    }
+    let (payload, checksum_bytes) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+    if checksum(payload) != checksum_bytes {
+        return None
+    }
+    Some(payload[1..].to_vec())
+}
+
+pub fn is_valid_address(address: &str) -> bool {
+    decode_address(address).is_some()
+}
+
+fn checksum(payload: &[u8]) -> Vec<u8> {
+    let hash = crypto_hash::digest(crypto_hash::Algorithm::SHA256, payload);
+    crypto_hash::digest(crypto_hash::Algorithm::SHA256, &hash)[..CHECKSUM_SIZE].to_vec()
+}
+
+pub fn verify(pubkey: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    UnparsedPublicKey::new(&signature::ED25519, pubkey)
+        .verify(message, signature)
+        .is_ok()
+}
@@ -2,19 +2,32 @@ use chrono::prelude::*;
 
 pub mod block;
 mod hashable;
-mod blockchain;
+pub mod blockchain;
 pub mod transaction;
+pub mod key;
+pub mod target;
+pub mod miner;
 pub mod app;
 pub mod utxo;
-pub mod handler;
+pub mod mempool;
+pub mod coin_selection;
+pub mod storage;
+pub mod codec;
+pub mod script;
+pub mod network;
+pub mod wallet;
+pub mod cli;
 
 pub use crate::{ // Leave main.rs compact. mods loaded into lib.rs from elsewhere can be used in main.rs without specifying them in main.rs
-    block::Block,
+    block::{Block, BlockHeader, MerkleProof},
     hashable::Hashable,
     blockchain::Blockchain,
     transaction::Transaction,
+    key::Privatekey,
+    target::U256,
+    miner::Miner,
     utxo::UtxoSet,
-    handler::*,
+    mempool::Mempool,
 };
 
 type Hash = Vec<u8>;
@@ -25,68 +38,13 @@ pub fn now() -> u128 {
 }
 
 pub fn u32_to_bytes(u: &u32) -> [u8; 4] { // e.g. u32_to_bytes(u32::max) = [255, 255, 255, 255]
-    [
-        (u >> 8 * 0x0) as u8,
-        (u >> 8 * 0x1) as u8,
-        (u >> 8 * 0x2) as u8,
-        (u >> 8 * 0x3) as u8,
-    ]
+    u.to_le_bytes()
 }
 
 pub fn u64_to_bytes(u: &u64) -> [u8; 8] {
-    [
-        (u >> 8 * 0x0) as u8,
-        (u >> 8 * 0x1) as u8,
-        (u >> 8 * 0x2) as u8,
-        (u >> 8 * 0x3) as u8,
-
-        (u >> 8 * 0x4) as u8,
-        (u >> 8 * 0x5) as u8,
-        (u >> 8 * 0x6) as u8,
-        (u >> 8 * 0x7) as u8,
-    ]
+    u.to_le_bytes()
 }
 
 pub fn u128_to_bytes(u: &u128) -> [u8; 16] {
-    [
-        (u >> 8 * 0x0) as u8,
-        (u >> 8 * 0x1) as u8,
-        (u >> 8 * 0x2) as u8,
-        (u >> 8 * 0x3) as u8,
-
-        (u >> 8 * 0x4) as u8,
-        (u >> 8 * 0x5) as u8,
-        (u >> 8 * 0x6) as u8,
-        (u >> 8 * 0x7) as u8,
-
-        (u >> 8 * 0x8) as u8,
-        (u >> 8 * 0x9) as u8,
-        (u >> 8 * 0xa) as u8,
-        (u >> 8 * 0xb) as u8,
-
-        (u >> 8 * 0xc) as u8,
-        (u >> 8 * 0xd) as u8,
-        (u >> 8 * 0xe) as u8,
-        (u >> 8 * 0xf) as u8,
-    ]
-}
-
-// v[16..32] u8 type으로 담긴 difficulty의 값을 u128로 치환하기.
-pub fn difficulty_bytes_as_u128 (v: &Vec<u8>) -> u128 {
-    ((v[31] as u128) << 0xf * 8) |
-    ((v[30] as u128) << 0xe * 8) |
-    ((v[29] as u128) << 0xd * 8) |
-    ((v[28] as u128) << 0xc * 8) |
-    ((v[27] as u128) << 0xb * 8) |
-    ((v[26] as u128) << 0xa * 8) |
-    ((v[25] as u128) << 0x9 * 8) |
-    ((v[24] as u128) << 0x8 * 8) |
-    ((v[23] as u128) << 0x7 * 8) |
-    ((v[22] as u128) << 0x6 * 8) |
-    ((v[21] as u128) << 0x5 * 8) |
-    ((v[20] as u128) << 0x4 * 8) |
-    ((v[19] as u128) << 0x3 * 8) |
-    ((v[18] as u128) << 0x2 * 8) |
-    ((v[17] as u128) << 0x1 * 8) |
-    ((v[16] as u128) << 0x0 * 8)
+    u.to_le_bytes()
 }
\ No newline at end of file
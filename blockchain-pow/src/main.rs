@@ -1,5 +1,28 @@
-use blockchainlib::app;
+use std::process::ExitCode;
+use blockchainlib::cli;
 
-fn main() {
-    app::run();
-}
\ No newline at end of file
+// library(network 등)가 log로 남긴 message를 stderr로 출력한다.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+fn main() -> ExitCode {
+    log::set_logger(&LOGGER).expect("Failed to set logger");
+    log::set_max_level(log::LevelFilter::Info);
+
+    cli::run()
+}
@@ -0,0 +1,438 @@
+use super::*;
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+use crate::blockchain::{self, BlockValidationErr};
+use crate::transaction::{OutPoint, Txid};
+use crate::utxo::Utxo;
+
+pub const DEFAULT_MAX_MEMPOOL_SIZE: usize = 300_000; // mempool이 보관할 수 있는 tx의 총 크기(byte)
+pub const DEFAULT_MAX_BLOCK_SIZE: usize = 100_000; // block template에 담을 수 있는 tx의 총 크기(byte)
+
+// custom Error type
+#[derive(Debug)]
+pub enum MempoolErr {
+    AlreadyKnown,
+    Conflict, // 이미 mempool에 있는 tx가 사용하는 output을 다시 사용하려는 tx 중, 교체(replace-by-fee)할 수 없는 tx
+    InsufficientFee, // 기존 tx를 교체하기 위한 fee 조건을 만족하지 못한 tx
+    MempoolFull, // 크기 제한을 넘어 mempool에서 밀려난 tx. fee rate가 가장 낮은 tx부터 밀려난다.
+    Invalid(BlockValidationErr),
+}
+
+impl From<BlockValidationErr> for MempoolErr {
+    fn from(e: BlockValidationErr) -> Self {
+        MempoolErr::Invalid(e)
+    }
+}
+
+// 아직 block에 포함되지 않은(unconfirmed) tx.
+#[derive(Clone)]
+pub struct MempoolEntry {
+    pub transaction: Transaction,
+    pub txid: Txid,
+    pub fee: u64,
+    pub size: usize, // 직렬화한 tx의 크기(byte)
+    sequence: u64, // mempool에 들어온 순서. fee rate가 같다면 먼저 들어온 tx를 먼저 꺼낸다.
+}
+
+impl MempoolEntry {
+    pub fn cmp_fee_rate(&self, other: &MempoolEntry) -> Ordering {
+        cmp_fee_rate((self.fee, self.size), (other.fee, other.size))
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+// fee per byte. 소수점 계산을 피하기 위해 fee_a / size_a 와 fee_b / size_b를
+// fee_a * size_b 와 fee_b * size_a 로 비교한다.
+fn cmp_fee_rate((fee_a, size_a): (u64, usize), (fee_b, size_b): (u64, usize)) -> Ordering {
+    (fee_a as u128 * size_b as u128).cmp(&(fee_b as u128 * size_a as u128))
+}
+
+// Mempool.
+// node가 받은 unconfirmed tx를 block에 포함될 때까지 보관한다. 들어오는 tx는 현재 utxo_set(tip 기준)으로 검증하고,
+// 이미 mempool에 있는 tx와 같은 output을 사용하는 tx(conflict)는 기존 tx보다 fee를 더 낼 때만 기존 tx를 교체한다(replace-by-fee).
+// 아직 block에 포함되지 않은 mempool tx의 output도 사용할 수 있으며, 이렇게 연결된 tx들(parent, child)은
+// block template을 만들 때 하나의 package로 묶여 선택된다(child-pays-for-parent).
+// 채굴자는 block 크기가 제한되어 있으므로 fee per byte가 높은 tx부터 block template에 담고,
+// mempool이 크기 제한을 넘으면 fee per byte가 가장 낮은 tx부터 버린다.
+pub struct Mempool {
+    entries: HashMap<Txid, MempoolEntry>, // txid -> entry
+    spends: HashMap<OutPoint, Txid>, // mempool tx가 사용하는 outpoint -> 사용하는 tx의 txid
+    total_size: usize,
+    max_size: usize,
+    next_sequence: u64,
+}
+
+impl Mempool {
+    pub fn new(max_size: usize) -> Self {
+        Mempool {
+            entries: HashMap::new(),
+            spends: HashMap::new(),
+            total_size: 0,
+            max_size,
+            next_sequence: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+
+    pub fn contains(&self, txid: &Txid) -> bool {
+        self.entries.contains_key(txid)
+    }
+
+    pub fn get(&self, txid: &Txid) -> Option<&MempoolEntry> {
+        self.entries.get(txid)
+    }
+
+    // mempool의 tx가 이미 사용한 outpoint인지
+    pub fn spends(&self, outpoint: &OutPoint) -> bool {
+        self.spends.contains_key(outpoint)
+    }
+
+    // mempool에 들어온 순서대로의 tx. parent tx는 항상 child tx보다 먼저 들어온다.
+    pub fn transactions(&self) -> Vec<&Transaction> {
+        let mut entries = self.entries.values().collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.sequence);
+        entries.into_iter().map(|entry| &entry.transaction).collect()
+    }
+
+    // tx를 검증해 mempool에 추가하고 txid를 반환한다.
+    // tx는 다음 block(tip + 1)에 포함된다고 가정하고 검증하므로 coinbase maturity도 그 높이를 기준으로 확인한다.
+    pub fn add_transaction(
+        &mut self,
+        transaction: Transaction,
+        blockchain: &Blockchain,
+        utxo_set: &UtxoSet,
+    ) -> Result<Txid, MempoolErr> {
+        let txid = transaction.txid();
+        if self.entries.contains_key(&txid) {
+            return Err(MempoolErr::AlreadyKnown)
+        }
+
+        // utxo_set에 없는 output은 mempool에 있는 parent tx의 output에서 찾는다.
+        let spend_height = blockchain.chain.last().map_or(0, |tip| tip.index + 1);
+        let unconfirmed = self.unconfirmed_outputs(&transaction, spend_height);
+        let fee = blockchain::check_transaction(&transaction, blockchain, |outpoint| {
+            utxo_set.get(outpoint).or_else(|| unconfirmed.get(outpoint))
+        })?;
+        let size = transaction.bytes().len();
+
+        // Replace-by-fee.
+        // 같은 output을 사용하는 mempool tx가 있다면, 새 tx가 그 tx들(과 그 tx들의 자손)보다 fee를 더 많이 내고,
+        // 각 conflict tx보다 fee per byte도 높아야 교체할 수 있다. 교체되는 tx들이 내던 fee만큼 mempool이 손해 보지 않도록 한다.
+        let conflicts = transaction.inputs
+            .iter()
+            .filter_map(|input| self.spends.get(&input.outpoint))
+            .copied()
+            .collect::<HashSet<Txid>>();
+        let mut replaced = HashSet::new();
+        for conflict in conflicts.iter() {
+            replaced.extend(self.descendants(conflict));
+        }
+        // 교체될 tx의 output을 사용하는 tx는 교체와 함께 사라질 output에 의존하므로 받을 수 없다.
+        if self.parents(&transaction).iter().any(|parent| replaced.contains(parent)) {
+            return Err(MempoolErr::Conflict)
+        }
+        let replaced_fee: u64 = replaced.iter().map(|txid| self.entries[txid].fee).sum();
+        let pays_more = conflicts
+            .iter()
+            .all(|txid| cmp_fee_rate((fee, size), (self.entries[txid].fee, self.entries[txid].size)) == Ordering::Greater);
+        if !conflicts.is_empty() && (fee <= replaced_fee || !pays_more) {
+            return Err(MempoolErr::InsufficientFee)
+        }
+        let mut removed = replaced.iter().filter_map(|txid| self.remove(txid)).collect::<Vec<_>>();
+
+        let entry = MempoolEntry {
+            size,
+            transaction,
+            txid,
+            fee,
+            sequence: self.next_sequence,
+        };
+        self.next_sequence += 1;
+        self.insert(entry);
+
+        // 새 tx가 크기 제한에 밀려났다면 교체된 tx와 함께 밀려난 tx를 모두 되돌린다.
+        // 새 tx를 받기 전의 mempool은 크기 제한 안에 있었으므로 되돌린 mempool도 그렇다.
+        removed.extend(self.trim_to_size());
+        if !self.entries.contains_key(&txid) {
+            for entry in removed.into_iter().filter(|entry| entry.txid != txid) {
+                self.insert(entry);
+            }
+            return Err(MempoolErr::MempoolFull)
+        }
+
+        Ok(txid)
+    }
+
+    // transaction이 사용하는 output 중 mempool tx가 만든(아직 block에 포함되지 않은) output
+    fn unconfirmed_outputs(&self, transaction: &Transaction, spend_height: u32) -> HashMap<OutPoint, Utxo> {
+        let mut outputs = HashMap::new();
+        for input in transaction.inputs.iter() {
+            let output = self.entries
+                .get(&input.outpoint.txid)
+                .and_then(|parent| parent.transaction.outputs.get(input.outpoint.vout as usize));
+            if let Some(output) = output {
+                outputs.insert(input.outpoint, Utxo::from_output(output, spend_height, false));
+            }
+        }
+        outputs
+    }
+
+    // transaction이 output을 사용하는 mempool tx들
+    fn parents(&self, transaction: &Transaction) -> HashSet<Txid> {
+        transaction.inputs
+            .iter()
+            .map(|input| input.outpoint.txid)
+            .filter(|txid| self.entries.contains_key(txid))
+            .collect()
+    }
+
+    // txid의 output을 사용하는 mempool tx들
+    fn children(&self, txid: &Txid) -> HashSet<Txid> {
+        let outputs = self.entries.get(txid).map_or(0, |entry| entry.transaction.outputs.len());
+        (0..outputs as u32)
+            .filter_map(|vout| self.spends.get(&OutPoint::new(*txid, vout)))
+            .copied()
+            .collect()
+    }
+
+    // txid와 그 자손(txid의 output을 직간접적으로 사용하는 tx)
+    fn descendants(&self, txid: &Txid) -> HashSet<Txid> {
+        let mut descendants = HashSet::new();
+        let mut stack = vec![*txid];
+        while let Some(txid) = stack.pop() {
+            if descendants.insert(txid) {
+                stack.extend(self.children(&txid));
+            }
+        }
+        descendants
+    }
+
+    // txid와 그 조상(txid가 직간접적으로 output을 사용하는 mempool tx)
+    fn ancestors(&self, txid: &Txid) -> HashSet<Txid> {
+        let mut ancestors = HashSet::new();
+        let mut stack = vec![*txid];
+        while let Some(txid) = stack.pop() {
+            if ancestors.insert(txid) {
+                stack.extend(self.parents(&self.entries[&txid].transaction));
+            }
+        }
+        ancestors
+    }
+
+    fn insert(&mut self, entry: MempoolEntry) {
+        for input in entry.transaction.inputs.iter() {
+            self.spends.insert(input.outpoint, entry.txid);
+        }
+        self.total_size += entry.size;
+        self.entries.insert(entry.txid, entry);
+    }
+
+    // tx 하나만 제거한다. 자손 tx가 남아 있다면 함께 제거해야 하는지는 호출하는 쪽에서 판단한다.
+    fn remove(&mut self, txid: &Txid) -> Option<MempoolEntry> {
+        let entry = self.entries.remove(txid)?;
+        for input in entry.transaction.inputs.iter() {
+            self.spends.remove(&input.outpoint);
+        }
+        self.total_size -= entry.size;
+        Some(entry)
+    }
+
+    // tx와 그 자손을 함께 제거하고 제거한 entry들을 반환한다. 자손 tx는 제거된 tx의 output에 의존하므로 홀로 남을 수 없다.
+    pub fn remove_with_descendants(&mut self, txid: &Txid) -> Vec<MempoolEntry> {
+        self.descendants(txid)
+            .iter()
+            .filter_map(|txid| self.remove(txid))
+            .collect()
+    }
+
+    // 크기 제한을 넘었다면 fee per byte가 가장 낮은 tx부터 자손과 함께 버린다.
+    // fee가 낮은 parent라도 fee가 높은 child가 있다면 함께 채굴될 수 있으므로,
+    // tx 자신과 자손 전체(package)의 fee per byte 중 높은 값을 기준으로 비교한다.
+    // 버린 entry들을 반환한다.
+    fn trim_to_size(&mut self) -> Vec<MempoolEntry> {
+        let mut evicted = vec![];
+        while self.total_size > self.max_size {
+            let lowest = self.entries
+                .keys()
+                .map(|txid| (self.descendant_score(txid), txid))
+                .min_by(|(a, _), (b, _)| cmp_fee_rate(*a, *b))
+                .map(|(_, txid)| *txid);
+            match lowest {
+                Some(txid) => evicted.extend(self.remove_with_descendants(&txid)),
+                None => break,
+            };
+        }
+        evicted
+    }
+
+    fn descendant_score(&self, txid: &Txid) -> (u64, usize) {
+        let entry = &self.entries[txid];
+        let package = self.package_fee_and_size(&self.descendants(txid));
+        match cmp_fee_rate(package, (entry.fee, entry.size)) {
+            Ordering::Greater => package,
+            _ => (entry.fee, entry.size),
+        }
+    }
+
+    fn package_fee_and_size(&self, txids: &HashSet<Txid>) -> (u64, usize) {
+        txids.iter().fold((0, 0), |(fee, size), txid| {
+            (fee + self.entries[txid].fee, size + self.entries[txid].size)
+        })
+    }
+
+    // block이 chain에 연결되면 block에 포함된 tx와, block의 tx와 같은 output을 사용하는(conflict) tx를 제거한다.
+    // block에 포함된 tx의 자손은 parent의 output이 utxo_set으로 옮겨졌을 뿐이므로 mempool에 남는다.
+    // conflict tx의 자손은 더 이상 존재할 수 없는 output에 의존하므로 함께 제거한다.
+    pub fn remove_for_block(&mut self, block: &Block) {
+        for transaction in block.transactions.iter() {
+            self.remove(&transaction.txid());
+            for input in transaction.inputs.iter() {
+                if let Some(txid) = self.spends.get(&input.outpoint).copied() {
+                    self.remove_with_descendants(&txid);
+                }
+            }
+        }
+    }
+
+    // reorg로 active chain에서 떨어져 나간 block의 tx(coinbase 제외)를 mempool로 되돌리고,
+    // 기존 mempool tx와 함께 새 tip 기준으로 다시 검증한다. 새 chain에 이미 포함되었거나 더 이상 유효하지 않은 tx는 버려진다.
+    // disconnected는 chain_changes와 같이 tip 쪽 block부터 들어 있다.
+    pub fn reorganize(&mut self, disconnected: &[Block], blockchain: &Blockchain, utxo_set: &UtxoSet) {
+        let mut entries = self.entries.drain().map(|(_, entry)| entry).collect::<Vec<_>>();
+        // parent tx가 child보다 먼저 mempool에 들어왔으므로 들어온 순서대로 다시 넣는다.
+        entries.sort_by_key(|entry| entry.sequence);
+        *self = Mempool::new(self.max_size);
+
+        for block in disconnected.iter().rev() {
+            for transaction in block.transactions.iter().skip(1) {
+                let _ = self.add_transaction(transaction.clone(), blockchain, utxo_set);
+            }
+        }
+        for entry in entries {
+            let _ = self.add_transaction(entry.transaction, blockchain, utxo_set);
+        }
+    }
+
+    // Package selection(child-pays-for-parent).
+    // child tx는 parent tx가 먼저 block에 포함되어야 유효하므로, 각 tx를 아직 선택되지 않은 조상들과 묶은 package의
+    // fee per byte로 비교한다. fee가 낮아 block에 포함되지 못하던 parent도 fee가 높은 child가 생기면 함께 선택된다.
+    // package의 fee per byte가 가장 높은 것부터, 총 크기가 max_block_size를 넘지 않도록 parent가 먼저 오는 순서로 담는다.
+    // 각 tx의 조상과 package의 fee, 크기는 처음 한 번만 계산하고, package가 선택되면 선택된 tx의 자손만 갱신한다.
+    // 반환되는 tx의 fee 합과 함께 block template을 만드는 데 사용된다.
+    pub fn select_transactions(&self, max_block_size: usize) -> (Vec<Transaction>, u64) {
+        let ancestors = self.entries
+            .keys()
+            .map(|txid| (*txid, self.ancestors(txid)))
+            .collect::<HashMap<_, _>>();
+        // txid -> 아직 선택되지 않은 조상과 자신의 (fee 합, 크기 합)
+        let mut packages = ancestors
+            .iter()
+            .map(|(txid, package)| (*txid, self.package_fee_and_size(package)))
+            .collect::<HashMap<_, _>>();
+        let mut candidates = packages
+            .iter()
+            .map(|(txid, &(fee, size))| Candidate { fee, size, sequence: self.entries[txid].sequence, txid: *txid })
+            .collect::<BinaryHeap<_>>();
+
+        let mut selected: HashSet<Txid> = HashSet::new();
+        let mut skipped: HashSet<Txid> = HashSet::new();
+        let mut size = 0;
+        let mut total_fee = 0;
+        let mut transactions = vec![];
+
+        while let Some(candidate) = candidates.pop() {
+            // package가 갱신되기 전에 넣어 둔 후보는 버린다.
+            if selected.contains(&candidate.txid)
+                || skipped.contains(&candidate.txid)
+                || packages[&candidate.txid] != (candidate.fee, candidate.size)
+            {
+                continue
+            }
+            if size + candidate.size > max_block_size {
+                skipped.insert(candidate.txid);
+                continue
+            }
+
+            // 조상이 적은 tx(parent)부터 담는다. 조상은 항상 자손보다 조상 수가 적다.
+            let mut package = ancestors[&candidate.txid]
+                .iter()
+                .filter(|txid| !selected.contains(*txid))
+                .map(|txid| (ancestors[txid].len(), self.entries[txid].sequence, *txid))
+                .collect::<Vec<_>>();
+            package.sort();
+            for (_, _, txid) in package {
+                let entry = &self.entries[&txid];
+                transactions.push(entry.transaction.clone());
+                selected.insert(txid);
+
+                // 선택된 tx는 자손들의 package에서 빠진다.
+                for descendant in self.descendants(&txid) {
+                    if selected.contains(&descendant) {
+                        continue
+                    }
+                    let (fee, size) = packages.get_mut(&descendant).unwrap();
+                    *fee -= entry.fee;
+                    *size -= entry.size;
+                    candidates.push(Candidate {
+                        fee: *fee,
+                        size: *size,
+                        sequence: self.entries[&descendant].sequence,
+                        txid: descendant,
+                    });
+                }
+            }
+            size += candidate.size;
+            total_fee += candidate.fee;
+        }
+
+        (transactions, total_fee)
+    }
+}
+
+// block template 후보. package의 fee per byte가 높을수록, 같다면 먼저 들어온 tx일수록 먼저 꺼낸다.
+struct Candidate {
+    fee: u64,
+    size: usize,
+    sequence: u64,
+    txid: Txid,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_fee_rate((self.fee, self.size), (other.fee, other.size))
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Mempool::new(DEFAULT_MAX_MEMPOOL_SIZE)
+    }
+}
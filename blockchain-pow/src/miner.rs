@@ -0,0 +1,126 @@
+use super::*;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+// Multi-thread miner.
+// nonce 공간(u32)을 worker thread 수만큼 나눠(thread i는 i, i + N, i + 2N, ... 를 시도) 동시에 탐색한다.
+// 각 worker는 block 전체가 아닌 고정 크기 header만 hashing하고, header의 nonce 자리(NONCE_OFFSET)만 바꿔가며 시도한다.
+// nonce 공간을 모두 소진하면 coinbase의 extra nonce를 올려 merkle_root가 다른 새로운 header로 다시 탐색한다.
+// 다른 node에서 새로운 tip이 도착하면 지금 채굴 중인 block은 의미가 없어지므로 cancel()로 탐색을 중단할 수 있다.
+pub struct Miner {
+    threads: usize,
+    max_nonce: u32, // header 하나에서 시도할 가장 큰 nonce. 이 값을 넘으면 extra nonce를 올린다.
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Miner {
+    pub fn new(threads: usize) -> Self {
+        Miner {
+            threads: threads.max(1),
+            max_nonce: u32::MAX,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // header마다 시도할 nonce를 0..=max_nonce로 제한한다. extra nonce가 올라가는 경로를 시험할 때 사용한다.
+    pub fn with_max_nonce(mut self, max_nonce: u32) -> Self {
+        self.max_nonce = max_nonce;
+        self
+    }
+
+    // 다른 thread에서 채굴을 중단시킬 수 있도록 cancel flag를 공유한다.
+    pub fn cancel_handle(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    // 새로운 block을 채굴하기 전에 cancel flag를 초기화한다.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // target을 만족하는 nonce를 찾으면 block의 nonce와 hash를 채우고 true를 반환한다.
+    // 채굴 도중 cancel되면 false를 반환하며, 이때 block의 nonce와 hash는 의미 없는 값이다.
+    pub fn mine(&self, block: &mut Block) -> bool {
+        let target = block.target().expect("Invalid compact bits");
+
+        loop {
+            if let Some(nonce) = self.search(&block.header.bytes(), target) {
+                block.header.nonce = nonce;
+                block.hash = block.header.hash();
+                return true;
+            }
+            if self.is_cancelled() {
+                return false;
+            }
+            // nonce 공간 소진. extra nonce를 올려 새로운 header를 만든다.
+            block.set_extra_nonce(block.extra_nonce().wrapping_add(1));
+        }
+    }
+
+    // header 하나에 대해 0..=max_nonce를 나눠서 탐색한다.
+    fn search(&self, header: &[u8], target: U256) -> Option<u32> {
+        let found = AtomicBool::new(false);
+        let found_nonce = AtomicU32::new(0);
+        let threads = self.threads as u32;
+        let max_nonce = self.max_nonce;
+
+        thread::scope(|scope| {
+            for start in 0..threads {
+                let found = &found;
+                let found_nonce = &found_nonce;
+                let cancelled = &self.cancelled;
+                let mut header = header.to_vec();
+
+                scope.spawn(move || {
+                    let mut nonce = start;
+                    if nonce > max_nonce {
+                        return;
+                    }
+                    loop {
+                        if found.load(Ordering::Relaxed) || cancelled.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        header[block::NONCE_OFFSET..].copy_from_slice(&u32_to_bytes(&nonce));
+                        let hash = crypto_hash::digest(crypto_hash::Algorithm::SHA256, &header);
+                        if U256::from_le_bytes(&hash) <= target {
+                            if !found.swap(true, Ordering::Relaxed) {
+                                found_nonce.store(nonce, Ordering::Relaxed);
+                            }
+                            return;
+                        }
+                        nonce = match nonce.checked_add(threads) {
+                            Some(next) if next <= max_nonce => next,
+                            _ => return,
+                        };
+                    }
+                });
+            }
+        });
+
+        if found.load(Ordering::Relaxed) {
+            Some(found_nonce.load(Ordering::Relaxed))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Miner {
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Miner::new(threads)
+    }
+}
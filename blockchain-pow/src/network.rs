@@ -0,0 +1,627 @@
+use super::*;
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Sender},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use crate::blockchain::BlockValidationErr;
+use crate::codec::{self, Decode, DecodeErr, Decoder, Encode, Encoder};
+use crate::mempool::MempoolErr;
+use crate::storage::BlockStore;
+use crate::transaction::Txid;
+
+// P2P network.
+// node들은 TCP로 연결되어 block과 tx를 주고받는다. message는 [magic(4)][payload 길이(4)][payload]로 framing하고,
+// payload는 codec의 canonical encoding을 그대로 사용한다.
+// 1. handshake: 연결되면 양쪽이 Version을 보내고, 상대의 Version을 받으면 Verack으로 답한다.
+//    Verack을 받기 전에는 다른 message를 받지 않는다.
+// 2. headers-first sync: handshake가 끝나면 block locator를 담은 GetHeaders를 보내고, 받은 Headers 중 모르는 block만
+//    GetData로 요청한다. header는 tx 없이 PoW, 연결, difficulty, timestamp를 검증할 수 있으므로(Blockchain::add_header)
+//    block 전체를 받기 전에 잘못된 chain을 걸러낸다.
+// 3. relay: 새로 tip이 된 block과 mempool에 들어온 tx는 Inv로 알리고, 상대는 모르는 것만 GetData로 받아 간다.
+//    부모를 모르는 block을 받으면 GetHeaders로 그 사이의 block부터 받아 온다.
+// 여러 node가 동시에 채굴하면 잠시 서로 다른 tip을 가질 수 있지만, 누적 work가 더 큰 branch가 relay되면 모두 그 branch로 reorg한다.
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const NETWORK_MAGIC: [u8; 4] = [0xf9, 0xbe, 0xb4, 0xd9]; // btc mainnet과 같은 값
+pub const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+pub const MAX_HEADERS: usize = 2_000; // Headers message 하나에 담는 최대 header 수
+
+type PeerId = u64;
+
+// custom Error type
+#[derive(Debug)]
+pub enum NetworkErr {
+    Io(io::Error),
+    Decode(DecodeErr),
+    InvalidMagic,
+    MessageTooLarge(usize),
+    UnsupportedVersion(u32),
+    SelfConnection, // 자기 자신에게 연결함(Version의 nonce가 같음)
+    GenesisMismatch, // 다른 genesis block으로 시작한 chain의 node
+    UnexpectedMessage, // handshake가 끝나기 전에 받은 message, 또는 두 번 받은 Version/Verack
+    InvalidMessageType(u8), // 모르는 message(또는 Inv item) type
+    InvalidHeaders(BlockValidationErr), // header 검증(Blockchain::add_header)에 실패했거나 서로 이어지지 않는 header
+}
+
+impl From<io::Error> for NetworkErr {
+    fn from(e: io::Error) -> Self {
+        NetworkErr::Io(e)
+    }
+}
+
+impl From<DecodeErr> for NetworkErr {
+    fn from(e: DecodeErr) -> Self {
+        NetworkErr::Decode(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub version: u32,
+    pub nonce: u64, // node마다 random한 값. 자기 자신과의 연결을 알아본다.
+    pub height: u32, // active chain의 block 수
+    pub genesis: Hash, // chain이 비어 있으면 빈 값
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvItem {
+    Block(Hash),
+    Tx(Txid),
+}
+
+#[derive(Clone)]
+pub enum Message {
+    Version(Version),
+    Verack,
+    Inv(Vec<InvItem>), // 가지고 있는 block, tx를 알린다
+    GetData(Vec<InvItem>), // Inv로 알게 된 block, tx를 요청한다
+    NotFound(Vec<InvItem>), // GetData로 요청받았지만 가지고 있지 않은 것
+    GetHeaders { locator: Vec<Hash>, stop: Hash }, // locator와 처음 일치하는 block 다음부터 stop(비어 있으면 MAX_HEADERS개)까지
+    Headers(Vec<BlockHeader>),
+    Block(Block),
+    Tx(Transaction),
+}
+
+const MSG_VERSION: u8 = 0x00;
+const MSG_VERACK: u8 = 0x01;
+const MSG_INV: u8 = 0x02;
+const MSG_GETDATA: u8 = 0x03;
+const MSG_NOTFOUND: u8 = 0x04;
+const MSG_GETHEADERS: u8 = 0x05;
+const MSG_HEADERS: u8 = 0x06;
+const MSG_BLOCK: u8 = 0x07;
+const MSG_TX: u8 = 0x08;
+
+const INV_BLOCK: u8 = 0x00;
+const INV_TX: u8 = 0x01;
+
+impl Encode for Version {
+    fn encode_to(&self, e: &mut Encoder) {
+        e.u32(self.version);
+        e.u64(self.nonce);
+        e.u32(self.height);
+        e.bytes(&self.genesis);
+    }
+}
+
+impl Decode for Version {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        Ok(Version {
+            version: d.u32()?,
+            nonce: d.u64()?,
+            height: d.u32()?,
+            genesis: d.bytes()?,
+        })
+    }
+}
+
+impl Encode for InvItem {
+    fn encode_to(&self, e: &mut Encoder) {
+        match self {
+            InvItem::Block(hash) => {
+                e.u8(INV_BLOCK);
+                e.bytes(hash);
+            },
+            InvItem::Tx(txid) => {
+                e.u8(INV_TX);
+                e.raw(txid);
+            },
+        }
+    }
+}
+
+// message type은 codec이 아닌 network protocol의 일부이므로 모르는 type은 NetworkErr로 구분한다.
+impl InvItem {
+    fn decode_from(d: &mut Decoder) -> Result<Self, NetworkErr> {
+        match d.u8()? {
+            INV_BLOCK => Ok(InvItem::Block(d.bytes()?)),
+            INV_TX => Ok(InvItem::Tx(d.raw(32)?.try_into().unwrap())),
+            tag => Err(NetworkErr::InvalidMessageType(tag)),
+        }
+    }
+
+    fn decode_list(d: &mut Decoder) -> Result<Vec<Self>, NetworkErr> {
+        let len = d.u32()?;
+        (0..len).map(|_| InvItem::decode_from(d)).collect()
+    }
+}
+
+impl Encode for Message {
+    fn encode_to(&self, e: &mut Encoder) {
+        match self {
+            Message::Version(version) => {
+                e.u8(MSG_VERSION);
+                version.encode_to(e);
+            },
+            Message::Verack => e.u8(MSG_VERACK),
+            Message::Inv(items) => {
+                e.u8(MSG_INV);
+                e.list(items);
+            },
+            Message::GetData(items) => {
+                e.u8(MSG_GETDATA);
+                e.list(items);
+            },
+            Message::NotFound(items) => {
+                e.u8(MSG_NOTFOUND);
+                e.list(items);
+            },
+            Message::GetHeaders { locator, stop } => {
+                e.u8(MSG_GETHEADERS);
+                e.u32(locator.len() as u32);
+                for hash in locator {
+                    e.bytes(hash);
+                }
+                e.bytes(stop);
+            },
+            Message::Headers(headers) => {
+                e.u8(MSG_HEADERS);
+                e.list(headers);
+            },
+            Message::Block(block) => {
+                e.u8(MSG_BLOCK);
+                block.encode_to(e);
+            },
+            Message::Tx(transaction) => {
+                e.u8(MSG_TX);
+                transaction.encode_to(e);
+            },
+        }
+    }
+}
+
+impl Message {
+    // codec::decode와 같이 FORMAT_VERSION을 확인하고, payload 전체가 message 하나여야 한다.
+    pub fn decode(payload: &[u8]) -> Result<Self, NetworkErr> {
+        let mut d = Decoder::new(payload);
+        let version = d.u8()?;
+        if version != codec::FORMAT_VERSION {
+            return Err(DecodeErr::UnsupportedVersion(version).into())
+        }
+        let message = Message::decode_from(&mut d)?;
+        d.finish()?;
+        Ok(message)
+    }
+
+    fn decode_from(d: &mut Decoder) -> Result<Self, NetworkErr> {
+        match d.u8()? {
+            MSG_VERSION => Ok(Message::Version(Version::decode_from(d)?)),
+            MSG_VERACK => Ok(Message::Verack),
+            MSG_INV => Ok(Message::Inv(InvItem::decode_list(d)?)),
+            MSG_GETDATA => Ok(Message::GetData(InvItem::decode_list(d)?)),
+            MSG_NOTFOUND => Ok(Message::NotFound(InvItem::decode_list(d)?)),
+            MSG_GETHEADERS => {
+                let len = d.u32()?;
+                let locator = (0..len).map(|_| d.bytes()).collect::<Result<_, DecodeErr>>()?;
+                Ok(Message::GetHeaders { locator, stop: d.bytes()? })
+            },
+            MSG_HEADERS => Ok(Message::Headers(d.list()?)),
+            MSG_BLOCK => Ok(Message::Block(Block::decode_from(d)?)),
+            MSG_TX => Ok(Message::Tx(Transaction::decode_from(d)?)),
+            tag => Err(NetworkErr::InvalidMessageType(tag)),
+        }
+    }
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Message) -> Result<(), NetworkErr> {
+    let payload = codec::encode(message);
+    let mut frame = Vec::with_capacity(8 + payload.len());
+    frame.extend(NETWORK_MAGIC);
+    frame.extend(u32_to_bytes(&(payload.len() as u32)));
+    frame.extend(payload);
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+// payload를 읽기 전에 magic과 길이를 확인해, 다른 protocol의 연결이나 너무 큰 message에 메모리를 할당하지 않는다.
+pub fn read_message<R: Read>(reader: &mut R) -> Result<Message, NetworkErr> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header)?;
+    if header[..4] != NETWORK_MAGIC {
+        return Err(NetworkErr::InvalidMagic)
+    }
+    let len = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(NetworkErr::MessageTooLarge(len))
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    Message::decode(&payload)
+}
+
+// node 하나의 chain 상태. peer thread들과 miner가 함께 사용하므로 Node 안에서 Mutex로 보호된다.
+pub struct NodeState {
+    pub blockchain: Blockchain,
+    pub utxo_set: UtxoSet,
+    pub mempool: Mempool,
+    store: Option<BlockStore>, // 있으면 tip이 바뀔 때마다 저장한다
+}
+
+impl NodeState {
+    // tip이 바뀐 뒤 mempool과 저장소를 새 chain에 맞춘다.
+    fn update_tip(&mut self, old_tip: &Hash) {
+        let (disconnected, connected) = self.blockchain.chain_changes(old_tip);
+        if disconnected.is_empty() {
+            for block in connected {
+                self.mempool.remove_for_block(block);
+            }
+        } else {
+            self.mempool.reorganize(&disconnected, &self.blockchain, &self.utxo_set);
+        }
+
+        if let Some(store) = self.store.as_mut() {
+            if let Err(e) = store.sync(&self.blockchain, &self.utxo_set) {
+                log::error!("Failed to store block: {:?}", e);
+            }
+        }
+    }
+}
+
+// peer마다 writer thread가 하나씩 있어 outbox로 보낸 message를 순서대로 쓴다.
+// 보내는 쪽은 socket이 막혀도 기다리지 않으므로, state lock을 잡은 채로 보내도 node끼리 서로를 기다리지 않는다.
+struct Peer {
+    addr: SocketAddr,
+    outbox: Sender<Message>,
+}
+
+struct Shared {
+    nonce: u64,
+    state: Mutex<NodeState>,
+    peers: Mutex<HashMap<PeerId, Peer>>,
+    next_peer_id: AtomicU64,
+    miner: Miner,
+}
+
+// lock 순서는 항상 state -> peers이다.
+#[derive(Clone)]
+pub struct Node {
+    shared: Arc<Shared>,
+}
+
+impl Node {
+    pub fn new(blockchain: Blockchain, utxo_set: UtxoSet, store: Option<BlockStore>, miner: Miner) -> Self {
+        let mut nonce = [0u8; 8];
+        SystemRandom::new().fill(&mut nonce).expect("Failed to generate random nonce");
+
+        Node {
+            shared: Arc::new(Shared {
+                nonce: u64::from_le_bytes(nonce),
+                state: Mutex::new(NodeState {
+                    blockchain,
+                    utxo_set,
+                    mempool: Mempool::default(),
+                    store,
+                }),
+                peers: Mutex::new(HashMap::new()),
+                next_peer_id: AtomicU64::new(0),
+                miner,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, NodeState> {
+        self.shared.state.lock().unwrap()
+    }
+
+    // active chain tip의 hash. chain이 비어 있으면 빈 값.
+    pub fn tip(&self) -> Hash {
+        self.state().blockchain.tip.clone()
+    }
+
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        self.shared.peers.lock().unwrap().values().map(|peer| peer.addr).collect()
+    }
+
+    // addr에서 들어오는 연결을 받는 thread를 띄우고 실제로 bind된 주소를 반환한다(port 0이면 OS가 고른 port).
+    pub fn listen<A: ToSocketAddrs>(&self, addr: A) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let node = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Err(e) = stream.and_then(|stream| node.add_peer(stream)) {
+                    log::warn!("Failed to accept peer: {}", e);
+                }
+            }
+        });
+        Ok(local_addr)
+    }
+
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        self.add_peer(TcpStream::connect(addr)?)
+    }
+
+    // 직접 만든 block(genesis, 채굴한 block)을 chain에 추가하고 peer들에게 알린다.
+    pub fn submit_block(&self, block: Block) -> Result<(), BlockValidationErr> {
+        self.accept_block(None, block)
+    }
+
+    // tx를 mempool에 추가하고 peer들에게 알린다.
+    pub fn submit_transaction(&self, transaction: Transaction) -> Result<Txid, MempoolErr> {
+        self.accept_transaction(None, transaction)
+    }
+
+    // tip 위에 block을 하나 채굴해 chain에 연결하고 peer들에게 알린다.
+    // 채굴 도중 다른 node의 block으로 tip이 바뀌면 채굴을 멈추고 None을 반환한다. 채굴한 block이 tip이 되지 못해도 None.
+    // chain이 비어 있으면(genesis를 받기 전) 채굴하지 않는다. Node는 Miner 하나를 공유하므로 한 thread에서만 호출해야 한다.
+    pub fn mine_block(&self, miner_addr: Address) -> Option<Block> {
+        let mut block = {
+            let state = self.state();
+            if state.blockchain.chain.is_empty() {
+                return None
+            }
+            // tip이 바뀔 때 cancel은 state lock 안에서 일어나므로, 같은 lock 안에서 초기화해야 취소를 놓치지 않는다.
+            self.shared.miner.reset();
+            state.blockchain.block_template(&state.mempool, miner_addr)
+        };
+
+        if !self.shared.miner.mine(&mut block) {
+            return None
+        }
+        self.submit_block(block.clone()).ok()?;
+        (self.tip() == block.hash).then_some(block)
+    }
+
+    fn version(&self) -> Version {
+        let state = self.state();
+        Version {
+            version: PROTOCOL_VERSION,
+            nonce: self.shared.nonce,
+            height: state.blockchain.chain.len() as u32,
+            genesis: state.blockchain.chain.first().map_or(vec![], |genesis| genesis.hash.clone()),
+        }
+    }
+
+    fn add_peer(&self, stream: TcpStream) -> io::Result<()> {
+        let addr = stream.peer_addr()?;
+        let id = self.shared.next_peer_id.fetch_add(1, Ordering::Relaxed);
+        let (outbox, inbox) = mpsc::channel::<Message>();
+
+        let mut writer = stream.try_clone()?;
+        thread::spawn(move || {
+            for message in inbox {
+                if write_message(&mut writer, &message).is_err() {
+                    break
+                }
+            }
+            let _ = writer.shutdown(Shutdown::Both);
+        });
+
+        let _ = outbox.send(Message::Version(self.version()));
+        self.shared.peers.lock().unwrap().insert(id, Peer { addr, outbox });
+
+        let node = self.clone();
+        thread::spawn(move || {
+            if let Err(e) = node.run_peer(id, &stream) {
+                log::info!("Disconnected from {}: {:?}", addr, e);
+            }
+            let _ = stream.shutdown(Shutdown::Both);
+            // outbox가 drop되면 writer thread도 끝난다.
+            node.shared.peers.lock().unwrap().remove(&id);
+        });
+
+        Ok(())
+    }
+
+    fn send(&self, id: PeerId, message: Message) {
+        if let Some(peer) = self.shared.peers.lock().unwrap().get(&id) {
+            let _ = peer.outbox.send(message);
+        }
+    }
+
+    // source(받아 온 peer)를 제외한 모든 peer에게 알린다.
+    fn relay(&self, source: Option<PeerId>, item: InvItem) {
+        for (id, peer) in self.shared.peers.lock().unwrap().iter() {
+            if Some(*id) != source {
+                let _ = peer.outbox.send(Message::Inv(vec![item.clone()]));
+            }
+        }
+    }
+
+    fn run_peer(&self, id: PeerId, mut stream: &TcpStream) -> Result<(), NetworkErr> {
+        let mut version_received = false;
+        let mut handshake_done = false;
+
+        loop {
+            match read_message(&mut stream)? {
+                Message::Version(version) => {
+                    if version_received {
+                        return Err(NetworkErr::UnexpectedMessage)
+                    }
+                    if version.version != PROTOCOL_VERSION {
+                        return Err(NetworkErr::UnsupportedVersion(version.version))
+                    }
+                    if version.nonce == self.shared.nonce {
+                        return Err(NetworkErr::SelfConnection)
+                    }
+                    let genesis = self.version().genesis;
+                    if !genesis.is_empty() && !version.genesis.is_empty() && genesis != version.genesis {
+                        return Err(NetworkErr::GenesisMismatch)
+                    }
+                    version_received = true;
+                    self.send(id, Message::Verack);
+                },
+                Message::Verack => {
+                    if !version_received || handshake_done {
+                        return Err(NetworkErr::UnexpectedMessage)
+                    }
+                    handshake_done = true;
+                    // 상대의 chain이 더 길 수 있으므로 header부터 받아 온다.
+                    let locator = self.state().blockchain.block_locator();
+                    self.send(id, Message::GetHeaders { locator, stop: vec![] });
+                },
+                _ if !handshake_done => return Err(NetworkErr::UnexpectedMessage),
+                message => self.handle_message(id, message)?,
+            }
+        }
+    }
+
+    fn handle_message(&self, id: PeerId, message: Message) -> Result<(), NetworkErr> {
+        match message {
+            Message::Inv(items) => {
+                let state = self.state();
+                let wanted = items
+                    .into_iter()
+                    .filter(|item| match item {
+                        InvItem::Block(hash) => !state.blockchain.blocks.contains_key(hash),
+                        InvItem::Tx(txid) => !state.mempool.contains(txid),
+                    })
+                    .collect::<Vec<_>>();
+                if !wanted.is_empty() {
+                    self.send(id, Message::GetData(wanted));
+                }
+            },
+            Message::GetData(items) => {
+                let state = self.state();
+                let mut not_found = vec![];
+                for item in items {
+                    let found = match &item {
+                        InvItem::Block(hash) => state.blockchain.blocks.get(hash).map(|node| Message::Block(node.block.clone())),
+                        InvItem::Tx(txid) => state.mempool.get(txid).map(|entry| Message::Tx(entry.transaction.clone())),
+                    };
+                    match found {
+                        Some(message) => self.send(id, message),
+                        None => not_found.push(item),
+                    }
+                }
+                if !not_found.is_empty() {
+                    self.send(id, Message::NotFound(not_found));
+                }
+            },
+            Message::NotFound(_) => {},
+            Message::GetHeaders { locator, stop } => {
+                let headers = headers_after(&self.state().blockchain, &locator, &stop);
+                self.send(id, Message::Headers(headers));
+            },
+            Message::Headers(headers) => self.handle_headers(id, headers)?,
+            Message::Block(block) => {
+                let hash = block.hash.clone();
+                if let Err(e) = self.accept_block(Some(id), block) {
+                    log::warn!("Rejected block {}: {:?}", hex::encode(hash), e);
+                }
+            },
+            Message::Tx(transaction) => {
+                let _ = self.accept_transaction(Some(id), transaction);
+            },
+            Message::Version(_) | Message::Verack => return Err(NetworkErr::UnexpectedMessage),
+        }
+        Ok(())
+    }
+
+    // header를 순서대로 header tree에 추가하고(Blockchain::add_header), 아직 받지 못한 block을 요청한다.
+    // 하나라도 검증에 실패하면 그 peer와의 연결을 끊는다. 앞에서 추가된 header는 유효하므로 tree에 남는다.
+    // 요청한 block은 header 순서대로 도착하므로 부모부터 chain에 연결된다.
+    fn handle_headers(&self, id: PeerId, headers: Vec<BlockHeader>) -> Result<(), NetworkErr> {
+        let mut state = self.state();
+        let mut wanted = vec![];
+        let mut prev_hash: Option<Hash> = None;
+
+        for header in headers.iter() {
+            if prev_hash.as_ref().is_some_and(|prev_hash| &header.prev_block_hash != prev_hash) {
+                return Err(NetworkErr::InvalidHeaders(BlockValidationErr::MismatchedPreviousHash))
+            }
+            let hash = state.blockchain.add_header(header.clone()).map_err(NetworkErr::InvalidHeaders)?;
+            if !state.blockchain.blocks.contains_key(&hash) {
+                wanted.push(InvItem::Block(hash.clone()));
+            }
+            prev_hash = Some(hash);
+        }
+
+        if !wanted.is_empty() {
+            self.send(id, Message::GetData(wanted));
+        }
+        // 가득 찬 Headers를 받았다면 상대에게 더 있을 수 있으므로 마지막 header 다음부터 이어서 요청한다.
+        if let Some(last) = prev_hash.filter(|_| headers.len() == MAX_HEADERS) {
+            self.send(id, Message::GetHeaders { locator: vec![last], stop: vec![] });
+        }
+        Ok(())
+    }
+
+    fn accept_block(&self, source: Option<PeerId>, block: Block) -> Result<(), BlockValidationErr> {
+        let mut state = self.state();
+        if state.blockchain.blocks.contains_key(&block.hash) {
+            return Ok(())
+        }
+
+        // 부모를 모르는 block(orphan). 그 사이의 block을 header부터 다시 받아 온다.
+        if block.index != 0 && !state.blockchain.blocks.contains_key(&block.header.prev_block_hash) {
+            if let Some(id) = source {
+                let locator = state.blockchain.block_locator();
+                self.send(id, Message::GetHeaders { locator, stop: block.hash.clone() });
+            }
+            return Err(BlockValidationErr::MismatchedPreviousHash)
+        }
+
+        let hash = block.hash.clone();
+        let old_tip = state.blockchain.tip.clone();
+        let state = &mut *state;
+        // reorg 도중 새 branch의 block이 검증에 실패해도, 앞부분이 더 무겁다면 tip은 바뀌어 있을 수 있다.
+        let result = state.blockchain.update_with_block(block, &mut state.utxo_set);
+
+        if state.blockchain.tip != old_tip {
+            state.update_tip(&old_tip);
+            // 지금 채굴 중인 block은 이전 tip 위에 있으므로 더 이상 의미가 없다.
+            self.shared.miner.cancel();
+        }
+        result?;
+        // 새 tip이 된 block만 알린다. 누적 work가 모자란 branch의 block은 다른 node의 tip도 바꾸지 못한다.
+        if state.blockchain.tip == hash {
+            self.relay(source, InvItem::Block(hash));
+        }
+        Ok(())
+    }
+
+    fn accept_transaction(&self, source: Option<PeerId>, transaction: Transaction) -> Result<Txid, MempoolErr> {
+        let mut state = self.state();
+        let state = &mut *state;
+        let txid = state.mempool.add_transaction(transaction, &state.blockchain, &state.utxo_set)?;
+        self.relay(source, InvItem::Tx(txid));
+        Ok(txid)
+    }
+}
+
+// locator 중 active chain에 있는 첫 번째 block 다음부터 stop까지(stop 포함), 최대 MAX_HEADERS개의 header.
+// 일치하는 block이 없으면 genesis부터 보낸다.
+fn headers_after(blockchain: &Blockchain, locator: &[Hash], stop: &Hash) -> Vec<BlockHeader> {
+    let start = locator
+        .iter()
+        .find(|hash| blockchain.is_active(hash))
+        .map_or(0, |hash| blockchain.blocks[hash].block.index as usize + 1);
+
+    let mut headers = vec![];
+    for block in blockchain.chain.iter().skip(start).take(MAX_HEADERS) {
+        headers.push(block.header.clone());
+        if &block.hash == stop {
+            break
+        }
+    }
+    headers
+}
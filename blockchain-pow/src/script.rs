@@ -0,0 +1,341 @@
+use super::*;
+use std::fmt::{self, Display, Formatter};
+use crate::transaction::{
+    LOCKTIME_THRESHOLD, SEQUENCE_FINAL, SEQUENCE_LOCKTIME_DISABLE_FLAG, SEQUENCE_LOCKTIME_MASK, SEQUENCE_LOCKTIME_TYPE_FLAG,
+};
+
+// Script.
+// Output은 사용 조건을 담은 locking script(script_pubkey)로 잠기고, Input은 그 조건을 만족시키는 unlocking script(script_sig)를 제출한다.
+// btc와 같이 script_sig를 먼저 실행해 stack에 data를 올리고, 같은 stack으로 script_pubkey를 실행해
+// 오류 없이 끝나고 stack의 맨 위가 true이면 사용할 수 있다.
+// script_sig는 data를 올리는 것(push) 외에는 할 수 없으므로 script_pubkey의 조건을 우회할 수 없다.
+// 반복문이 없고 실행할 수 있는 op 수가 제한되어 있어 실행 시간이 script 길이에 비례한다.
+//
+// 표준 script:
+// - P2PKH:     DUP HASH_ADDRESS <address> EQUALVERIFY CHECKSIG              | <sig> <pubkey>
+// - multisig:  <m> <pubkey 1> .. <pubkey n> <n> CHECKMULTISIG                | <sig 1> .. <sig m> (pubkey 순서대로)
+// - HTLC:      IF SHA256 <hash> EQUALVERIFY <P2PKH(recipient)>               | <sig> <pubkey> <preimage> 1
+//              ELSE <locktime> CHECKLOCKTIMEVERIFY DROP <P2PKH(refund)> ENDIF | <sig> <pubkey> 0
+//
+// CHECKLOCKTIMEVERIFY, CHECKSEQUENCEVERIFY는 chain을 보지 않고 tx의 lock_time과 Input의 sequence만 확인한다.
+// lock_time과 sequence가 실제로 지나야 block에 포함될 수 있으므로(blockchain::check_transaction) script의 조건도 함께 만족된다.
+pub const MAX_SCRIPT_OPS: usize = 201; // 하나의 script에 담을 수 있는 op 수
+pub const MAX_STACK_SIZE: usize = 1_000;
+pub const MAX_PUSH_SIZE: usize = 520; // 한 번에 stack에 올릴 수 있는 data의 크기(byte)
+pub const MAX_MULTISIG_KEYS: usize = 20;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    Push(Vec<u8>),
+    Dup,
+    Drop,
+    Equal,
+    EqualVerify,
+    Verify,
+    Sha256,
+    HashAddress, // key::pubkey_to_address. pubkey를 address로 hashing한다.
+    CheckSig,
+    CheckMultiSig,
+    CheckLockTimeVerify,
+    CheckSequenceVerify,
+    If,
+    Else,
+    EndIf,
+}
+
+// custom Error type
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScriptErr {
+    TooManyOps,
+    PushTooLarge,
+    StackOverflow,
+    StackUnderflow,
+    NonPushScriptSig, // script_sig에 push가 아닌 op가 있음
+    UnbalancedConditional,
+    InvalidNumber,
+    InvalidMultisig,
+    VerifyFailed,
+    LockTimeNotReached,
+    EvalFalse, // 실행은 끝났지만 stack의 맨 위가 false
+}
+
+// script 밖에서 오는 값. CHECKSIG는 sighash에 대한 signature를 확인하고,
+// CHECKLOCKTIMEVERIFY는 tx의 lock_time, CHECKSEQUENCEVERIFY는 실행 중인 Input의 sequence와 비교한다.
+pub struct ScriptContext<'a> {
+    pub sighash: &'a [u8],
+    pub lock_time: u64,
+    pub sequence: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Script(pub Vec<Op>);
+
+impl Script {
+    pub fn new(ops: Vec<Op>) -> Self {
+        Script(ops)
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.0
+    }
+
+    // address로 hashing되는 pubkey의 signature로만 사용할 수 있는 script
+    pub fn p2pkh(address: &str) -> Self {
+        Script(vec![
+            Op::Dup,
+            Op::HashAddress,
+            Op::Push(address.as_bytes().to_vec()),
+            Op::EqualVerify,
+            Op::CheckSig,
+        ])
+    }
+
+    pub fn p2pkh_unlock(signature: Hash, pubkey: Hash) -> Self {
+        Script(vec![Op::Push(signature), Op::Push(pubkey)])
+    }
+
+    // pubkeys 중 required개의 signature가 있어야 사용할 수 있는 script(escrow 등)
+    pub fn multisig(required: u32, pubkeys: Vec<Hash>) -> Self {
+        let total = pubkeys.len() as u64;
+        let mut ops = vec![push_num(required as u64)];
+        ops.extend(pubkeys.into_iter().map(Op::Push));
+        ops.push(push_num(total));
+        ops.push(Op::CheckMultiSig);
+        Script(ops)
+    }
+
+    // signature는 multisig script의 pubkey 순서와 같은 순서여야 한다.
+    pub fn multisig_unlock(signatures: Vec<Hash>) -> Self {
+        Script(signatures.into_iter().map(Op::Push).collect())
+    }
+
+    // Hash timelock(HTLC).
+    // sha256(preimage) == hash인 preimage를 아는 recipient가 사용하거나, lock_time 이후에는 refund가 돌려받을 수 있다.
+    // refund tx는 lock_time을 그 이상으로 설정하고 Input의 sequence를 SEQUENCE_FINAL이 아닌 값으로 설정해야 한다.
+    // payment channel, atomic swap 등에 사용된다.
+    pub fn htlc(hash: Hash, recipient: &str, lock_time: u64, refund: &str) -> Self {
+        let mut ops = vec![Op::If, Op::Sha256, Op::Push(hash), Op::EqualVerify];
+        ops.extend(Script::p2pkh(recipient).0);
+        ops.extend([Op::Else, push_num(lock_time), Op::CheckLockTimeVerify, Op::Drop]);
+        ops.extend(Script::p2pkh(refund).0);
+        ops.push(Op::EndIf);
+        Script(ops)
+    }
+
+    pub fn htlc_claim(signature: Hash, pubkey: Hash, preimage: Vec<u8>) -> Self {
+        Script(vec![Op::Push(signature), Op::Push(pubkey), Op::Push(preimage), push_num(1)])
+    }
+
+    pub fn htlc_refund(signature: Hash, pubkey: Hash) -> Self {
+        Script(vec![Op::Push(signature), Op::Push(pubkey), push_num(0)])
+    }
+
+    // P2PKH script라면 잠긴 address. address별 UTXO index와 잔액 계산에 사용된다.
+    pub fn p2pkh_address(&self) -> Option<&str> {
+        match self.0.as_slice() {
+            [Op::Dup, Op::HashAddress, Op::Push(address), Op::EqualVerify, Op::CheckSig] => {
+                std::str::from_utf8(address).ok()
+            },
+            _ => None,
+        }
+    }
+
+    pub fn is_push_only(&self) -> bool {
+        self.0.iter().all(|op| matches!(op, Op::Push(_)))
+    }
+}
+
+impl Display for Script {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (i, op) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            match op {
+                Op::Push(data) => write!(f, "<{}>", hex::encode(data))?,
+                op => write!(f, "{:?}", op)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// 숫자는 little-endian으로 push하고 뒤의 0은 생략한다(0은 빈 data).
+pub fn push_num(n: u64) -> Op {
+    let mut bytes = n.to_le_bytes().to_vec();
+    while bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    Op::Push(bytes)
+}
+
+fn to_num(bytes: &[u8]) -> Result<u64, ScriptErr> {
+    if bytes.len() > 8 || bytes.last() == Some(&0) {
+        return Err(ScriptErr::InvalidNumber)
+    }
+    let mut buf = [0u8; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn is_true(bytes: &[u8]) -> bool {
+    bytes.iter().any(|&b| b != 0)
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>, ScriptErr> {
+    stack.pop().ok_or(ScriptErr::StackUnderflow)
+}
+
+fn push(stack: &mut Vec<Vec<u8>>, item: Vec<u8>) -> Result<(), ScriptErr> {
+    if stack.len() >= MAX_STACK_SIZE {
+        return Err(ScriptErr::StackOverflow)
+    }
+    stack.push(item);
+    Ok(())
+}
+
+fn push_bool(stack: &mut Vec<Vec<u8>>, v: bool) -> Result<(), ScriptErr> {
+    push(stack, if v { vec![1] } else { vec![] })
+}
+
+// script_sig로 script_pubkey를 unlock할 수 있는지 확인한다.
+pub fn verify(script_sig: &Script, script_pubkey: &Script, context: &ScriptContext) -> Result<(), ScriptErr> {
+    if !script_sig.is_push_only() {
+        return Err(ScriptErr::NonPushScriptSig)
+    }
+    let mut stack = vec![];
+    execute(script_sig, &mut stack, context)?;
+    execute(script_pubkey, &mut stack, context)?;
+    match stack.last() {
+        Some(top) if is_true(top) => Ok(()),
+        _ => Err(ScriptErr::EvalFalse),
+    }
+}
+
+fn execute(script: &Script, stack: &mut Vec<Vec<u8>>, context: &ScriptContext) -> Result<(), ScriptErr> {
+    if script.0.len() > MAX_SCRIPT_OPS {
+        return Err(ScriptErr::TooManyOps)
+    }
+
+    // 중첩된 IF마다 해당 분기를 실행하는지. 하나라도 false라면 IF/ELSE/ENDIF 외의 op는 건너뛴다.
+    let mut branches: Vec<bool> = vec![];
+    for op in script.0.iter() {
+        let executing = branches.iter().all(|&b| b);
+        match op {
+            Op::If => {
+                let branch = executing && is_true(&pop(stack)?);
+                branches.push(branch);
+                continue
+            },
+            Op::Else => {
+                let branch = branches.pop().ok_or(ScriptErr::UnbalancedConditional)?;
+                // 바깥 분기가 실행 중일 때만 ELSE 쪽을 실행한다.
+                branches.push(branches.iter().all(|&b| b) && !branch);
+                continue
+            },
+            Op::EndIf => {
+                branches.pop().ok_or(ScriptErr::UnbalancedConditional)?;
+                continue
+            },
+            _ if !executing => continue,
+            _ => {},
+        }
+
+        match op {
+            Op::Push(data) => {
+                if data.len() > MAX_PUSH_SIZE {
+                    return Err(ScriptErr::PushTooLarge)
+                }
+                push(stack, data.clone())?;
+            },
+            Op::Dup => {
+                let top = stack.last().ok_or(ScriptErr::StackUnderflow)?.clone();
+                push(stack, top)?;
+            },
+            Op::Drop => {
+                pop(stack)?;
+            },
+            Op::Equal | Op::EqualVerify => {
+                let a = pop(stack)?;
+                let b = pop(stack)?;
+                if *op == Op::EqualVerify {
+                    if a != b {
+                        return Err(ScriptErr::VerifyFailed)
+                    }
+                } else {
+                    push_bool(stack, a == b)?;
+                }
+            },
+            Op::Verify => {
+                if !is_true(&pop(stack)?) {
+                    return Err(ScriptErr::VerifyFailed)
+                }
+            },
+            Op::Sha256 => {
+                let data = pop(stack)?;
+                push(stack, crypto_hash::digest(crypto_hash::Algorithm::SHA256, &data))?;
+            },
+            Op::HashAddress => {
+                let pubkey = pop(stack)?;
+                push(stack, key::pubkey_to_address(&pubkey).into_bytes())?;
+            },
+            Op::CheckSig => {
+                let pubkey = pop(stack)?;
+                let signature = pop(stack)?;
+                push_bool(stack, key::verify(&pubkey, context.sighash, &signature))?;
+            },
+            Op::CheckMultiSig => {
+                let total = to_num(&pop(stack)?)? as usize;
+                if total > MAX_MULTISIG_KEYS {
+                    return Err(ScriptErr::InvalidMultisig)
+                }
+                let mut pubkeys = (0..total).map(|_| pop(stack)).collect::<Result<Vec<_>, _>>()?;
+                pubkeys.reverse();
+                let required = to_num(&pop(stack)?)? as usize;
+                if required > total {
+                    return Err(ScriptErr::InvalidMultisig)
+                }
+                let mut signatures = (0..required).map(|_| pop(stack)).collect::<Result<Vec<_>, _>>()?;
+                signatures.reverse();
+
+                // signature는 pubkey와 같은 순서여야 하므로, 각 signature에 맞는 pubkey를 앞에서부터 차례로 찾는다.
+                let mut pubkeys = pubkeys.iter();
+                let valid = signatures.iter().all(|signature| {
+                    pubkeys.any(|pubkey| key::verify(pubkey, context.sighash, signature))
+                });
+                push_bool(stack, valid)?;
+            },
+            // 값은 stack에 남겨 두므로 보통 DROP이 뒤따른다(CHECKSEQUENCEVERIFY도 같다).
+            // tx의 lock_time이 값 이상이고 같은 단위(높이/시각)여야 한다. sequence가 SEQUENCE_FINAL이면 lock_time이 무시되므로 실패한다.
+            Op::CheckLockTimeVerify => {
+                let lock_time = to_num(stack.last().ok_or(ScriptErr::StackUnderflow)?)?;
+                let same_type = (lock_time < LOCKTIME_THRESHOLD) == (context.lock_time < LOCKTIME_THRESHOLD);
+                if !same_type || lock_time > context.lock_time || context.sequence == SEQUENCE_FINAL {
+                    return Err(ScriptErr::LockTimeNotReached)
+                }
+            },
+            // Input의 relative lock(sequence)이 값 이상이고 같은 단위여야 한다. 값에 DISABLE_FLAG가 있으면 아무것도 하지 않는다.
+            Op::CheckSequenceVerify => {
+                let sequence = to_num(stack.last().ok_or(ScriptErr::StackUnderflow)?)?;
+                let sequence = u32::try_from(sequence).map_err(|_| ScriptErr::InvalidNumber)?;
+                if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+                    continue
+                }
+                let lock_mask = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+                let same_type = (sequence & SEQUENCE_LOCKTIME_TYPE_FLAG) == (context.sequence & SEQUENCE_LOCKTIME_TYPE_FLAG);
+                if context.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0
+                    || !same_type
+                    || (sequence & lock_mask) > (context.sequence & lock_mask)
+                {
+                    return Err(ScriptErr::LockTimeNotReached)
+                }
+            },
+            Op::If | Op::Else | Op::EndIf => unreachable!(),
+        }
+    }
+
+    if !branches.is_empty() {
+        return Err(ScriptErr::UnbalancedConditional)
+    }
+    Ok(())
+}
@@ -0,0 +1,440 @@
+use super::*;
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition};
+use crate::blockchain::BlockValidationErr;
+use crate::codec::{self, Decode, DecodeErr, Decoder, Encode, Encoder};
+use crate::script::Script;
+use crate::transaction::OutPoint;
+use crate::utxo::{BlockUndo, Utxo};
+
+// active chain에 연결된 block은 blocks.dat에 순서대로 덧붙이고(append-only), 위치는 hash index로 찾는다.
+// reorg로 active chain에서 떨어져 나간 block도 파일과 index에 남지만, active chain이 된 적 없는 fork block은 저장하지 않는다.
+// active chain(height -> hash), block별 undo 기록, UTXO set과 tip은 key-value store(chainstate.redb)에 저장한다.
+// chainstate는 하나의 write transaction으로 갱신되므로 도중에 종료되어도 이전 tip이나 새 tip 중 하나의 상태로만 남는다.
+// UTXO set이 어느 tip까지 반영했는지와 UTXO 수도 같은 transaction에 기록해 두고, load할 때 맞지 않으면 blocks.dat에서 다시 만든다.
+const BLOCK_FILE: &str = "blocks.dat";
+const CHAINSTATE_FILE: &str = "chainstate.redb";
+
+const BLOCK_INDEX: TableDefinition<&[u8], (u64, u32)> = TableDefinition::new("block_index"); // hash -> (offset, len)
+const HEIGHTS: TableDefinition<u32, &[u8]> = TableDefinition::new("heights"); // height -> active chain의 block hash
+const UNDO: TableDefinition<u32, &[u8]> = TableDefinition::new("undo"); // height -> BlockUndo
+const UTXOS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("utxos"); // OutPoint(txid + vout) -> Utxo
+const META: TableDefinition<&str, &[u8]> = TableDefinition::new("meta");
+const MEMPOOL: TableDefinition<u64, &[u8]> = TableDefinition::new("mempool"); // mempool에 들어온 순서 -> Transaction
+
+const TIP_KEY: &str = "tip";
+const UTXO_TIP_KEY: &str = "utxo_tip"; // UTXOS table이 반영한 마지막 block의 hash
+const UTXO_COUNT_KEY: &str = "utxo_count"; // UTXOS table의 UTXO 수(u64)
+
+// custom Error type
+#[derive(Debug)]
+pub enum StorageErr {
+    Io(io::Error),
+    Db(Box<redb::Error>),
+    Corrupted(String), // 저장된 data를 읽을 수 없거나 서로 맞지 않음
+    Invalid(BlockValidationErr), // 저장된 block이 검증을 통과하지 못함
+}
+
+impl From<io::Error> for StorageErr {
+    fn from(e: io::Error) -> Self {
+        StorageErr::Io(e)
+    }
+}
+
+impl From<redb::Error> for StorageErr {
+    fn from(e: redb::Error) -> Self {
+        StorageErr::Db(Box::new(e))
+    }
+}
+
+impl From<redb::DatabaseError> for StorageErr {
+    fn from(e: redb::DatabaseError) -> Self {
+        StorageErr::Db(Box::new(e.into()))
+    }
+}
+
+impl From<redb::TransactionError> for StorageErr {
+    fn from(e: redb::TransactionError) -> Self {
+        StorageErr::Db(Box::new(e.into()))
+    }
+}
+
+impl From<redb::TableError> for StorageErr {
+    fn from(e: redb::TableError) -> Self {
+        StorageErr::Db(Box::new(e.into()))
+    }
+}
+
+impl From<redb::StorageError> for StorageErr {
+    fn from(e: redb::StorageError) -> Self {
+        StorageErr::Db(Box::new(e.into()))
+    }
+}
+
+impl From<redb::CommitError> for StorageErr {
+    fn from(e: redb::CommitError) -> Self {
+        StorageErr::Db(Box::new(e.into()))
+    }
+}
+
+impl From<DecodeErr> for StorageErr {
+    fn from(e: DecodeErr) -> Self {
+        StorageErr::Corrupted(format!("{:?}", e))
+    }
+}
+
+impl From<BlockValidationErr> for StorageErr {
+    fn from(e: BlockValidationErr) -> Self {
+        StorageErr::Invalid(e)
+    }
+}
+
+pub struct BlockStore {
+    dir: PathBuf,
+    blocks: File,
+    db: Database,
+}
+
+impl BlockStore {
+    // dir이 없으면 새로 만든다.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, StorageErr> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let blocks = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(dir.join(BLOCK_FILE))?;
+        let db = Database::create(dir.join(CHAINSTATE_FILE))?;
+
+        // read transaction은 없는 table을 열 수 없으므로 미리 만들어 둔다.
+        let write = db.begin_write()?;
+        write.open_table(BLOCK_INDEX)?;
+        write.open_table(HEIGHTS)?;
+        write.open_table(UNDO)?;
+        write.open_table(UTXOS)?;
+        write.open_table(META)?;
+        write.open_table(MEMPOOL)?;
+        write.commit()?;
+
+        Ok(BlockStore { dir, blocks, db })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // 저장된 tip의 hash. 저장된 chain이 없으면 None.
+    pub fn tip(&self) -> Result<Option<Hash>, StorageErr> {
+        let read = self.db.begin_read()?;
+        let meta = read.open_table(META)?;
+        let tip = meta.get(TIP_KEY)?.map(|tip| tip.value().to_vec());
+        Ok(tip)
+    }
+
+    // active chain의 block 수
+    pub fn height(&self) -> Result<u32, StorageErr> {
+        let read = self.db.begin_read()?;
+        Ok(read.open_table(HEIGHTS)?.len()? as u32)
+    }
+
+    pub fn get_block(&self, hash: &[u8]) -> Result<Option<Block>, StorageErr> {
+        let location = {
+            let read = self.db.begin_read()?;
+            let index = read.open_table(BLOCK_INDEX)?;
+            let location = index.get(hash)?.map(|location| location.value());
+            location
+        };
+        match location {
+            Some((offset, len)) => self.read_block(hash, offset, len).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // active chain에서 height 높이의 block
+    pub fn get_block_by_height(&self, height: u32) -> Result<Option<Block>, StorageErr> {
+        let hash = {
+            let read = self.db.begin_read()?;
+            let heights = read.open_table(HEIGHTS)?;
+            let hash = heights.get(height)?.map(|hash| hash.value().to_vec());
+            hash
+        };
+        match hash {
+            Some(hash) => self.get_block(&hash),
+            None => Ok(None),
+        }
+    }
+
+    fn read_block(&self, hash: &[u8], offset: u64, len: u32) -> Result<Block, StorageErr> {
+        let mut file = &self.blocks;
+        let mut bytes = vec![0u8; len as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut bytes)?;
+
+        let block: Block = codec::decode(&bytes)?;
+        // 파일이 손상되었다면 index의 hash와 decode한 header의 hash가 달라진다.
+        if block.hash != hash {
+            return Err(StorageErr::Corrupted(format!("block {} does not match its index", hex::encode(hash))))
+        }
+        Ok(block)
+    }
+
+    // blockchain(active chain)과 utxo_set의 현재 상태를 저장한다. 매 update_with_block 이후 호출한다.
+    // 저장된 chain과 갈라지는 높이(fork point)를 찾아 그 위의 height index와 undo 기록을 새 chain으로 교체하고,
+    // 교체된 block들(끊어진 쪽과 새로 연결된 쪽)이 건드린 UTXO만 utxo_set의 현재 값으로 갱신한다.
+    pub fn sync(&mut self, blockchain: &Blockchain, utxo_set: &UtxoSet) -> Result<(), StorageErr> {
+        // 아직 저장되지 않은 block을 파일에 먼저 덧붙인다. 아래 transaction이 commit되지 않으면 index에 없는 data로 남을 뿐이다.
+        // 저장된 block의 조상은 모두 저장되어 있으므로 tip부터 내려가다 저장된 block을 만나면 멈춘다.
+        let mut unstored = vec![];
+        {
+            let read = self.db.begin_read()?;
+            let index = read.open_table(BLOCK_INDEX)?;
+            for block in blockchain.chain.iter().rev() {
+                if index.get(block.hash.as_slice())?.is_some() {
+                    break
+                }
+                unstored.push(block);
+            }
+        }
+        let mut locations = vec![];
+        for block in unstored.into_iter().rev() {
+            locations.push((block.hash.clone(), self.append_block(block)?));
+        }
+        self.blocks.sync_data()?;
+
+        let write = self.db.begin_write()?;
+        {
+            let mut index = write.open_table(BLOCK_INDEX)?;
+            for (hash, location) in locations.iter() {
+                index.insert(hash.as_slice(), *location)?;
+            }
+
+            let mut heights = write.open_table(HEIGHTS)?;
+            let mut undo = write.open_table(UNDO)?;
+            let mut utxos = write.open_table(UTXOS)?;
+
+            // fork point: 저장된 chain과 현재 chain의 hash가 처음으로 달라지는 높이.
+            // 같은 높이의 hash가 같으면 그 아래의 조상도 모두 같으므로, 저장된 tip에서부터 내려가며 찾는다.
+            let stored_height = heights.len()? as u32;
+            let mut fork_height = stored_height.min(blockchain.chain.len() as u32);
+            while fork_height > 0 {
+                let stored = heights.get(fork_height - 1)?.map(|hash| hash.value().to_vec());
+                if stored.as_deref() == Some(blockchain.chain[fork_height as usize - 1].hash.as_slice()) {
+                    break
+                }
+                fork_height -= 1;
+            }
+
+            let mut touched: HashSet<OutPoint> = HashSet::new();
+            for height in (fork_height..stored_height).rev() {
+                if let Some(bytes) = undo.remove(height)? {
+                    let block_undo: BlockUndo = codec::decode(bytes.value())?;
+                    touched.extend(block_undo.spent.into_iter().map(|(outpoint, _)| outpoint));
+                    touched.extend(block_undo.created);
+                }
+                heights.remove(height)?;
+            }
+            for height in fork_height..blockchain.chain.len() as u32 {
+                let block = &blockchain.chain[height as usize];
+                let block_undo = &blockchain.undo[height as usize];
+                heights.insert(height, block.hash.as_slice())?;
+                undo.insert(height, codec::encode(block_undo).as_slice())?;
+                touched.extend(block_undo.spent.iter().map(|(outpoint, _)| *outpoint));
+                touched.extend(block_undo.created.iter().copied());
+            }
+
+            for outpoint in touched.iter() {
+                let key = outpoint_key(outpoint);
+                match utxo_set.get(outpoint) {
+                    Some(utxo) => { utxos.insert(key.as_slice(), codec::encode(utxo).as_slice())?; },
+                    None => { utxos.remove(key.as_slice())?; },
+                }
+            }
+
+            let mut meta = write.open_table(META)?;
+            meta.insert(TIP_KEY, blockchain.tip.as_slice())?;
+            meta.insert(UTXO_TIP_KEY, blockchain.tip.as_slice())?;
+            meta.insert(UTXO_COUNT_KEY, u64_to_bytes(&(utxo_set.len() as u64)).as_slice())?;
+        }
+        write.commit()?;
+
+        Ok(())
+    }
+
+    // 아직 block에 포함되지 않은 mempool의 tx를 들어온 순서대로 저장한다. 이전에 저장한 tx는 모두 지운다.
+    pub fn save_mempool(&self, mempool: &Mempool) -> Result<(), StorageErr> {
+        let write = self.db.begin_write()?;
+        write.delete_table(MEMPOOL)?;
+        {
+            let mut table = write.open_table(MEMPOOL)?;
+            for (i, transaction) in mempool.transactions().into_iter().enumerate() {
+                table.insert(i as u64, codec::encode(transaction).as_slice())?;
+            }
+        }
+        write.commit()?;
+        Ok(())
+    }
+
+    // 저장된 tx를 현재 chain 기준으로 다시 검증해 mempool에 넣는다. 저장한 뒤 block에 포함되었거나
+    // 더 이상 유효하지 않은 tx는 버린다. parent tx가 먼저 저장되어 있으므로 child tx도 다시 들어갈 수 있다.
+    pub fn load_mempool(&self, mempool: &mut Mempool, blockchain: &Blockchain, utxo_set: &UtxoSet) -> Result<(), StorageErr> {
+        let read = self.db.begin_read()?;
+        for entry in read.open_table(MEMPOOL)?.iter()? {
+            let (_, bytes) = entry?;
+            let transaction: Transaction = codec::decode(bytes.value())?;
+            let _ = mempool.add_transaction(transaction, blockchain, utxo_set);
+        }
+        Ok(())
+    }
+
+    fn append_block(&mut self, block: &Block) -> Result<(u64, u32), StorageErr> {
+        let bytes = codec::encode(block);
+        let offset = self.blocks.seek(SeekFrom::End(0))?;
+        self.blocks.write_all(&bytes)?;
+        Ok((offset, bytes.len() as u32))
+    }
+
+    // Startup.
+    // 저장된 active chain을 genesis부터 읽어 blockchain에 다시 연결하고, UTXO set을 utxo_set에 읽어 온다.
+    // 각 block은 hash, PoW, merkle root, 부모 block과의 연결, difficulty를 다시 검증하고(tx는 검증하지 않는다),
+    // 마지막 block이 저장된 tip과 같은지, 모든 block의 undo 기록이 있는지 확인한다.
+    // 저장된 UTXO set이 tip까지 반영하지 않았거나 UTXO 수가 다르면 active chain의 block을 다시 적용해 만들고 저장한다.
+    // blockchain과 utxo_set은 비어 있어야 한다.
+    pub fn load(&self, blockchain: &mut Blockchain, utxo_set: &mut UtxoSet) -> Result<(), StorageErr> {
+        self.load_chain(blockchain)?;
+        if !self.load_utxos(&blockchain.tip, utxo_set)? {
+            *utxo_set = UtxoSet::new();
+            for block in blockchain.chain.iter() {
+                utxo_set.apply_block(block)?;
+            }
+            self.save_utxos(&blockchain.tip, utxo_set)?;
+        }
+        Ok(())
+    }
+
+    fn load_chain(&self, blockchain: &mut Blockchain) -> Result<(), StorageErr> {
+        let read = self.db.begin_read()?;
+        let heights = read.open_table(HEIGHTS)?;
+        let undo = read.open_table(UNDO)?;
+        let index = read.open_table(BLOCK_INDEX)?;
+
+        let height = heights.len()? as u32;
+        for h in 0..height {
+            let hash = heights
+                .get(h)?
+                .map(|hash| hash.value().to_vec())
+                .ok_or_else(|| StorageErr::Corrupted(format!("missing block hash at height {}", h)))?;
+            let (offset, len) = index
+                .get(hash.as_slice())?
+                .map(|location| location.value())
+                .ok_or_else(|| StorageErr::Corrupted(format!("missing block {}", hex::encode(&hash))))?;
+            let block_undo = undo
+                .get(h)?
+                .ok_or_else(|| StorageErr::Corrupted(format!("missing undo record at height {}", h)))
+                .and_then(|bytes| Ok(codec::decode::<BlockUndo>(bytes.value())?))?;
+
+            let block = self.read_block(&hash, offset, len)?;
+            if block.index != h {
+                return Err(StorageErr::Corrupted(format!("block {} stored at height {}", block.index, h)))
+            }
+            blockchain.restore_block(block, block_undo)?;
+        }
+
+        let tip = read.open_table(META)?.get(TIP_KEY)?.map(|tip| tip.value().to_vec());
+        if tip.unwrap_or_default() != blockchain.tip {
+            return Err(StorageErr::Corrupted("stored tip is not the last block of the chain".to_owned()))
+        }
+
+        Ok(())
+    }
+
+    // 저장된 UTXO set을 utxo_set에 읽어 온다. UTXO set이 tip까지 반영되어 있고 UTXO 수도 맞으면 true
+    fn load_utxos(&self, tip: &[u8], utxo_set: &mut UtxoSet) -> Result<bool, StorageErr> {
+        let read = self.db.begin_read()?;
+        let meta = read.open_table(META)?;
+        let utxo_tip = meta.get(UTXO_TIP_KEY)?.map(|tip| tip.value().to_vec());
+        let utxo_count = meta
+            .get(UTXO_COUNT_KEY)?
+            .and_then(|count| count.value().try_into().ok().map(u64::from_le_bytes));
+        if utxo_tip.as_deref() != Some(tip) {
+            return Ok(false)
+        }
+
+        for entry in read.open_table(UTXOS)?.iter()? {
+            let (key, bytes) = entry?;
+            let mut d = Decoder::new(key.value());
+            let outpoint = OutPoint::decode_from(&mut d)?;
+            d.finish()?;
+            utxo_set.add_utxo(outpoint, codec::decode(bytes.value())?);
+        }
+        Ok(utxo_count == Some(utxo_set.len() as u64))
+    }
+
+    // 저장된 UTXO set을 utxo_set으로 모두 바꾼다.
+    fn save_utxos(&self, tip: &[u8], utxo_set: &UtxoSet) -> Result<(), StorageErr> {
+        let write = self.db.begin_write()?;
+        write.delete_table(UTXOS)?;
+        {
+            let mut utxos = write.open_table(UTXOS)?;
+            for (outpoint, utxo) in utxo_set.iter() {
+                utxos.insert(outpoint_key(outpoint).as_slice(), codec::encode(utxo).as_slice())?;
+            }
+
+            let mut meta = write.open_table(META)?;
+            meta.insert(UTXO_TIP_KEY, tip)?;
+            meta.insert(UTXO_COUNT_KEY, u64_to_bytes(&(utxo_set.len() as u64)).as_slice())?;
+        }
+        write.commit()?;
+        Ok(())
+    }
+}
+
+// UTXOS table의 key. 같은 outpoint가 항상 같은 key가 되도록 version 없이 outpoint만 encoding한다.
+fn outpoint_key(outpoint: &OutPoint) -> Vec<u8> {
+    let mut e = Encoder::new();
+    outpoint.encode_to(&mut e);
+    e.into_bytes()
+}
+
+// block은 codec의 canonical encoding을 그대로 사용하고, 저장소에서만 쓰이는 UTXO와 undo 기록의 encoding은 여기서 정의한다.
+impl Encode for Utxo {
+    fn encode_to(&self, e: &mut Encoder) {
+        e.u64(self.value);
+        self.script_pubkey().encode_to(e);
+        e.u32(self.height);
+        e.bool(self.is_coinbase);
+    }
+}
+
+impl Decode for Utxo {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        Ok(Utxo::new(d.u64()?, Script::decode_from(d)?, d.u32()?, d.bool()?))
+    }
+}
+
+impl Encode for BlockUndo {
+    fn encode_to(&self, e: &mut Encoder) {
+        e.u32(self.spent.len() as u32);
+        for (outpoint, utxo) in self.spent.iter() {
+            outpoint.encode_to(e);
+            utxo.encode_to(e);
+        }
+        e.list(&self.created);
+    }
+}
+
+impl Decode for BlockUndo {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        let spent = (0..d.u32()?)
+            .map(|_| Ok((OutPoint::decode_from(d)?, Utxo::decode_from(d)?)))
+            .collect::<Result<Vec<_>, DecodeErr>>()?;
+        let created = d.list()?;
+        Ok(BlockUndo { spent, created })
+    }
+}
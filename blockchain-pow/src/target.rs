@@ -0,0 +1,257 @@
+use std::cmp::Ordering;
+use std::ops::{Div, Not, Shl, Shr};
+
+// 256bit unsigned integer. SHA-256 hash 전체를 하나의 숫자로 보고 목표값(target)과 비교하기 위해 사용한다.
+// limb는 little-endian 순서(self.0[0]이 가장 낮은 64bit)로 저장한다.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct U256(pub [u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const ONE: U256 = U256([1, 0, 0, 0]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub fn from_u64(u: u64) -> Self {
+        U256([u, 0, 0, 0])
+    }
+
+    // hash는 little-endian으로 해석한다. hash의 가장 중요한(most significant) byte는 v[31]이다.
+    pub fn from_le_bytes(v: &[u8]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&v[i * 8..i * 8 + 8]);
+            *limb = u64::from_le_bytes(bytes);
+        }
+        U256(limbs)
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            bytes[i * 8..i * 8 + 8].copy_from_slice(&limb.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    // 값을 표현하는데 필요한 bit 수
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + (64 - self.0[i].leading_zeros());
+            }
+        }
+        0
+    }
+
+    fn bit(&self, i: u32) -> bool {
+        self.0[(i / 64) as usize] >> (i % 64) & 1 == 1
+    }
+
+    pub fn low_u64(&self) -> u64 {
+        self.0[0]
+    }
+
+    pub fn overflowing_add(self, other: U256) -> (U256, bool) {
+        let mut limbs = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = c1 || c2;
+        }
+        (U256(limbs), carry)
+    }
+
+    pub fn saturating_add(self, other: U256) -> U256 {
+        match self.overflowing_add(other) {
+            (_, true) => U256::MAX,
+            (sum, false) => sum,
+        }
+    }
+
+    fn wrapping_sub(self, other: U256) -> U256 {
+        let mut limbs = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = b1 || b2;
+        }
+        U256(limbs)
+    }
+
+    // 256bit를 넘으면 None
+    pub fn checked_mul_u64(self, other: u64) -> Option<U256> {
+        let mut limbs = [0u64; 4];
+        let mut carry = 0u128;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let product = self.0[i] as u128 * other as u128 + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+        if carry != 0 {
+            return None;
+        }
+        Some(U256(limbs))
+    }
+
+    // 256bit를 넘으면 U256::MAX
+    pub fn saturating_mul_u64(self, other: u64) -> U256 {
+        self.checked_mul_u64(other).unwrap_or(U256::MAX)
+    }
+
+    pub fn div_u64(self, other: u64) -> U256 {
+        self.div_rem_u64(other).0
+    }
+
+    // 몫과 나머지
+    pub fn div_rem_u64(self, other: u64) -> (U256, u64) {
+        let mut limbs = [0u64; 4];
+        let mut rem = 0u128;
+        for i in (0..4).rev() {
+            let cur = (rem << 64) | self.0[i] as u128;
+            limbs[i] = (cur / other as u128) as u64;
+            rem = cur % other as u128;
+        }
+        (U256(limbs), rem as u64)
+    }
+}
+
+// shift-subtract 방식의 나눗셈. work 계산처럼 가끔 호출되는 곳에서만 사용한다.
+impl Div for U256 {
+    type Output = U256;
+
+    fn div(self, other: U256) -> U256 {
+        assert!(!other.is_zero(), "U256 division by zero");
+        let mut quotient = U256::ZERO;
+        let mut rem = U256::ZERO;
+        for i in (0..self.bits()).rev() {
+            rem = rem << 1;
+            if self.bit(i) {
+                rem.0[0] |= 1;
+            }
+            if rem >= other {
+                rem = rem.wrapping_sub(other);
+                quotient.0[(i / 64) as usize] |= 1 << (i % 64);
+            }
+        }
+        quotient
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: u32) -> U256 {
+        let mut limbs = [0u64; 4];
+        let (limb_shift, bit_shift) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in limbs.iter_mut().enumerate().skip(limb_shift) {
+            *limb = self.0[i - limb_shift] << bit_shift;
+            if bit_shift > 0 && i > limb_shift {
+                *limb |= self.0[i - limb_shift - 1] >> (64 - bit_shift);
+            }
+        }
+        U256(limbs)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: u32) -> U256 {
+        let mut limbs = [0u64; 4];
+        let (limb_shift, bit_shift) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in limbs.iter_mut().enumerate().take(4usize.saturating_sub(limb_shift)) {
+            *limb = self.0[i + limb_shift] >> bit_shift;
+            if bit_shift > 0 && i + limb_shift + 1 < 4 {
+                *limb |= self.0[i + limb_shift + 1] << (64 - bit_shift);
+            }
+        }
+        U256(limbs)
+    }
+}
+
+impl Not for U256 {
+    type Output = U256;
+
+    fn not(self) -> U256 {
+        U256([!self.0[0], !self.0[1], !self.0[2], !self.0[3]])
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for i in (0..4).rev() {
+            match self.0[i].cmp(&other.0[i]) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Compact bits(nBits).
+// btc block header는 256bit target을 4byte로 압축해 저장한다. 최상위 byte는 target의 byte 길이(exponent),
+// 나머지 3byte는 target의 상위 3byte(mantissa)이다. target = mantissa * 256^(exponent - 3)
+// mantissa의 최상위 bit(0x00800000)는 부호 bit이므로, 부호가 켜진 값과 256bit를 넘는 값은 유효하지 않은 target이다.
+pub fn compact_to_target(bits: u32) -> Option<U256> {
+    let exponent = bits >> 24;
+    let mantissa = bits & 0x007fffff;
+    if bits & 0x00800000 != 0 && mantissa != 0 {
+        return None;
+    }
+
+    let target = if exponent <= 3 {
+        U256::from_u64((mantissa >> (8 * (3 - exponent))) as u64)
+    } else {
+        let target = U256::from_u64(mantissa as u64);
+        if target.bits() + 8 * (exponent - 3) > 256 {
+            return None;
+        }
+        target << (8 * (exponent - 3))
+    };
+
+    Some(target)
+}
+
+// target을 compact bits로 압축한다. 하위 byte는 버려지므로(절삭) 압축한 값은 원래 target보다 작거나 같다.
+pub fn target_to_compact(target: U256) -> u32 {
+    let mut exponent = target.bits().div_ceil(8);
+    let mut mantissa = if exponent <= 3 {
+        (target.low_u64() << (8 * (3 - exponent))) as u32
+    } else {
+        (target >> (8 * (exponent - 3))).low_u64() as u32
+    };
+
+    // mantissa의 부호 bit가 켜지면 한 byte 밀어서 exponent를 늘린다.
+    if mantissa & 0x00800000 != 0 {
+        mantissa >>= 8;
+        exponent += 1;
+    }
+
+    (exponent << 24) | mantissa
+}
+
+// target을 만족하는 hash를 찾기 위해 필요한 평균 hashing 횟수(2^256 / (target + 1)).
+// target이 작을수록(difficulty가 높을수록) block 하나의 work가 커지고, chain 선택 시 누적 work를 비교하는 데 사용된다.
+pub fn target_to_work(target: U256) -> U256 {
+    if target == U256::MAX {
+        return U256::ONE;
+    }
+    // 2^256은 U256으로 표현할 수 없으므로 (2^256 - target - 1) / (target + 1) + 1 로 계산한다.
+    (!target / target.saturating_add(U256::ONE)).saturating_add(U256::ONE)
+}
@@ -1,48 +1,204 @@
 use super::*;
-use std::collections::HashSet;
+use std::fmt::{self, Debug, Display, Formatter};
+use crate::codec::{self, Encoder};
+use crate::script::{self, Script, ScriptContext, ScriptErr};
 
+// tx hash(SHA-256)
+pub type Txid = [u8; 32];
+
+// Timelock.
+// lock_time(absolute): 0이 아니면 tx는 그 높이(또는 시각) 이후의 block에만 포함될 수 있다.
+// LOCKTIME_THRESHOLD보다 작으면 block 높이, 크거나 같으면 timestamp(ms)로 해석하고, timestamp는 block의 timestamp 대신
+// 이전 block들의 median-time-past와 비교한다(btc BIP113). 채굴자가 timestamp를 조작해 lock을 앞당길 수 없게 하기 위함.
+// 모든 Input의 sequence가 SEQUENCE_FINAL이면 lock_time은 무시된다.
+//
+// sequence(relative, btc BIP68): Input이 참조하는 UTXO가 chain에 포함된 뒤 일정 block 수(또는 시간)가 지나야 사용할 수 있다.
+// DISABLE_FLAG가 설정되면 relative lock이 없고, TYPE_FLAG가 설정되면 하위 16 bit는 SEQUENCE_LOCKTIME_GRANULARITY 단위의 시간,
+// 아니면 block 수이다.
+pub const LOCKTIME_THRESHOLD: u64 = 500_000_000;
+pub const SEQUENCE_FINAL: u32 = 0xffff_ffff;
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_ffff;
+// btc는 512초 단위를 사용하지만, 여기서는 block time(TARGET_BLOCK_TIME)이 1초이므로 1초 단위로 한다.
+pub const SEQUENCE_LOCKTIME_GRANULARITY: u128 = 1_000;
+
+// 이전 tx의 Output을 가리키는 참조. UTXO set의 key로도 사용된다.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OutPoint {
+    pub txid: Txid,
+    pub vout: u32, // tx의 outputs 중 몇 번째 Output인지
+}
+
+impl OutPoint {
+    pub fn new(txid: Txid, vout: u32) -> Self {
+        OutPoint { txid, vout }
+    }
+}
+
+// "txid:vout"
+impl Display for OutPoint {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}", hex::encode(self.txid), self.vout)
+    }
+}
+
+impl Debug for OutPoint {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "OutPoint({})", self)
+    }
+}
+
+// Output은 script_pubkey로 잠긴다(locking). 보통은 address 하나로 잠그는 P2PKH script이다.
 #[derive(Clone)]
 pub struct Output {
-    pub to_addr: Address,
     pub value: u64,
+    pub script_pubkey: Script,
+}
+
+impl Output {
+    // to_addr로 잠긴(P2PKH) Output
+    pub fn new(to_addr: &str, value: u64) -> Self {
+        Output::with_script(Script::p2pkh(to_addr), value)
+    }
+
+    pub fn with_script(script_pubkey: Script, value: u64) -> Self {
+        Output {
+            value,
+            script_pubkey,
+        }
+    }
+
+    // P2PKH Output이라면 잠긴 address
+    pub fn address(&self) -> Option<&str> {
+        self.script_pubkey.p2pkh_address()
+    }
 }
 
 impl Hashable for Output {
     fn bytes(&self) -> Vec<u8> {
-        let mut bytes = vec![];
+        codec::encode(self)
+    }
+}
+
+// Input은 이전 tx의 Output(UTXO)을 참조한다.
+// 참조하는 Output을 사용할 수 있음을 증명하기 위해, Output의 script_pubkey를 만족시키는
+// unlocking script(script_sig)를 담는다. P2PKH라면 tx digest(signature_hash)에 대한 signature와 public key.
+#[derive(Clone)]
+pub struct Input {
+    pub prev_output: Output,
+    pub outpoint: OutPoint,
+    pub sequence: u32, // relative lock. 기본값 SEQUENCE_FINAL은 lock 없음
+    pub script_sig: Script,
+}
 
-        bytes.extend(self.to_addr.as_bytes());
-        bytes.extend(&u64_to_bytes(&self.value));
+impl Input {
+    pub fn new(prev_output: Output, outpoint: OutPoint) -> Self {
+        Input {
+            prev_output,
+            outpoint,
+            sequence: SEQUENCE_FINAL,
+            script_sig: Script::default(),
+        }
+    }
+
+    // relative lock이 있다면 (시간 단위인지, 필요한 block 수 또는 시간(ms))
+    pub fn relative_lock(&self) -> Option<(bool, u128)> {
+        if self.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return None
+        }
+        let value = (self.sequence & SEQUENCE_LOCKTIME_MASK) as u128;
+        if self.sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+            Some((true, value * SEQUENCE_LOCKTIME_GRANULARITY))
+        } else {
+            Some((false, value))
+        }
+    }
+}
 
-        bytes
+impl Hashable for Input {
+    fn bytes(&self) -> Vec<u8> {
+        codec::encode(self)
     }
 }
 
 #[derive(Clone)]
 pub struct Transaction {
-    pub inputs: Vec<(Output, String)>,
+    pub inputs: Vec<Input>,
     pub outputs: Vec<Output>,
+    // coinbase tx에만 사용되는 임의의 data(btc coinbase input의 scriptSig에 해당). 일반 tx는 비어 있어야 한다.
+    // block 높이(u32)와 extra nonce(u64)를 담아 coinbase tx의 txid가 block마다 달라지게 하고,
+    // miner가 header의 nonce를 모두 소진했을 때 extra nonce를 바꿔 merkle_root를 새로 만들 수 있게 한다.
+    pub coinbase_data: Vec<u8>,
+    pub lock_time: u64, // absolute lock. 0이면 lock 없음
 }
 
 impl Transaction {
-    pub fn input_value(&self) -> u64 {
+    pub fn new(inputs: Vec<Input>, outputs: Vec<Output>) -> Self {
+        Transaction {
+            inputs,
+            outputs,
+            coinbase_data: vec![],
+            lock_time: 0,
+        }
+    }
+
+    pub fn coinbase(index: u32, outputs: Vec<Output>) -> Self {
+        let mut coinbase_data = vec![];
+        coinbase_data.extend(&u32_to_bytes(&index));
+        coinbase_data.extend(&u64_to_bytes(&0));
+
+        Transaction {
+            inputs: vec![],
+            outputs,
+            coinbase_data,
+            lock_time: 0,
+        }
+    }
+
+    pub fn set_extra_nonce(&mut self, extra_nonce: u64) {
+        self.coinbase_data.resize(12, 0);
+        self.coinbase_data[4..12].copy_from_slice(&u64_to_bytes(&extra_nonce));
+    }
+
+    // coinbase_data의 앞 4byte에 기록된 block 높이
+    pub fn coinbase_height(&self) -> Option<u32> {
+        self.coinbase_data.get(0..4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn extra_nonce(&self) -> u64 {
+        match self.coinbase_data.get(4..12) {
+            Some(bytes) => u64::from_le_bytes(bytes.try_into().unwrap()),
+            None => 0,
+        }
+    }
+
+    pub fn txid(&self) -> Txid {
+        self.hash().try_into().expect("SHA-256 digest is 32 bytes")
+    }
+
+    // 이 tx의 vout번째 Output을 가리키는 OutPoint
+    pub fn outpoint(&self, vout: u32) -> OutPoint {
+        OutPoint::new(self.txid(), vout)
+    }
+
+    // 합이 u64를 넘으면 None. 그냥 더하면 wrap된 작은 값이 검증을 통과해 coin을 만들어낼 수 있다.
+    pub fn input_value(&self) -> Option<u64> {
         self.inputs
             .iter()
-            .map(|input| input.0.value)
-            .sum()
+            .try_fold(0u64, |sum, input| sum.checked_add(input.prev_output.value))
     }
 
-    pub fn output_value(&self) -> u64 {
+    pub fn output_value(&self) -> Option<u64> {
         self.outputs
             .iter()
-            .map(|output| output.value)
-            .sum()
+            .try_fold(0u64, |sum, output| sum.checked_add(output.value))
     }
 
     // pub fn input_hashes(&self) -> HashSet<Hash> {
     //     self.inputs
     //         .iter()
-    //         .map(|input| input.0.hash())
+    //         .map(|input| input.prev_output.hash())
     //         .collect::<HashSet<Hash>>()
     // }
     //
@@ -57,27 +213,69 @@ impl Transaction {
     pub fn is_coinbase(&self) -> bool {
         self.inputs.is_empty()
     }
+
+    // height 높이의 block에 포함될 수 있는지(absolute lock). median_time_past는 그 block의 부모까지의 median-time-past.
+    pub fn is_final(&self, height: u32, median_time_past: u128) -> bool {
+        if self.lock_time == 0 {
+            return true
+        }
+        let unlocked = if self.lock_time < LOCKTIME_THRESHOLD {
+            self.lock_time < height as u64
+        } else {
+            (self.lock_time as u128) < median_time_past
+        };
+        unlocked || self.inputs.iter().all(|input| input.sequence == SEQUENCE_FINAL)
+    }
+
+    // 서명 대상이 되는 tx digest. signature는 자기 자신을 서명할 수 없으므로
+    // 모든 Input의 script_sig를 제외한 canonical encoding을 hashing한다.
+    // 참조하는 Output(script_pubkey 포함)은 포함되므로 signature는 사용하는 Output에도 묶인다.
+    pub fn signature_hash(&self) -> Hash {
+        let mut e = Encoder::new();
+        e.u8(codec::FORMAT_VERSION);
+        e.u32(self.inputs.len() as u32);
+        for input in self.inputs.iter() {
+            input.encode_unsigned_to(&mut e);
+        }
+        e.list(&self.outputs);
+        e.u64(self.lock_time);
+
+        crypto_hash::digest(crypto_hash::Algorithm::SHA256, &e.into_bytes())
+    }
+
+    // 모든 Input을 같은 key로 서명한다(P2PKH).
+    // multisig, HTLC 등 다른 script로 잠긴 Input은 signature_hash에 서명해 script_sig를 직접 채운다.
+    pub fn sign(&mut self, privatekey: &Privatekey) {
+        let sighash = self.signature_hash();
+        let pubkey = privatekey.pubkey();
+        let signature = privatekey.sign(&sighash);
+        for input in self.inputs.iter_mut() {
+            input.script_sig = Script::p2pkh_unlock(signature.clone(), pubkey.clone());
+        }
+    }
+
+    // index번째 Input만 privatekey로 서명한다(P2PKH). Input마다 소유자가 다를 때(wallet의 여러 address) 사용한다.
+    // signature_hash는 script_sig를 포함하지 않으므로 Input을 어떤 순서로 서명해도 같은 digest에 서명하게 된다.
+    pub fn sign_input(&mut self, index: usize, privatekey: &Privatekey) {
+        let signature = privatekey.sign(&self.signature_hash());
+        self.inputs[index].script_sig = Script::p2pkh_unlock(signature, privatekey.pubkey());
+    }
+
+    // Input의 script_sig로 참조하는 Output의 script_pubkey를 unlock할 수 있는지 확인한다.
+    pub fn verify_input(&self, input: &Input, script_pubkey: &Script) -> Result<(), ScriptErr> {
+        let sighash = self.signature_hash();
+        let context = ScriptContext {
+            sighash: &sighash,
+            lock_time: self.lock_time,
+            sequence: input.sequence,
+        };
+        script::verify(&input.script_sig, script_pubkey, &context)
+    }
 }
 
-// transaction 직렬화
+// transaction 직렬화. txid는 codec의 canonical encoding을 hashing한 값이다.
 impl Hashable for Transaction {
     fn bytes(&self) -> Vec<u8> {
-        let mut bytes = vec![];
-
-        bytes.extend(
-            self.inputs
-                .iter()
-                .flat_map(|input| input.0.bytes())
-                .collect::<Vec<u8>>()
-        );
-
-        bytes.extend(
-            self.outputs
-                .iter()
-                .flat_map(|output| output.bytes())
-                .collect::<Vec<u8>>()
-        );
-
-        bytes
+        codec::encode(self)
     }
 }
\ No newline at end of file
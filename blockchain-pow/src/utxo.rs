@@ -1,74 +1,229 @@
-use super::*;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use crate::blockchain::BlockValidationErr;
+use crate::Block;
+use crate::script::Script;
+use crate::transaction::{OutPoint, Output};
+use crate::coin_selection::{CoinSelector, Selection};
 
 #[derive(Debug, Clone)]
 pub struct Utxo {
     pub value: u64,
-    script_pubkey: String,
+    script_pubkey: Script, // Output을 잠근(locking) script. Input의 script_sig로 unlock해야 사용 가능.
+    pub height: u32, // Output을 만든 tx가 포함된 block의 index
+    pub is_coinbase: bool, // coinbase tx의 Output인지. coinbase Output은 maturity를 채워야 사용할 수 있다.
 }
 
-#[derive(Debug)]
+impl Utxo {
+    pub fn new(value: u64, script_pubkey: Script, height: u32, is_coinbase: bool) -> Self {
+        Utxo {
+            value,
+            script_pubkey,
+            height,
+            is_coinbase,
+        }
+    }
+
+    pub fn from_output(output: &Output, height: u32, is_coinbase: bool) -> Self {
+        Utxo::new(output.value, output.script_pubkey.clone(), height, is_coinbase)
+    }
+
+    pub fn script_pubkey(&self) -> &Script {
+        &self.script_pubkey
+    }
+
+    // P2PKH UTXO라면 잠긴 address
+    pub fn address(&self) -> Option<&str> {
+        self.script_pubkey.p2pkh_address()
+    }
+
+    pub fn to_output(&self) -> Output {
+        Output::with_script(self.script_pubkey.clone(), self.value)
+    }
+
+    // spend_height 높이의 block에서 사용할 수 있는지.
+    // coinbase Output은 만들어진 block 위로 maturity개의 block이 쌓인 뒤에야 사용할 수 있다.
+    pub fn is_mature(&self, spend_height: u32, maturity: u32) -> bool {
+        !self.is_coinbase || spend_height.saturating_sub(self.height) >= maturity
+    }
+}
+
+// block 하나를 utxo_set에 적용할 때 생성되는 undo 기록.
+// block을 chain에서 떼어낼 때(disconnect) 사용된 UTXO를 되살리고, 생성된 UTXO를 제거해
+// block을 적용하기 전의 utxo_set으로 되돌린다. reorg와 잘못된 block을 안전하게 거부하는 데 사용된다.
+#[derive(Debug, Clone, Default)]
+pub struct BlockUndo {
+    pub spent: Vec<(OutPoint, Utxo)>, // block에서 사용된 UTXO(outpoint, 사용되기 전의 값)
+    pub created: Vec<OutPoint>,       // block에서 생성된 UTXO의 outpoint
+}
+
+// utxos와 by_address는 항상 같은 UTXO를 가리켜야 하므로 직접 수정할 수 없고, insert/remove를 통해서만 변경된다.
+// by_address는 P2PKH UTXO만 담는다. multisig, HTLC 등 address 하나에 속하지 않는 UTXO는 outpoint로만 찾을 수 있다.
+#[derive(Debug, Default)]
 pub struct UtxoSet {
-    pub utxos: HashMap<String, Utxo>,
+    utxos: HashMap<OutPoint, Utxo>,
+    by_address: HashMap<String, BTreeSet<OutPoint>>, // address -> 그 address로 잠긴 UTXO의 outpoint
 }
 
 impl UtxoSet {
     pub fn new() -> Self {
         UtxoSet {
             utxos: HashMap::new(),
+            by_address: HashMap::new(),
         }
     }
 
-    pub fn add_utxo(&mut self, txid: String, output_index: usize, value: u64, script_pubkey: String) {
-        let utxo = Utxo {
-            value,
-            script_pubkey,
-        };
-        let key = format!("{}:{}", txid, output_index);
-        self.utxos.insert(key, utxo);
+    fn insert(&mut self, outpoint: OutPoint, utxo: Utxo) {
+        if let Some(address) = utxo.address() {
+            self.by_address
+                .entry(address.to_owned())
+                .or_default()
+                .insert(outpoint);
+        }
+        if let Some(replaced) = self.utxos.insert(outpoint, utxo) {
+            self.unindex(&outpoint, &replaced);
+        }
     }
 
-    pub fn spend(&mut self, txid: String, output_index: usize) -> Result<(), BlockValidationErr> {
-        let key = format!("{}:{}", txid, output_index);
-        match self.utxos.remove(&key) {
-            None => return Err(BlockValidationErr::UtxoSpentFailure),
-            _ => Ok(())
-        }
+    fn remove(&mut self, outpoint: &OutPoint) -> Option<Utxo> {
+        let utxo = self.utxos.remove(outpoint)?;
+        self.unindex(outpoint, &utxo);
+        Some(utxo)
     }
 
-    pub fn get_balance(&self) -> u64 {
-        let mut balance = 0;
-        for (_, utxo) in &self.utxos {
-            balance += utxo.value;
+    fn unindex(&mut self, outpoint: &OutPoint, utxo: &Utxo) {
+        if self.utxos.get(outpoint).is_some_and(|current| current.script_pubkey == utxo.script_pubkey) {
+            return
+        }
+        let address = match utxo.address() {
+            Some(address) => address,
+            None => return,
+        };
+        if let Some(outpoints) = self.by_address.get_mut(address) {
+            outpoints.remove(outpoint);
+            if outpoints.is_empty() {
+                self.by_address.remove(address);
+            }
         }
-        balance
     }
 
-    pub fn get_optimal_inputs(&self, target_value: u64) -> Result<Vec<(String, usize, u64, String)>, BlockValidationErr> {
-        // First, sort the UTXOs by value in descending order
-        let mut utxos: Vec<(&String, &Utxo)> = self.utxos.iter().collect();
-        utxos.sort_by(|a, b| b.1.value.cmp(&a.1.value));
+    pub fn add_utxo(&mut self, outpoint: OutPoint, utxo: Utxo) {
+        self.insert(outpoint, utxo);
+    }
 
-        // Next, iterate over the UTXOs to find the optimal inputs
-        let mut total_value = 0;
-        let mut optimal_inputs = Vec::new();
-        for (txo_id, utxo) in utxos {
-            if total_value > target_value {
-                // If we have already accumulated enough value, we can stop
-                break;
+    pub fn spend(&mut self, outpoint: &OutPoint) -> Result<Utxo, BlockValidationErr> {
+        self.remove(outpoint).ok_or(BlockValidationErr::UtxoSpentFailure)
+    }
+
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&Utxo> {
+        self.utxos.get(outpoint)
+    }
+
+    pub fn contains(&self, outpoint: &OutPoint) -> bool {
+        self.utxos.contains_key(outpoint)
+    }
+
+    pub fn len(&self) -> usize {
+        self.utxos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.utxos.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&OutPoint, &Utxo)> {
+        self.utxos.iter()
+    }
+
+    // UTXO를 하나 이상 가진 address들
+    pub fn addresses(&self) -> impl Iterator<Item = &String> {
+        self.by_address.keys()
+    }
+
+    // block의 tx를 순서대로 적용한다. 사용된 UTXO는 제거하고 생성된 output은 추가하면서 undo 기록을 남긴다.
+    // 이미 있는 outpoint를 다시 만드는 block도 거부한다. 도중에 실패하면 그때까지 적용한 부분을 되돌리고
+    // Err를 반환하므로, utxo_set은 block 전체가 적용되거나 전혀 적용되지 않은 상태로만 남는다.
+    pub fn apply_block(&mut self, block: &Block) -> Result<BlockUndo, BlockValidationErr> {
+        let mut undo = BlockUndo::default();
+
+        for transaction in block.transactions.iter() {
+            for input in transaction.inputs.iter() {
+                match self.spend(&input.outpoint) {
+                    Ok(utxo) => undo.spent.push((input.outpoint, utxo)),
+                    Err(e) => {
+                        self.undo_block(undo);
+                        return Err(e)
+                    }
+                }
+            }
+
+            let txid = transaction.txid();
+            for (vout, output) in transaction.outputs.iter().enumerate() {
+                let outpoint = OutPoint::new(txid, vout as u32);
+                // 아직 사용되지 않은 UTXO를 덮어쓰면 undo_block이 그 outpoint를 지워 원래 UTXO가 사라진다(btc BIP30).
+                if self.utxos.contains_key(&outpoint) {
+                    self.undo_block(undo);
+                    return Err(BlockValidationErr::DuplicateTransaction)
+                }
+                let utxo = Utxo::from_output(output, block.index, transaction.is_coinbase());
+                self.add_utxo(outpoint, utxo);
+                undo.created.push(outpoint);
             }
-            let val = utxo.value;
-            total_value += val;
-            let mut txo_id_and_idx = txo_id.split(":");
-            optimal_inputs.push((txo_id_and_idx.next().unwrap().to_owned(), txo_id_and_idx.next().unwrap().parse::<usize>().unwrap(), val, utxo.script_pubkey.clone()));
         }
 
-        if total_value < target_value {
-            // If we couldn't accumulate enough value, return Err
-            return Err(BlockValidationErr::InsufficientInputValue);
+        Ok(undo)
+    }
+
+    // apply_block의 역연산. 사용된 UTXO를 먼저 되살린 뒤 생성된 UTXO를 제거한다.
+    // 같은 block 안에서 생성되고 사용된 output은 되살아났다가 다시 제거되므로 순서가 중요하다.
+    pub fn undo_block(&mut self, undo: BlockUndo) {
+        for (outpoint, utxo) in undo.spent.into_iter().rev() {
+            self.insert(outpoint, utxo);
+        }
+        for outpoint in undo.created.iter().rev() {
+            self.remove(outpoint);
         }
+    }
+
+    // address가 가진 UTXO 가치의 합. address index로 찾으므로 O(address가 가진 UTXO 수)
+    // 합이 u64를 넘으면 None
+    pub fn get_balance(&self, address: &str) -> Option<u64> {
+        self.list_unspent(address)
+            .iter()
+            .try_fold(0u64, |sum, (_, utxo)| sum.checked_add(utxo.value))
+    }
+
+    // 모든 UTXO 가치의 합(총 발행량 - 소각된 fee). 합이 u64를 넘으면 None
+    pub fn total_value(&self) -> Option<u64> {
+        self.utxos.values().try_fold(0u64, |sum, utxo| sum.checked_add(utxo.value))
+    }
+
+    // address로 잠긴 UTXO들(outpoint, UTXO). outpoint 순서로 정렬되어 있다.
+    pub fn list_unspent(&self, address: &str) -> Vec<(OutPoint, &Utxo)> {
+        self.by_address
+            .get(address)
+            .map(|outpoints| {
+                outpoints.iter()
+                    .map(|outpoint| (*outpoint, &self.utxos[outpoint]))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 
-        Ok(optimal_inputs)
+    // owner가 spend_height 높이의 block에서 사용할 수 있는 UTXO 중 target_value와 fee를 채우는 조합을 selector의 strategy로 고른다.
+    // coinbase maturity를 채우지 못한 UTXO는 고르지 않는다.
+    pub fn get_optimal_inputs(
+        &self,
+        owner: &str,
+        target_value: u64,
+        spend_height: u32,
+        coinbase_maturity: u32,
+        selector: &CoinSelector,
+    ) -> Result<Selection, BlockValidationErr> {
+        let candidates = self.list_unspent(owner)
+            .into_iter()
+            .filter(|(_, utxo)| utxo.is_mature(spend_height, coinbase_maturity))
+            .map(|(outpoint, utxo)| (outpoint, utxo.clone()))
+            .collect();
+        selector.select(candidates, target_value)
     }
 }
\ No newline at end of file
@@ -0,0 +1,378 @@
+use super::*;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, io,
+    io::Write,
+    path::Path,
+};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use bip39::Mnemonic;
+use ring::{
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use crate::blockchain::BlockValidationErr;
+use crate::codec::{self, Decode, DecodeErr, Decoder, Encode, Encoder};
+use crate::coin_selection::CoinSelector;
+use crate::transaction::{Input, OutPoint, Output};
+use crate::utxo::Utxo;
+
+// HD(Hierarchical Deterministic) wallet.
+// BIP39 mnemonic(12 단어)에서 seed를 만들고, seed 하나에서 필요한 만큼 key를 유도(derive)한다.
+// mnemonic만 있으면 같은 순서로 같은 key를 다시 만들 수 있으므로 key를 따로 backup할 필요가 없다.
+// key는 Ed25519이므로 BIP32 대신 SLIP-0010 방식으로 유도한다. Ed25519는 public key만으로 자식 key를 만들 수 없어
+// 모든 단계가 hardened derivation이다.
+// path: m/44'/COIN_TYPE'/account'/chain'/index'
+//   chain 0: 받는 address(receive), chain 1: change address
+pub const PURPOSE: u32 = 44;
+pub const COIN_TYPE: u32 = 1; // 등록된 coin type이 없으므로 testnet 값을 사용한다
+pub const HARDENED: u32 = 1 << 31;
+pub const GAP_LIMIT: usize = 20; // UTXO가 있는 마지막 address 뒤로 미리 만들어 두는 address 수
+const MNEMONIC_ENTROPY_SIZE: usize = 16; // 128bit -> 12 단어
+
+const RECEIVE_CHAIN: u32 = 0;
+const CHANGE_CHAIN: u32 = 1;
+
+// custom Error type
+#[derive(Debug)]
+pub enum WalletErr {
+    InvalidMnemonic(bip39::Error),
+    InvalidAddress(String), // checksum이 맞지 않는 address
+    Io(io::Error),
+    Corrupted(DecodeErr), // wallet file을 읽을 수 없음
+    WrongPassphrase, // wallet file에 저장된 fingerprint와 passphrase로 유도한 master key가 다름
+    Transaction(BlockValidationErr), // 보낼 금액과 fee를 채울 UTXO가 없음
+}
+
+impl From<io::Error> for WalletErr {
+    fn from(e: io::Error) -> Self {
+        WalletErr::Io(e)
+    }
+}
+
+impl From<DecodeErr> for WalletErr {
+    fn from(e: DecodeErr) -> Self {
+        WalletErr::Corrupted(e)
+    }
+}
+
+impl From<BlockValidationErr> for WalletErr {
+    fn from(e: BlockValidationErr) -> Self {
+        WalletErr::Transaction(e)
+    }
+}
+
+// SLIP-0010 extended private key. key와, 자식 key를 유도할 때 함께 쓰는 chain code.
+#[derive(Clone)]
+pub struct ExtendedKey {
+    key: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    // master key = HMAC-SHA512(key = "ed25519 seed", data = seed)
+    pub fn master(seed: &[u8]) -> Self {
+        ExtendedKey::from_hmac(b"ed25519 seed", seed)
+    }
+
+    // 자식 key = HMAC-SHA512(key = chain code, data = 0x00 || key || index). index는 항상 hardened.
+    pub fn derive_child(&self, index: u32) -> Self {
+        let mut data = vec![0];
+        data.extend(self.key);
+        data.extend((index | HARDENED).to_be_bytes());
+        ExtendedKey::from_hmac(&self.chain_code, &data)
+    }
+
+    pub fn derive_path(&self, path: &[u32]) -> Self {
+        path.iter().fold(self.clone(), |key, index| key.derive_child(*index))
+    }
+
+    // 앞 32byte는 key, 뒤 32byte는 chain code
+    fn from_hmac(key: &[u8], data: &[u8]) -> Self {
+        let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA512, key), data);
+        let (key, chain_code) = tag.as_ref().split_at(32);
+        ExtendedKey {
+            key: key.try_into().unwrap(),
+            chain_code: chain_code.try_into().unwrap(),
+        }
+    }
+
+    pub fn key(&self) -> [u8; 32] {
+        self.key
+    }
+
+    pub fn chain_code(&self) -> [u8; 32] {
+        self.chain_code
+    }
+
+    pub fn privatekey(&self) -> Privatekey {
+        Privatekey::from_seed(self.key)
+    }
+}
+
+// receive 또는 change chain 하나. addresses[i]는 chain'/i' 에서 유도한 key의 address.
+struct KeyChain {
+    branch: ExtendedKey,
+    addresses: Vec<Address>,
+    used: usize, // 이미 내어준(또는 UTXO를 받은) address 수. 다음 address는 addresses[used]
+}
+
+impl KeyChain {
+    fn new(branch: ExtendedKey) -> Self {
+        KeyChain {
+            branch,
+            addresses: vec![],
+            used: 0,
+        }
+    }
+
+    // used 뒤로 GAP_LIMIT개의 address가 있도록 key를 유도한다. 새로 만든 key가 있으면 true.
+    fn fill(&mut self, keys: &mut HashMap<Address, Privatekey>) -> bool {
+        let mut extended = false;
+        while self.addresses.len() < self.used + GAP_LIMIT {
+            let key = self.branch.derive_child(self.addresses.len() as u32).privatekey();
+            self.addresses.push(key.address());
+            keys.insert(key.address(), key);
+            extended = true;
+        }
+        extended
+    }
+
+    fn next_address(&mut self, keys: &mut HashMap<Address, Privatekey>) -> Address {
+        let address = self.addresses[self.used].clone();
+        self.used += 1;
+        self.fill(keys);
+        address
+    }
+}
+
+pub struct Wallet {
+    mnemonic: Mnemonic,
+    fingerprint: [u8; 4], // master key의 public key hash 앞 4byte. passphrase를 저장하지 않고도 맞게 입력했는지 확인한다.
+    receive: KeyChain,
+    change: KeyChain,
+    keys: HashMap<Address, Privatekey>, // 유도한 모든 key(receive, change)
+    utxos: BTreeMap<OutPoint, Utxo>, // wallet의 address로 잠긴 UTXO. sync로 갱신한다.
+    pending: HashSet<OutPoint>, // wallet이 만든 tx에서 이미 사용했지만 아직 block에 포함되지 않은 UTXO
+}
+
+impl Wallet {
+    // 새로운 random mnemonic으로 wallet을 만든다. 같은 mnemonic이라도 passphrase가 다르면 다른 wallet이 된다.
+    pub fn generate(passphrase: &str) -> Self {
+        let mut entropy = [0u8; MNEMONIC_ENTROPY_SIZE];
+        SystemRandom::new().fill(&mut entropy).expect("Failed to generate random entropy");
+        let mnemonic = Mnemonic::from_entropy(&entropy).expect("Invalid mnemonic entropy size");
+        Wallet::with_mnemonic(mnemonic, passphrase)
+    }
+
+    // mnemonic(과 passphrase)으로 wallet을 복구한다. 복구한 뒤 sync하면 이전에 받은 coin을 다시 찾는다.
+    pub fn from_mnemonic(phrase: &str, passphrase: &str) -> Result<Self, WalletErr> {
+        let mnemonic = Mnemonic::parse(phrase).map_err(WalletErr::InvalidMnemonic)?;
+        Ok(Wallet::with_mnemonic(mnemonic, passphrase))
+    }
+
+    fn with_mnemonic(mnemonic: Mnemonic, passphrase: &str) -> Self {
+        let master = ExtendedKey::master(&mnemonic.to_seed(passphrase));
+        let pubkey_hash = crypto_hash::digest(crypto_hash::Algorithm::SHA256, &master.privatekey().pubkey());
+        let account = master.derive_path(&[PURPOSE, COIN_TYPE, 0]);
+
+        let mut wallet = Wallet {
+            receive: KeyChain::new(account.derive_child(RECEIVE_CHAIN)),
+            change: KeyChain::new(account.derive_child(CHANGE_CHAIN)),
+            mnemonic,
+            fingerprint: pubkey_hash[..4].try_into().unwrap(),
+            keys: HashMap::new(),
+            utxos: BTreeMap::new(),
+            pending: HashSet::new(),
+        };
+        wallet.receive.fill(&mut wallet.keys);
+        wallet.change.fill(&mut wallet.keys);
+        wallet
+    }
+
+    pub fn mnemonic(&self) -> String {
+        self.mnemonic.to_string()
+    }
+
+    pub fn fingerprint(&self) -> [u8; 4] {
+        self.fingerprint
+    }
+
+    // 아직 사용하지 않은 다음 receive address. 같은 address를 재사용하지 않도록 호출할 때마다 새 address를 내어준다.
+    pub fn new_address(&mut self) -> Address {
+        self.receive.next_address(&mut self.keys)
+    }
+
+    // 다음에 내어줄 receive address. new_address와 달리 내어준 것으로 표시하지 않으므로,
+    // 이 address로 coin을 받고 sync하기 전까지는 같은 address를 반환한다(e.g. 채굴 보상을 받을 address).
+    pub fn receive_address(&self) -> Address {
+        self.receive.addresses[self.receive.used].clone()
+    }
+
+    fn new_change_address(&mut self) -> Address {
+        self.change.next_address(&mut self.keys)
+    }
+
+    // 지금까지 내어준 receive address들
+    pub fn addresses(&self) -> &[Address] {
+        &self.receive.addresses[..self.receive.used]
+    }
+
+    pub fn owns(&self, address: &str) -> bool {
+        self.keys.contains_key(address)
+    }
+
+    // chain의 utxo_set에서 wallet의 address로 잠긴 UTXO를 다시 모은다. reorg가 있어도 utxo_set을 따르므로 따로 되돌릴 필요가 없다.
+    // UTXO를 가진 address가 미리 만들어 둔 address의 끝에 가까우면 더 유도해, 같은 mnemonic으로 다른 곳에서 받은 coin도 찾는다.
+    // utxo_set에는 사용된 output이 남지 않으므로, 복구한 wallet은 모두 사용된 address를 아직 쓰지 않은 address로 본다.
+    pub fn sync(&mut self, utxo_set: &UtxoSet) {
+        loop {
+            let mut extended = false;
+            for chain in [&mut self.receive, &mut self.change] {
+                let last_funded = chain.addresses
+                    .iter()
+                    .rposition(|address| !utxo_set.list_unspent(address).is_empty());
+                if let Some(index) = last_funded {
+                    chain.used = chain.used.max(index + 1);
+                }
+                extended |= chain.fill(&mut self.keys);
+            }
+            if !extended {
+                break
+            }
+        }
+
+        self.utxos = self.keys
+            .keys()
+            .flat_map(|address| utxo_set.list_unspent(address))
+            .map(|(outpoint, utxo)| (outpoint, utxo.clone()))
+            .collect();
+        // block에 포함되었거나(utxo_set에서 사라짐) 다른 tx에 밀려난 UTXO는 더 이상 pending이 아니다.
+        self.pending.retain(|outpoint| self.utxos.contains_key(outpoint));
+    }
+
+    // mempool의 tx가 이미 사용한 UTXO를 pending으로 표시한다. pending은 저장하지 않으므로,
+    // 다시 읽어 온 wallet이 아직 block에 포함되지 않은 tx의 UTXO를 또 사용하지 않도록 sync 뒤에 호출한다.
+    pub fn sync_mempool(&mut self, mempool: &Mempool) {
+        self.pending.extend(self.utxos.keys().filter(|outpoint| mempool.spends(outpoint)));
+    }
+
+    // 아직 사용하지 않은(pending이 아닌) UTXO
+    pub fn unspent(&self) -> impl Iterator<Item = (&OutPoint, &Utxo)> {
+        self.utxos.iter().filter(|(outpoint, _)| !self.pending.contains(outpoint))
+    }
+
+    pub fn balance(&self) -> u64 {
+        self.unspent().map(|(_, utxo)| utxo.value).sum()
+    }
+
+    // recipient에게 amount를 보내는 tx를 만들고 Input마다 소유한 key로 서명한다.
+    // 남는 금액은 새 change address로 돌려받는다. 사용한 UTXO는 다음 sync에서 block에 포함된 것이 확인될 때까지 pending으로 둔다.
+    pub fn create_transaction(
+        &mut self,
+        blockchain: &Blockchain,
+        recipient: &str,
+        amount: u64,
+        selector: &CoinSelector,
+    ) -> Result<Transaction, WalletErr> {
+        if !key::is_valid_address(recipient) {
+            return Err(WalletErr::InvalidAddress(recipient.to_owned()))
+        }
+
+        let spend_height = blockchain.chain.last().map_or(0, |tip| tip.index + 1);
+        let candidates = self.unspent()
+            .filter(|(_, utxo)| utxo.is_mature(spend_height, blockchain.coinbase_maturity))
+            .map(|(outpoint, utxo)| (*outpoint, utxo.clone()))
+            .collect();
+        let selection = selector.select(candidates, amount)?;
+
+        let inputs = selection.inputs
+            .iter()
+            .map(|(outpoint, utxo)| Input::new(utxo.to_output(), *outpoint))
+            .collect::<Vec<_>>();
+        let mut outputs = vec![Output::new(recipient, amount)];
+        if selection.change > 0 {
+            outputs.push(Output::new(&self.new_change_address(), selection.change));
+        }
+
+        let mut transaction = Transaction::new(inputs, outputs);
+        for (index, (_, utxo)) in selection.inputs.iter().enumerate() {
+            let owner = utxo.address().and_then(|address| self.keys.get(address)).expect("wallet UTXO must be locked to a wallet key");
+            transaction.sign_input(index, owner);
+        }
+
+        self.pending.extend(selection.inputs.iter().map(|(outpoint, _)| *outpoint));
+        Ok(transaction)
+    }
+
+    // mnemonic, fingerprint와 내어준 address 수만 저장한다. key와 UTXO는 load한 뒤 다시 유도하고 sync한다.
+    // passphrase는 저장하지 않으므로 load할 때 다시 받는다.
+    // mnemonic을 그대로 저장하므로 wallet file은 private key와 같이 다뤄야 한다. unix에서는 소유자만 읽고 쓸 수 있는 file(0600)로 만든다.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), WalletErr> {
+        let file = WalletFile {
+            mnemonic: self.mnemonic(),
+            fingerprint: self.fingerprint,
+            receive_used: self.receive.used as u32,
+            change_used: self.change.used as u32,
+        };
+        // 쓰는 도중 종료되어도 이전 file이 남도록 임시 file에 쓴 뒤 교체한다.
+        // 이전 실행에서 남은 임시 file의 권한을 물려받지 않도록 지우고 새로 만든다.
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        match fs::remove_file(&tmp) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {},
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut out = options.open(&tmp)?;
+        out.write_all(&codec::encode(&file))?;
+        out.sync_all()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    // passphrase가 wallet을 만들 때와 다르면 다른 key가 유도되므로 fingerprint로 확인해 거부한다.
+    pub fn load<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Self, WalletErr> {
+        let file: WalletFile = codec::decode(&fs::read(path)?)?;
+        let mut wallet = Wallet::from_mnemonic(&file.mnemonic, passphrase)?;
+        if wallet.fingerprint != file.fingerprint {
+            return Err(WalletErr::WrongPassphrase)
+        }
+        wallet.receive.used = file.receive_used as usize;
+        wallet.change.used = file.change_used as usize;
+        wallet.receive.fill(&mut wallet.keys);
+        wallet.change.fill(&mut wallet.keys);
+        Ok(wallet)
+    }
+}
+
+struct WalletFile {
+    mnemonic: String,
+    fingerprint: [u8; 4],
+    receive_used: u32,
+    change_used: u32,
+}
+
+impl Encode for WalletFile {
+    fn encode_to(&self, e: &mut Encoder) {
+        e.string(&self.mnemonic);
+        e.raw(&self.fingerprint);
+        e.u32(self.receive_used);
+        e.u32(self.change_used);
+    }
+}
+
+impl Decode for WalletFile {
+    fn decode_from(d: &mut Decoder) -> Result<Self, DecodeErr> {
+        Ok(WalletFile {
+            mnemonic: d.string()?,
+            fingerprint: d.raw(4)?.try_into().unwrap(),
+            receive_used: d.u32()?,
+            change_used: d.u32()?,
+        })
+    }
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use blockchainlib::*;
+use blockchainlib::block::{merkle_proof, merkle_root};
+use common::*;
+
+fn txids(n: u8) -> Vec<Vec<u8>> {
+    (0..n).map(|i| vec![i; 32]).collect()
+}
+
+#[test]
+fn verifies_merkle_proofs() {
+    for n in 1..=9 {
+        let hashes = txids(n);
+        let root = merkle_root(&hashes);
+        for (i, txid) in hashes.iter().enumerate() {
+            let proof = merkle_proof(&hashes, i).unwrap();
+            assert!(proof.verify(txid, &root), "{} of {}", i, n);
+            // 다른 tx나 다른 root로는 통과하지 않는다.
+            assert!(!proof.verify(&[0xff; 32], &root));
+            assert!(!proof.verify(txid, &merkle_root(&txids(n + 1))));
+        }
+        assert_eq!(merkle_proof(&hashes, n as usize), None);
+    }
+
+    // block의 tx도 header의 merkle_root만으로 확인할 수 있다.
+    let block = genesis(&Privatekey::new());
+    let proof = block.merkle_proof(0).unwrap();
+    assert!(proof.verify(&block.transactions[0].hash(), &block.header.merkle_root));
+    assert_eq!(block.merkle_proof(1), None);
+}
+
+#[test]
+fn rejects_forged_merkle_proofs() {
+    let hashes = txids(4);
+    let root = merkle_root(&hashes);
+    let proof = merkle_proof(&hashes, 1).unwrap();
+    assert_eq!(proof.branch.len(), 2);
+
+    // 위치를 바꾸면 좌우가 바뀌어 root가 달라진다.
+    let mut forged = proof.clone();
+    forged.index = 0;
+    assert!(!forged.verify(&hashes[1], &root));
+
+    // branch 길이로 표현할 수 있는 범위(2^2) 밖의 index. 하위 bit가 같아도 통과하지 않는다.
+    for index in [1 + 4, 1 + 8, 1 + (1 << 40), usize::MAX] {
+        let mut forged = proof.clone();
+        forged.index = index;
+        assert!(!forged.verify(&hashes[1], &root), "{}", index);
+    }
+
+    // sibling을 바꾸거나, 빼거나, 더하면 통과하지 않는다.
+    let mut forged = proof.clone();
+    forged.branch[0] = hashes[2].clone();
+    assert!(!forged.verify(&hashes[1], &root));
+    let mut forged = proof.clone();
+    forged.branch.pop();
+    assert!(!forged.verify(&hashes[1], &root));
+    let mut forged = proof.clone();
+    forged.branch.push(root.clone());
+    assert!(!forged.verify(&hashes[1], &root));
+
+    // 내부 노드를 leaf로 내세울 수 없다(leaf와 노드의 prefix가 다르다).
+    let node = merkle_root(&hashes[..2]);
+    let forged = MerkleProof { index: 0, branch: merkle_proof(&hashes, 0).unwrap().branch[1..].to_vec() };
+    assert!(!forged.verify(&node, &root));
+
+    // branch가 없으면 index 0만 가능하다.
+    let single = merkle_proof(&hashes[..1], 0).unwrap();
+    assert!(single.branch.is_empty());
+    assert!(single.verify(&hashes[0], &merkle_root(&hashes[..1])));
+    let forged = MerkleProof { index: 1, branch: vec![] };
+    assert!(!forged.verify(&hashes[0], &merkle_root(&hashes[..1])));
+}
@@ -0,0 +1,497 @@
+mod common;
+
+use std::collections::BTreeMap;
+use blockchainlib::*;
+use blockchainlib::block::block_work;
+use blockchainlib::target;
+use blockchainlib::blockchain::{
+    block_subsidy, BlockNode, BlockValidationErr, HeaderNode, GENESIS_BITS, MAX_FUTURE_BLOCK_TIME, MEDIAN_TIME_SPAN, RETARGET_INTERVAL, TARGET_BLOCK_TIME,
+};
+use blockchainlib::mempool::MempoolErr;
+use blockchainlib::transaction::{Input, OutPoint, SEQUENCE_FINAL, SEQUENCE_LOCKTIME_DISABLE_FLAG, SEQUENCE_LOCKTIME_TYPE_FLAG};
+use common::*;
+
+fn hashes(blocks: &[Block]) -> Vec<Vec<u8>> {
+    blocks.iter().map(|block| block.hash.clone()).collect()
+}
+
+#[test]
+fn rejects_in_block_double_spends() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let coin = (outpoint(&genesis.transactions[0], 0), 50);
+
+    // 서로 다른 tx가 같은 output을 사용
+    let block = child(&blockchain, &genesis, "miner", vec![pay(&key, coin, "x", 50), pay(&key, coin, "y", 50)]);
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::InvalidInput)));
+
+    // 한 tx의 두 Input이 같은 output을 사용
+    let mut transaction = Transaction::new(
+        vec![Input::new(output(&key.address(), 50), coin.0), Input::new(output(&key.address(), 50), coin.0)],
+        vec![output("x", 100)],
+    );
+    transaction.sign(&key);
+    let block = child(&blockchain, &genesis, "miner", vec![transaction]);
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::InvalidInput)));
+
+    // 없는 output을 사용
+    let missing = (OutPoint::new([9; 32], 0), 50);
+    let block = child(&blockchain, &genesis, "miner", vec![pay(&key, missing, "x", 50)]);
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::InvalidInput)));
+
+    // 거부된 block은 chain과 utxo_set을 바꾸지 않는다.
+    assert_eq!(blockchain.tip, genesis.hash);
+    assert_eq!(snapshot(&utxo_set), BTreeMap::from([coin]));
+
+    // 같은 block의 앞선 tx가 만든 output은 사용할 수 있지만, 두 번 사용할 수는 없다.
+    let first = pay(&key, coin, &key.address(), 50);
+    let created = (outpoint(&first, 0), 50);
+    let block = child(&blockchain, &genesis, "miner", vec![first.clone(), pay(&key, created, "x", 50), pay(&key, created, "y", 50)]);
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::InvalidInput)));
+    let spend = pay(&key, created, "x", 50);
+    let block = child(&blockchain, &genesis, "miner", vec![first, spend.clone()]);
+    blockchain.update_with_block(block.clone(), &mut utxo_set).unwrap();
+    assert_eq!(
+        snapshot(&utxo_set),
+        BTreeMap::from([(outpoint(&block.transactions[0], 0), 50), (outpoint(&spend, 0), 50)])
+    );
+}
+
+#[test]
+fn rejects_blocks_without_coinbase() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let coin = (outpoint(&genesis.transactions[0], 0), 50);
+
+    // tx가 하나도 없는 block
+    let mut block = child(&blockchain, &genesis, "miner", vec![]);
+    block.transactions.clear();
+    block.header.merkle_root = vec![0; 32];
+    block.mine();
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::InvalidCoinbaseTransaction)));
+
+    // 첫 번째 tx가 coinbase가 아닌 block
+    let mut block = child(&blockchain, &genesis, "miner", vec![]);
+    block.transactions.clear();
+    block.add_transaction(pay(&key, coin, "x", 50));
+    block.mine();
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::InvalidCoinbaseTransaction)));
+
+    assert_eq!(blockchain.tip, genesis.hash);
+    assert_eq!(snapshot(&utxo_set), BTreeMap::from([coin]));
+}
+
+#[test]
+fn disconnects_tip_with_undo_data() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let before = snapshot(&utxo_set);
+
+    // 같은 block 안에서 만들어지고 사용된 output도 되돌린다.
+    let first = pay(&key, (outpoint(&genesis.transactions[0], 0), 50), &key.address(), 50);
+    let second = pay(&key, (outpoint(&first, 0), 50), "x", 50);
+    let block = child(&blockchain, &genesis, "miner", vec![first, second]);
+    blockchain.update_with_block(block.clone(), &mut utxo_set).unwrap();
+    assert_ne!(snapshot(&utxo_set), before);
+
+    assert_eq!(blockchain.disconnect_tip(&mut utxo_set).unwrap().hash, block.hash);
+    assert_eq!(blockchain.tip, genesis.hash);
+    assert_eq!(snapshot(&utxo_set), before);
+
+    blockchain.disconnect_tip(&mut utxo_set).unwrap();
+    assert!(blockchain.tip.is_empty());
+    assert!(utxo_set.is_empty());
+    assert!(blockchain.disconnect_tip(&mut utxo_set).is_none());
+}
+
+#[test]
+fn rejects_duplicate_outpoints() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let before = snapshot(&utxo_set);
+
+    // genesis와 같은 coinbase(같은 txid)를 가진 block. 받아들이면 genesis의 UTXO를 덮어쓰게 된다.
+    let mut block = Block::new(1, genesis.header.timestamp + 1, genesis.hash.clone(), vec![], blockchain.next_bits(&genesis));
+    block.add_transaction(genesis.transactions[0].clone());
+    block.check_merkle_and_mining().unwrap();
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::InvalidCoinbaseTransaction)));
+
+    // coinbase_data의 높이는 block 높이와 같아야 한다.
+    let mut block = Block::new(1, genesis.header.timestamp + 1, genesis.hash.clone(), vec![], blockchain.next_bits(&genesis));
+    block.add_transaction(coinbase(2, "miner", 50));
+    block.check_merkle_and_mining().unwrap();
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::InvalidCoinbaseTransaction)));
+    assert_eq!(blockchain.tip, genesis.hash);
+    assert_eq!(snapshot(&utxo_set), before);
+
+    // utxo_set도 아직 사용되지 않은 outpoint를 덮어쓰지 않는다.
+    let mut block = child(&blockchain, &genesis, "miner", vec![]);
+    block.transactions[0] = genesis.transactions[0].clone();
+    assert!(matches!(utxo_set.apply_block(&block), Err(BlockValidationErr::DuplicateTransaction)));
+    assert_eq!(snapshot(&utxo_set), before);
+
+    // 높이가 다른 coinbase는 같은 Output을 가져도 txid가 다르다.
+    let block = child(&blockchain, &genesis, &key.address(), vec![]);
+    assert_ne!(block.transactions[0].hash(), genesis.transactions[0].hash());
+    blockchain.update_with_block(block, &mut utxo_set).unwrap();
+    blockchain.disconnect_tip(&mut utxo_set).unwrap();
+    assert_eq!(snapshot(&utxo_set), before);
+}
+
+#[test]
+fn reorganizes_to_heaviest_chain() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let coin = (outpoint(&genesis.transactions[0], 0), 50);
+    let before = snapshot(&utxo_set);
+
+    let a1 = child(&blockchain, &genesis, "a1", vec![pay(&key, coin, "x", 50)]);
+    blockchain.update_with_block(a1.clone(), &mut utxo_set).unwrap();
+    assert!(!snapshot(&utxo_set).contains_key(&coin.0));
+
+    // 누적 work가 같은 branch는 tree에만 남는다.
+    let b1 = child(&blockchain, &genesis, "b1", vec![]);
+    blockchain.update_with_block(b1.clone(), &mut utxo_set).unwrap();
+    assert_eq!(blockchain.tip, a1.hash);
+    assert!(blockchain.blocks.contains_key(&b1.hash));
+
+    // 더 무거워지면 reorg한다. a1의 tx는 되돌려지고 genesis의 coin은 다시 사용할 수 있게 된다.
+    let b2 = child(&blockchain, &b1, "b2", vec![]);
+    blockchain.update_with_block(b2.clone(), &mut utxo_set).unwrap();
+    assert_eq!(blockchain.tip, b2.hash);
+    assert_eq!(hashes(&blockchain.chain), hashes(&[genesis, b1.clone(), b2.clone()]));
+    // a1이 떨어져 나가고 b1, b2가 연결되었다. b1에서 b2로는 reorg 없이 연결되었다.
+    let (disconnected, connected) = blockchain.chain_changes(&a1.hash);
+    assert_eq!(hashes(&disconnected), hashes(std::slice::from_ref(&a1)));
+    assert_eq!(hashes(connected), hashes(&[b1.clone(), b2.clone()]));
+    let (disconnected, connected) = blockchain.chain_changes(&b1.hash);
+    assert!(disconnected.is_empty());
+    assert_eq!(hashes(connected), hashes(std::slice::from_ref(&b2)));
+    let mut expected = before.clone();
+    expected.insert(outpoint(&b1.transactions[0], 0), 50);
+    expected.insert(outpoint(&b2.transactions[0], 0), 50);
+    assert_eq!(snapshot(&utxo_set), expected);
+
+    blockchain.disconnect_tip(&mut utxo_set).unwrap();
+    blockchain.disconnect_tip(&mut utxo_set).unwrap();
+    assert_eq!(snapshot(&utxo_set), before);
+}
+
+#[test]
+fn rolls_back_failed_reorg() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let a1 = child(&blockchain, &genesis, "a1", vec![]);
+    blockchain.update_with_block(a1.clone(), &mut utxo_set).unwrap();
+    let before = snapshot(&utxo_set);
+
+    // b2는 없는 output을 사용하므로 b1 위에 연결할 수 없다.
+    let b1 = child(&blockchain, &genesis, "b1", vec![]);
+    blockchain.update_with_block(b1.clone(), &mut utxo_set).unwrap();
+    let missing = (OutPoint::new([9; 32], 0), 50);
+    let b2 = child(&blockchain, &b1, "b2", vec![pay(&key, missing, "x", 50)]);
+    let b3 = child(&blockchain, &b2, "b3", vec![]);
+    assert!(matches!(blockchain.update_with_block(b2.clone(), &mut utxo_set), Err(BlockValidationErr::InvalidInput)));
+
+    // b1만으로는 a1보다 무겁지 않으므로 원래 chain으로 돌아온다.
+    assert_eq!(blockchain.tip, a1.hash);
+    assert_eq!(blockchain.chain.len(), 2);
+    assert_eq!(snapshot(&utxo_set), before);
+
+    // 잘못된 block은 tree에서 제거되어 그 자손도 받을 수 없다. b1은 남아 다시 reorg 대상이 될 수 있다.
+    assert!(!blockchain.blocks.contains_key(&b2.hash));
+    assert!(!blockchain.headers.contains_key(&b2.hash));
+    assert!(matches!(blockchain.update_with_block(b3, &mut utxo_set), Err(BlockValidationErr::MismatchedPreviousHash)));
+    let b2 = child(&blockchain, &b1, "b2", vec![]);
+    blockchain.update_with_block(b2.clone(), &mut utxo_set).unwrap();
+    assert_eq!(blockchain.tip, b2.hash);
+}
+
+#[test]
+fn stays_on_heaviest_valid_prefix() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let a1 = child(&blockchain, &genesis, "a1", vec![]);
+    blockchain.update_with_block(a1.clone(), &mut utxo_set).unwrap();
+
+    // b1, b2는 유효하지만 tree에만 들어와 있고(아직 연결해 보지 않은 branch) b3는 유효하지 않다.
+    let b1 = child(&blockchain, &genesis, "b1", vec![]);
+    let b2 = child(&blockchain, &b1, "b2", vec![]);
+    let mut chain_work = blockchain.blocks[&genesis.hash].chain_work;
+    for block in [&b1, &b2] {
+        chain_work = chain_work.saturating_add(block_work(block.header.bits));
+        blockchain.headers.insert(block.hash.clone(), HeaderNode { header: block.header.clone(), height: block.index });
+        blockchain.blocks.insert(block.hash.clone(), BlockNode { block: block.clone(), chain_work });
+    }
+    let missing = (OutPoint::new([9; 32], 0), 50);
+    let b3 = child(&blockchain, &b2, "b3", vec![pay(&key, missing, "x", 50)]);
+    assert!(blockchain.update_with_block(b3.clone(), &mut utxo_set).is_err());
+
+    // b1, b2만으로도 a1보다 무거우므로 원래 chain으로 돌아가지 않는다.
+    assert_eq!(blockchain.tip, b2.hash);
+    assert_eq!(hashes(&blockchain.chain), hashes(&[genesis, b1, b2]));
+    assert!(!blockchain.blocks.contains_key(&b3.hash));
+    assert!(!snapshot(&utxo_set).contains_key(&outpoint(&a1.transactions[0], 0)));
+}
+
+// parent 위에 timestamps 시각의 block들을 차례로 연결하고 마지막 block을 반환한다.
+fn extend(blockchain: &mut Blockchain, utxo_set: &mut UtxoSet, parent: &Block, timestamps: impl IntoIterator<Item = u128>) -> Block {
+    let mut tip = parent.clone();
+    for timestamp in timestamps {
+        let block = child_at(blockchain, &tip, timestamp, &format!("miner{}", tip.index + 1), vec![]);
+        blockchain.update_with_block(block.clone(), utxo_set).unwrap();
+        tip = block;
+    }
+    tip
+}
+
+#[test]
+fn retargets_at_interval_boundaries() {
+    let (_, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let start = genesis.header.timestamp;
+    let pow_limit = target::compact_to_target(GENESIS_BITS).unwrap();
+    let target_timespan = TARGET_BLOCK_TIME * RETARGET_INTERVAL as u128;
+
+    // 첫 구간을 1ms 간격으로 채굴한다. 구간 안에서는 difficulty가 바뀌지 않는다.
+    let tip = extend(&mut blockchain, &mut utxo_set, &genesis, (1..RETARGET_INTERVAL as u128).map(|i| start + i));
+    assert!(blockchain.chain.iter().all(|block| block.header.bits == GENESIS_BITS));
+
+    // 목표보다 훨씬 빨랐지만 한 번에 MAX_ADJUSTMENT_FACTOR배까지만 어려워진다.
+    let bits = blockchain.next_bits(&tip);
+    assert_eq!(bits, target::target_to_compact(pow_limit.div_u64(4)));
+
+    // 경계의 block은 다시 계산한 difficulty를 사용해야 한다.
+    let mut stale = Block::new(tip.index + 1, start + 10, tip.hash.clone(), vec![], GENESIS_BITS);
+    stale.add_transaction(coinbase(stale.index, "miner", 50));
+    stale.check_merkle_and_mining().unwrap();
+    assert!(matches!(blockchain.update_with_block(stale, &mut utxo_set), Err(BlockValidationErr::InvalidDifficulty)));
+
+    // 두 번째 구간은 목표의 두 배가 걸렸으므로 target이 두 배가 된다(쉬워진다).
+    let first = extend(&mut blockchain, &mut utxo_set, &tip, [start + 10]);
+    let tip = extend(&mut blockchain, &mut utxo_set, &first, (11..RETARGET_INTERVAL as u128 * 2 - 1).map(|i| start + i));
+    let tip = extend(&mut blockchain, &mut utxo_set, &tip, [first.header.timestamp + 2 * target_timespan]);
+    assert!(blockchain.chain[10..].iter().all(|block| block.header.bits == bits));
+    assert_eq!(blockchain.next_bits(&tip), target::target_to_compact(target::compact_to_target(bits).unwrap().saturating_mul_u64(2)));
+
+    // 세 번째 구간은 1시간이 걸렸다. 4배까지만 쉬워지고, 그마저 GENESIS_BITS(pow limit)를 넘을 수 없다.
+    let first = extend(&mut blockchain, &mut utxo_set, &tip, [tip.header.timestamp + 1]);
+    let tip = extend(&mut blockchain, &mut utxo_set, &first, (1..RETARGET_INTERVAL as u128 - 1).map(|i| first.header.timestamp + i));
+    let tip = extend(&mut blockchain, &mut utxo_set, &tip, [first.header.timestamp + 60 * 60 * 1_000]);
+    assert_eq!(blockchain.next_bits(&tip), GENESIS_BITS);
+}
+
+#[test]
+fn rejects_duplicated_transactions() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let first = pay(&key, (outpoint(&genesis.transactions[0], 0), 50), &key.address(), 50);
+    let second = pay(&key, (outpoint(&first, 0), 50), "x", 50);
+
+    // [coinbase, first, second]와 마지막 tx를 복제한 목록은 merkle_root가 같아 header(hash)도 같다.
+    let block = child(&blockchain, &genesis, "miner", vec![first, second.clone()]);
+    let mut mutated = block.clone();
+    mutated.transactions.push(second);
+    assert!(matches!(blockchain.update_with_block(mutated, &mut utxo_set), Err(BlockValidationErr::DuplicateTransaction)));
+    assert_eq!(blockchain.tip, genesis.hash);
+
+    // 복제된 block이 거부되어도 같은 hash의 원래 block은 받을 수 있다.
+    blockchain.update_with_block(block.clone(), &mut utxo_set).unwrap();
+    assert_eq!(blockchain.tip, block.hash);
+}
+
+#[test]
+fn rejects_overflowing_values() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let coin = (outpoint(&genesis.transactions[0], 0), 50);
+
+    // output의 합이 u64를 넘어 wrap되면 subsidy(50)보다 작아 보인다.
+    let mut block = Block::new(1, genesis.header.timestamp + 1, genesis.hash.clone(), vec![], blockchain.next_bits(&genesis));
+    block.add_transaction(Transaction::coinbase(1, vec![output("miner", u64::MAX), output("miner", 51)]));
+    block.check_merkle_and_mining().unwrap();
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::InvalidCoinbaseTransaction)));
+
+    // 50을 사용해 u64::MAX + 2를 보내는 tx. wrap되면 1을 보내고 fee 49를 내는 것처럼 보인다.
+    let mut transaction = Transaction::new(
+        vec![Input::new(output(&key.address(), coin.1), coin.0)],
+        vec![output("x", u64::MAX), output("x", 2)],
+    );
+    transaction.sign(&key);
+    assert_eq!(transaction.output_value(), None);
+    let block = child(&blockchain, &genesis, "miner", vec![transaction]);
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::InsufficientInputValue)));
+
+    assert_eq!(blockchain.tip, genesis.hash);
+    assert_eq!(snapshot(&utxo_set), BTreeMap::from([coin]));
+}
+
+#[test]
+fn coinbase_collects_subsidy_and_fees() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let spend = pay(&key, (outpoint(&genesis.transactions[0], 0), 50), "x", 40);
+
+    // fee 10을 포함해 subsidy + fee까지만 가져갈 수 있다.
+    let mine = |claim: u64| {
+        let mut block = Block::new(1, genesis.header.timestamp + 1, genesis.hash.clone(), vec![], blockchain.next_bits(&genesis));
+        block.add_transaction(coinbase(1, "miner", claim));
+        block.add_transaction(spend.clone());
+        block.check_merkle_and_mining().unwrap();
+        block
+    };
+    let overpaid = mine(block_subsidy(1) + 11);
+    let block = mine(block_subsidy(1) + 10);
+    assert!(matches!(blockchain.update_with_block(overpaid, &mut utxo_set), Err(BlockValidationErr::InvalidCoinbaseTransaction)));
+    blockchain.update_with_block(block.clone(), &mut utxo_set).unwrap();
+    assert_eq!(snapshot(&utxo_set).get(&outpoint(&block.transactions[0], 0)), Some(&(block_subsidy(1) + 10)));
+}
+
+#[test]
+fn enforces_coinbase_maturity_boundary() {
+    let maturity = 3;
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(maturity);
+    let spend = pay(&key, (outpoint(&genesis.transactions[0], 0), 50), "x", 50);
+
+    // 높이 0의 coinbase는 높이 maturity - 1까지는 사용할 수 없다.
+    let tip = extend(&mut blockchain, &mut utxo_set, &genesis, (1..maturity as u128 - 1).map(|i| genesis.header.timestamp + i));
+    let block = child(&blockchain, &tip, "miner", vec![spend.clone()]);
+    assert_eq!(block.index, maturity - 1);
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::ImmatureCoinbaseSpend)));
+
+    // 높이 maturity부터 사용할 수 있다.
+    let tip = extend(&mut blockchain, &mut utxo_set, &tip, [tip.header.timestamp + 1]);
+    let block = child(&blockchain, &tip, "miner", vec![spend.clone()]);
+    assert_eq!(block.index, maturity);
+    blockchain.update_with_block(block, &mut utxo_set).unwrap();
+    assert_eq!(snapshot(&utxo_set).get(&outpoint(&spend, 0)), Some(&50));
+}
+
+// tip 다음 block에 transaction을 담을 수 있는지. mempool은 block과 같은 규칙(check_transaction)으로 검증한다.
+fn accepts(blockchain: &Blockchain, utxo_set: &UtxoSet, transaction: Transaction) -> Result<(), BlockValidationErr> {
+    match Mempool::default().add_transaction(transaction, blockchain, utxo_set) {
+        Ok(_) => Ok(()),
+        Err(MempoolErr::Invalid(e)) => Err(e),
+        Err(e) => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn enforces_lock_time_and_sequence_locks() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let start = genesis.header.timestamp;
+    let coin = (outpoint(&genesis.transactions[0], 0), 50);
+    let locked = |lock_time: u64, sequence: u32| {
+        let mut transaction = pay(&key, coin, "x", 50);
+        transaction.lock_time = lock_time;
+        transaction.inputs[0].sequence = sequence;
+        transaction.sign(&key);
+        transaction
+    };
+
+    // 높이 1. lock_time(높이)은 block 높이보다 작아야 한다. 모든 Input이 SEQUENCE_FINAL이면 lock_time은 무시된다.
+    assert!(matches!(accepts(&blockchain, &utxo_set, locked(2, 0)), Err(BlockValidationErr::NonFinalTransaction)));
+    let block = child_at(&blockchain, &genesis, start + 1_000, "miner", vec![locked(2, 0)]);
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::NonFinalTransaction)));
+    accepts(&blockchain, &utxo_set, locked(2, SEQUENCE_FINAL)).unwrap();
+    // sequence 3: coin이 포함된 block 이후 3 block. DISABLE_FLAG가 있으면 relative lock이 없다.
+    assert!(matches!(accepts(&blockchain, &utxo_set, locked(0, 3)), Err(BlockValidationErr::SequenceLockNotSatisfied)));
+    accepts(&blockchain, &utxo_set, locked(0, SEQUENCE_LOCKTIME_DISABLE_FLAG | 3)).unwrap();
+
+    // 높이 2
+    let tip = extend(&mut blockchain, &mut utxo_set, &genesis, [start + 1_000]);
+    assert!(matches!(accepts(&blockchain, &utxo_set, locked(2, 0)), Err(BlockValidationErr::NonFinalTransaction)));
+    assert!(matches!(accepts(&blockchain, &utxo_set, locked(0, 3)), Err(BlockValidationErr::SequenceLockNotSatisfied)));
+
+    // 높이 3
+    let tip = extend(&mut blockchain, &mut utxo_set, &tip, [start + 2_000]);
+    accepts(&blockchain, &utxo_set, locked(2, 0)).unwrap();
+    accepts(&blockchain, &utxo_set, locked(0, 3)).unwrap();
+    assert!(matches!(accepts(&blockchain, &utxo_set, locked(0, 4)), Err(BlockValidationErr::SequenceLockNotSatisfied)));
+
+    // 시각은 block timestamp가 아니라 median-time-past로 비교한다. coin의 기준 시각은 genesis의 median-time-past(start)다.
+    assert_eq!(blockchain.median_time_past(&tip), start + 1_000);
+    accepts(&blockchain, &utxo_set, locked(0, SEQUENCE_LOCKTIME_TYPE_FLAG | 1)).unwrap();
+    assert!(matches!(
+        accepts(&blockchain, &utxo_set, locked(0, SEQUENCE_LOCKTIME_TYPE_FLAG | 2)),
+        Err(BlockValidationErr::SequenceLockNotSatisfied)
+    ));
+    accepts(&blockchain, &utxo_set, locked(start as u64 + 999, 0)).unwrap();
+    assert!(matches!(accepts(&blockchain, &utxo_set, locked(start as u64 + 1_000, 0)), Err(BlockValidationErr::NonFinalTransaction)));
+
+    // block timestamp가 lock_time보다 늦어도 median-time-past가 지나지 않았다면 거부된다.
+    let block = child_at(&blockchain, &tip, start + 600_000, "miner", vec![locked(start as u64 + 1_000, 0)]);
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::NonFinalTransaction)));
+    let block = child_at(&blockchain, &tip, start + 600_000, "miner", vec![locked(0, SEQUENCE_LOCKTIME_TYPE_FLAG | 2)]);
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::SequenceLockNotSatisfied)));
+
+    // median-time-past가 start + 2_000이 되면 2초의 relative lock이 풀린다.
+    let tip = extend(&mut blockchain, &mut utxo_set, &tip, [start + 3_000, start + 4_000]);
+    assert_eq!(blockchain.median_time_past(&tip), start + 2_000);
+    let block = child_at(&blockchain, &tip, start + 5_000, "miner", vec![locked(0, SEQUENCE_LOCKTIME_TYPE_FLAG | 2)]);
+    blockchain.update_with_block(block, &mut utxo_set).unwrap();
+    assert_eq!(utxo_set.get_balance("x"), Some(50));
+}
+
+#[test]
+fn checks_block_timestamps() {
+    let (_, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let start = genesis.header.timestamp;
+
+    // 부모보다 이른 timestamp도 median-time-past보다 크면 받는다.
+    let tip = extend(&mut blockchain, &mut utxo_set, &genesis, [start + 10_000, start + 20_000]);
+    let earlier = extend(&mut blockchain, &mut utxo_set, &tip, [start + 15_000]);
+    // [start, +10_000, +20_000, +15_000]의 중앙값. 짝수 개라면 큰 쪽을 고른다.
+    assert_eq!(blockchain.median_time_past(&earlier), start + 15_000);
+
+    // median-time-past와 같거나 이른 timestamp는 거부된다.
+    for timestamp in [start + 15_000, start + 5_000] {
+        let block = child_at(&blockchain, &earlier, timestamp, "miner", vec![]);
+        assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::TimestampTooOld)));
+    }
+    let tip = extend(&mut blockchain, &mut utxo_set, &earlier, [start + 15_001]);
+
+    // median-time-past는 최근 MEDIAN_TIME_SPAN개 block만 본다.
+    let tip = extend(&mut blockchain, &mut utxo_set, &tip, (1..=MEDIAN_TIME_SPAN as u128).map(|i| start + 20_000 + i * 1_000));
+    assert_eq!(blockchain.median_time_past(&tip), start + 26_000);
+    let block = child_at(&blockchain, &tip, start + 26_000, "miner", vec![]);
+    assert!(matches!(blockchain.update_with_block(block, &mut utxo_set), Err(BlockValidationErr::TimestampTooOld)));
+
+    // node의 시계보다 MAX_FUTURE_BLOCK_TIME 이상 앞선 block은 거부된다.
+    let tip = extend(&mut blockchain, &mut utxo_set, &tip, [now() + MAX_FUTURE_BLOCK_TIME - 60_000]);
+    let future = child_at(&blockchain, &tip, now() + MAX_FUTURE_BLOCK_TIME + 60_000, "miner", vec![]);
+    assert!(matches!(blockchain.update_with_block(future.clone(), &mut utxo_set), Err(BlockValidationErr::TimestampTooFarInFuture)));
+    // 시간이 지나면 받을 수 있으므로 invalidate하지 않는다. 허용 오차를 늘려 시계가 따라잡은 것처럼 만든다.
+    assert!(!blockchain.blocks.contains_key(&future.hash));
+    blockchain.max_future_drift = MAX_FUTURE_BLOCK_TIME + 120_000;
+    blockchain.update_with_block(future.clone(), &mut utxo_set).unwrap();
+    assert_eq!(blockchain.tip, future.hash);
+}
+
+#[test]
+fn validates_headers_before_blocks() {
+    // source에서 retarget 경계를 넘는 chain을 채굴하고, genesis만 가진 blockchain에 header부터 추가한다.
+    let (_, mut source, mut source_utxo_set, genesis) = setup(0);
+    let start = genesis.header.timestamp;
+    let tip = extend(&mut source, &mut source_utxo_set, &genesis, (1..=RETARGET_INTERVAL as u128).map(|i| start + i));
+    assert_ne!(tip.header.bits, GENESIS_BITS);
+
+    let mut blockchain = Blockchain::with_coinbase_maturity(0);
+    let mut utxo_set = UtxoSet::new();
+    blockchain.update_with_block(genesis.clone(), &mut utxo_set).unwrap();
+    for block in source.chain[1..].iter() {
+        assert_eq!(blockchain.add_header(block.header.clone()).unwrap(), block.hash);
+    }
+    assert_eq!(blockchain.headers[&tip.hash].height, tip.index);
+    assert_eq!(blockchain.chain.len(), 1);
+
+    // 경계 다음 block은 다시 계산된 difficulty를 그대로 써야 한다.
+    let mut easy = Block::new(tip.index + 1, tip.header.timestamp + 1, tip.hash.clone(), vec![], GENESIS_BITS);
+    easy.add_transaction(coinbase(easy.index, "miner", 50));
+    easy.check_merkle_and_mining().unwrap();
+    assert!(matches!(blockchain.add_header(easy.header), Err(BlockValidationErr::InvalidDifficulty)));
+
+    let old = child_at(&source, &tip, blockchain.median_time_past(&tip), "miner", vec![]);
+    assert!(matches!(blockchain.add_header(old.header), Err(BlockValidationErr::TimestampTooOld)));
+    let future = child_at(&source, &tip, now() + MAX_FUTURE_BLOCK_TIME + 60_000, "miner", vec![]);
+    assert!(matches!(blockchain.add_header(future.header), Err(BlockValidationErr::TimestampTooFarInFuture)));
+    let unknown = child(&source, &tip, "miner", vec![]);
+    let orphan = child(&source, &unknown, "miner", vec![]);
+    assert!(matches!(blockchain.add_header(orphan.header), Err(BlockValidationErr::MismatchedPreviousHash)));
+    assert_eq!(blockchain.headers.len(), source.chain.len());
+
+    // header를 받아 둔 block은 그대로 연결된다.
+    for block in source.chain[1..].iter() {
+        blockchain.update_with_block(block.clone(), &mut utxo_set).unwrap();
+    }
+    assert_eq!(blockchain.tip, tip.hash);
+}
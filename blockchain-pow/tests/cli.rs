@@ -0,0 +1,103 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+fn data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cli-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// 채굴한 coinbase를 바로 다음 block에서 사용할 수 있도록 coinbase maturity를 1로 낮춰 실행한다.
+fn blockchain(data_dir: &Path, args: &[&str]) -> Output {
+    blockchain_with(data_dir, &["--coinbase-maturity", "1"], args)
+}
+
+fn blockchain_with(data_dir: &Path, options: &[&str], args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_blockchain"))
+        .env_remove("BLOCKCHAIN_PASSPHRASE")
+        .arg("--data-dir")
+        .arg(data_dir)
+        .args(options)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn mines_sends_and_verifies() {
+    let dir = data_dir("flow");
+    let init = stdout(&blockchain(&dir, &["init", "--wallet", "alice"]));
+    // mnemonic은 요청할 때만 출력한다.
+    let mnemonic = stdout(&blockchain(&dir, &["show-mnemonic", "alice"]));
+    assert_eq!(mnemonic.split_whitespace().count(), 12);
+    assert!(!init.contains(mnemonic.trim()));
+    stdout(&blockchain(&dir, &["mine", "--blocks", "2", "--wallet", "alice"]));
+    assert!(stdout(&blockchain(&dir, &["balance", "alice"])).contains("alice: 150"));
+
+    // block을 채굴하지 않고 bob wallet만 만든다.
+    stdout(&blockchain(&dir, &["mine", "--blocks", "0", "--wallet", "bob"]));
+    // 보낸 tx는 mempool에 저장되어 다음 실행의 mine에서 block에 포함된다.
+    stdout(&blockchain(&dir, &["send", "--from", "alice", "--to", "bob", "--amount", "70", "--fee", "1"]));
+    assert!(stdout(&blockchain(&dir, &["balance", "bob"])).contains("bob: 0"));
+    stdout(&blockchain(&dir, &["mine", "--wallet", "carol"]));
+    assert!(stdout(&blockchain(&dir, &["balance", "bob"])).contains("bob: 70"));
+    assert!(stdout(&blockchain(&dir, &["show-block", "3"])).contains("Block[3]"));
+    assert!(stdout(&blockchain(&dir, &["verify-chain"])).contains("Verified 4 blocks"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_errors_with_exit_codes() {
+    let dir = data_dir("errors");
+    assert_eq!(blockchain(&dir, &["mine"]).status.code(), Some(1));
+    stdout(&blockchain(&dir, &["init"]));
+    assert_eq!(blockchain(&dir, &["init"]).status.code(), Some(1));
+    assert_eq!(blockchain(&dir, &["send", "--from", "default", "--to", "bob", "--amount", "1"]).status.code(), Some(1));
+    assert_eq!(blockchain(&dir, &["send", "--from", "default", "--to", "default", "--amount", "1000"]).status.code(), Some(1));
+    assert_eq!(blockchain(&dir, &["show-block", "7"]).status.code(), Some(1));
+    assert_eq!(blockchain(&dir, &["send", "--from", "default", "--amount", "x"]).status.code(), Some(2));
+
+    // block file이 손상되면 검증 실패로 3을 반환한다.
+    let path = dir.join("blocks.dat");
+    let mut bytes = std::fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    std::fs::write(&path, bytes).unwrap();
+    assert_eq!(blockchain(&dir, &["verify-chain"]).status.code(), Some(3));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn enforces_coinbase_maturity_by_default() {
+    let dir = data_dir("maturity");
+    let default = |args: &[&str]| blockchain_with(&dir, &[], args);
+    stdout(&default(&["init", "--wallet", "alice"]));
+    stdout(&default(&["mine", "--blocks", "2", "--wallet", "alice"]));
+    // genesis의 coinbase는 blockchain::COINBASE_MATURITY개의 block이 쌓이기 전에는 사용할 수 없다.
+    assert_eq!(default(&["send", "--from", "alice", "--to", "alice", "--amount", "10"]).status.code(), Some(1));
+    assert!(stdout(&default(&["verify-chain"])).contains("Verified 3 blocks"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn requires_the_wallet_passphrase() {
+    let dir = data_dir("passphrase");
+    stdout(&blockchain_with(&dir, &["--passphrase", "secret"], &["init", "--wallet", "alice"]));
+    assert_eq!(blockchain(&dir, &["show-mnemonic", "alice"]).status.code(), Some(1));
+    let env = Command::new(env!("CARGO_BIN_EXE_blockchain"))
+        .env("BLOCKCHAIN_PASSPHRASE", "secret")
+        .arg("--data-dir")
+        .arg(&dir)
+        .args(["balance", "alice"])
+        .output()
+        .unwrap();
+    assert!(stdout(&env).contains("alice: 50"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
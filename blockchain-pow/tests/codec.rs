@@ -0,0 +1,128 @@
+use blockchainlib::*;
+use blockchainlib::codec::{self, DecodeErr};
+use blockchainlib::script::Script;
+use blockchainlib::transaction::{Input, OutPoint, Output};
+
+fn output(to: &str, value: u64) -> Output {
+    Output::new(to, value)
+}
+
+fn signed_transaction(key: &Privatekey) -> Transaction {
+    let mut transaction = Transaction::new(
+        vec![Input::new(output(&key.address(), 50), OutPoint::new([7; 32], 0))],
+        vec![output("bob", 20), output(&key.address(), 29)],
+    );
+    transaction.lock_time = 1_000;
+    transaction.inputs[0].sequence = 10;
+    transaction.sign(key);
+    transaction
+}
+
+fn mined_block(key: &Privatekey) -> Block {
+    let mut block = Block::new(0, now(), vec![0; 32], vec![], blockchainlib::blockchain::GENESIS_BITS);
+    block.add_transaction(Transaction::coinbase(0, vec![output(&key.address(), 50)]));
+    block.add_transaction(signed_transaction(key));
+    block.check_merkle_and_mining().unwrap();
+    block
+}
+
+#[test]
+fn output_round_trip() {
+    let original = output("alice", u64::MAX);
+    let bytes = codec::encode(&original);
+    let decoded: Output = codec::decode(&bytes).unwrap();
+
+    assert_eq!(decoded.script_pubkey, original.script_pubkey);
+    assert_eq!(decoded.value, original.value);
+    assert_eq!(codec::encode(&decoded), bytes);
+
+    let pubkeys = vec![Privatekey::new().pubkey(), Privatekey::new().pubkey()];
+    for script in [Script::multisig(1, pubkeys), Script::htlc(vec![1; 32], "alice", 10, "bob")] {
+        let original = Output::with_script(script, 7);
+        let decoded: Output = codec::decode(&codec::encode(&original)).unwrap();
+        assert_eq!(decoded.script_pubkey, original.script_pubkey);
+    }
+}
+
+#[test]
+fn transaction_round_trip() {
+    let key = Privatekey::new();
+    let original = signed_transaction(&key);
+    let bytes = codec::encode(&original);
+    let decoded: Transaction = codec::decode(&bytes).unwrap();
+
+    assert_eq!(codec::encode(&decoded), bytes);
+    assert_eq!(decoded.hash(), original.hash());
+    assert_eq!(decoded.signature_hash(), original.signature_hash());
+    let input = &decoded.inputs[0];
+    assert!(decoded.verify_input(input, &input.prev_output.script_pubkey).is_ok());
+    assert_eq!(decoded.lock_time, original.lock_time);
+    assert_eq!(input.sequence, original.inputs[0].sequence);
+
+    let coinbase = Transaction::coinbase(3, vec![output("miner", 50)]);
+    let decoded: Transaction = codec::decode(&codec::encode(&coinbase)).unwrap();
+    assert!(decoded.is_coinbase());
+    assert_eq!(decoded.coinbase_data, coinbase.coinbase_data);
+}
+
+#[test]
+fn block_round_trip() {
+    let key = Privatekey::new();
+    let original = mined_block(&key);
+    let bytes = codec::encode(&original);
+    let decoded: Block = codec::decode(&bytes).unwrap();
+
+    assert_eq!(decoded.index, original.index);
+    assert_eq!(decoded.header, original.header);
+    assert_eq!(decoded.hash, original.hash);
+    assert_eq!(decoded.tx_hashes(), original.tx_hashes());
+    assert_eq!(codec::encode(&decoded), bytes);
+    assert!(decoded.header.check_proof_of_work());
+    assert_eq!(blockchainlib::block::merkle_root(&decoded.tx_hashes()), decoded.header.merkle_root);
+}
+
+// 길이가 없던 직렬화에서는 field 경계가 달라도 이어 붙인 byte가 같아 txid가 충돌할 수 있었다.
+#[test]
+fn field_boundaries_are_unambiguous() {
+    let a = Transaction::new(vec![], vec![output("ab", 1), output("c", 2)]);
+    let b = Transaction::new(vec![], vec![output("a", 1), output("bc", 2)]);
+    assert_ne!(codec::encode(&a), codec::encode(&b));
+    assert_ne!(a.hash(), b.hash());
+
+    let mut with_data = Transaction::new(vec![], vec![output("a", 1)]);
+    with_data.coinbase_data = vec![0];
+    let without_data = Transaction::new(vec![], vec![output("a", 1)]);
+    assert_ne!(with_data.hash(), without_data.hash());
+}
+
+#[test]
+fn rejects_unknown_version() {
+    let mut bytes = codec::encode(&output("alice", 1));
+    bytes[0] = codec::FORMAT_VERSION + 1;
+    assert_eq!(
+        codec::decode::<Output>(&bytes).err(),
+        Some(DecodeErr::UnsupportedVersion(codec::FORMAT_VERSION + 1))
+    );
+}
+
+#[test]
+fn rejects_unknown_opcode() {
+    let mut bytes = codec::encode(&output("alice", 1));
+    // version(1) + value(8) + op 수(4) 다음이 첫 번째 opcode
+    bytes[13] = 0xff;
+    assert_eq!(codec::decode::<Output>(&bytes).err(), Some(DecodeErr::InvalidOpcode(0xff)));
+}
+
+#[test]
+fn rejects_truncated_and_trailing_bytes() {
+    let key = Privatekey::new();
+    let bytes = codec::encode(&mined_block(&key));
+
+    for len in 0..bytes.len() {
+        assert!(codec::decode::<Block>(&bytes[..len]).is_err(), "decoded a block truncated to {} bytes", len);
+    }
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert_eq!(codec::decode::<Block>(&trailing).err(), Some(DecodeErr::TrailingBytes));
+}
@@ -0,0 +1,109 @@
+use blockchainlib::blockchain::BlockValidationErr;
+use blockchainlib::coin_selection::{CoinSelector, Selection, Strategy};
+use blockchainlib::script::Script;
+use blockchainlib::transaction::OutPoint;
+use blockchainlib::utxo::Utxo;
+
+// i번째 value마다 txid가 [i; 32]인 outpoint를 가진 UTXO
+fn candidates(values: &[u64]) -> Vec<(OutPoint, Utxo)> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| (OutPoint::new([i as u8; 32], 0), Utxo::new(value, Script::p2pkh("addr"), 0, false)))
+        .collect()
+}
+
+// 고른 UTXO의 가치. 가치가 큰 순서로 정렬한다.
+fn values(selection: &Selection) -> Vec<u64> {
+    let mut values = selection.inputs.iter().map(|(_, utxo)| utxo.value).collect::<Vec<_>>();
+    values.sort_by(|a, b| b.cmp(a));
+    values
+}
+
+fn select(strategy: Strategy, coins: &[u64], target_value: u64) -> Result<Selection, BlockValidationErr> {
+    CoinSelector::new(strategy, 1, 1).select(candidates(coins), target_value)
+}
+
+#[test]
+fn largest_first_uses_fewest_inputs() {
+    // need = 60 + base fee 1. effective value는 49, 29, 19, 4
+    let selection = select(Strategy::LargestFirst, &[20, 50, 5, 30], 60).unwrap();
+    assert_eq!(values(&selection), vec![50, 30]);
+    assert_eq!(selection.change, 78 - 61);
+    assert_eq!(selection.fee, 1 + 2);
+    assert_eq!(selection.input_value(), Some(60 + selection.fee + selection.change));
+
+    assert!(matches!(select(Strategy::LargestFirst, &[20, 50, 5, 30], 101), Err(BlockValidationErr::InsufficientInputValue)));
+}
+
+#[test]
+fn branch_and_bound_avoids_change() {
+    // need = 41. 100 하나면 change가 생기지만 30 + 11은 정확히 맞는다.
+    let selection = select(Strategy::BranchAndBound, &[100, 31, 21, 12], 40).unwrap();
+    assert_eq!(values(&selection), vec![31, 12]);
+    assert_eq!(selection.change, 0);
+    assert_eq!(selection.fee, 1 + 2);
+
+    // 초과분이 dust보다 작다면 change 없이 fee로 낸다.
+    let selection = select(Strategy::BranchAndBound, &[100, 31, 14], 40).unwrap();
+    assert_eq!(values(&selection), vec![31, 14]);
+    assert_eq!(selection.change, 0);
+    assert_eq!(selection.fee, 45 - 40);
+}
+
+#[test]
+fn branch_and_bound_falls_back_to_knapsack() {
+    // change 없이 맞는 조합이 없고, need보다 작은 UTXO를 모두 더해도 모자라므로 need보다 큰 것 중 가장 작은 UTXO를 쓴다.
+    for strategy in [Strategy::BranchAndBound, Strategy::Knapsack] {
+        let selection = select(strategy, &[200, 100, 5], 40).unwrap();
+        assert_eq!(values(&selection), vec![100]);
+        assert_eq!(selection.change, 99 - 41);
+        assert_eq!(selection.fee, 1 + 1);
+    }
+
+    // need보다 작은 UTXO들의 합이 정확히 need라면 모두 사용한다.
+    let selection = select(Strategy::Knapsack, &[200, 21, 11, 12], 40).unwrap();
+    assert_eq!(values(&selection), vec![21, 12, 11]);
+    assert_eq!(selection.change, 0);
+}
+
+#[test]
+fn charges_a_fee_per_input() {
+    // 사용하는 데 드는 fee(5)보다 가치가 작거나 같은 UTXO는 고르지 않는다.
+    let selector = CoinSelector::new(Strategy::LargestFirst, 2, 5);
+    assert!(matches!(selector.select(candidates(&[5, 4]), 0), Err(BlockValidationErr::InsufficientInputValue)));
+
+    // need = 30 + 2. effective value는 15, 15, 15
+    let selection = selector.select(candidates(&[20, 20, 20, 5]), 30).unwrap();
+    assert_eq!(values(&selection), vec![20, 20, 20]);
+    assert_eq!(selection.change, 45 - 32);
+    assert_eq!(selection.fee, 2 + 3 * 5);
+}
+
+#[test]
+fn drops_dust_change() {
+    let mut selector = CoinSelector::new(Strategy::LargestFirst, 0, 0);
+    selector.dust_threshold = 10;
+
+    // 초과분 9는 dust이므로 fee가 된다.
+    let selection = selector.select(candidates(&[59]), 50).unwrap();
+    assert_eq!((selection.change, selection.fee), (0, 9));
+    // 초과분 10부터 change Output을 만든다.
+    let selection = selector.select(candidates(&[60]), 50).unwrap();
+    assert_eq!((selection.change, selection.fee), (10, 0));
+}
+
+#[test]
+fn rejects_overflowing_values() {
+    // need + dust_threshold가 u64를 넘는다.
+    assert!(matches!(select(Strategy::LargestFirst, &[u64::MAX], u64::MAX - 1), Err(BlockValidationErr::InsufficientInputValue)));
+
+    // 두 UTXO를 모두 써야 하지만 합이 u64를 넘어 유효한 tx를 만들 수 없다.
+    let half = u64::MAX / 2 + 10;
+    for strategy in [Strategy::LargestFirst, Strategy::BranchAndBound, Strategy::Knapsack] {
+        assert!(matches!(select(strategy, &[half, half], u64::MAX - 10), Err(BlockValidationErr::InsufficientInputValue)));
+    }
+    // 하나로 충분하다면 고를 수 있다.
+    let selection = select(Strategy::LargestFirst, &[half, half], half - 2).unwrap();
+    assert_eq!(values(&selection), vec![half]);
+}
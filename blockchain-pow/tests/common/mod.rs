@@ -0,0 +1,70 @@
+// 여러 test file이 함께 쓰는 fixture. test file마다 쓰는 함수가 달라 사용하지 않는 함수가 생긴다.
+#![allow(dead_code)]
+use std::collections::BTreeMap;
+use blockchainlib::*;
+use blockchainlib::blockchain::GENESIS_BITS;
+use blockchainlib::transaction::{Input, OutPoint, Output};
+
+// to에게 value를 지급하는 P2PKH Output
+pub fn output(to: &str, value: u64) -> Output {
+    Output::new(to, value)
+}
+
+// index 높이의 block에서 to에게 value를 지급하는 coinbase tx
+pub fn coinbase(index: u32, to: &str, value: u64) -> Transaction {
+    Transaction::coinbase(index, vec![output(to, value)])
+}
+
+// key에게 50을 지급하는 genesis block
+pub fn genesis(key: &Privatekey) -> Block {
+    let mut block = Block::new(0, now(), vec![0; 32], vec![], GENESIS_BITS);
+    block.add_transaction(coinbase(0, &key.address(), 50));
+    block.check_merkle_and_mining().unwrap();
+    block
+}
+
+// parent 위에 timestamp 시각의 block을 채굴한다. coinbase는 miner_addr에게 50을 지급한다.
+pub fn child_at(blockchain: &Blockchain, parent: &Block, timestamp: u128, miner_addr: &str, transactions: Vec<Transaction>) -> Block {
+    let index = parent.index + 1;
+    let mut block = Block::new(index, timestamp, parent.hash.clone(), vec![], blockchain.next_bits(parent));
+    block.add_transaction(coinbase(index, miner_addr, 50));
+    for transaction in transactions {
+        block.add_transaction(transaction);
+    }
+    block.check_merkle_and_mining().unwrap();
+    block
+}
+
+// 시계를 읽으면 부모와 같은 millisecond에 채굴될 수 있으므로 timestamp는 부모보다 1ms 늦게 정한다.
+// 부모의 timestamp가 median-time-past보다 이르다면 median-time-past보다 1ms 늦게 정한다.
+pub fn child(blockchain: &Blockchain, parent: &Block, miner_addr: &str, transactions: Vec<Transaction>) -> Block {
+    let timestamp = (parent.header.timestamp + 1).max(blockchain.median_time_past(parent) + 1);
+    child_at(blockchain, parent, timestamp, miner_addr, transactions)
+}
+
+// transaction의 vout번째 Output을 가리키는 OutPoint
+pub fn outpoint(transaction: &Transaction, vout: u32) -> OutPoint {
+    transaction.outpoint(vout)
+}
+
+// key로 잠긴 prev(outpoint, value)를 사용해 to에게 value를 보내는 tx
+pub fn pay(key: &Privatekey, prev: (OutPoint, u64), to: &str, value: u64) -> Transaction {
+    let mut transaction = Transaction::new(vec![Input::new(output(&key.address(), prev.1), prev.0)], vec![output(to, value)]);
+    transaction.sign(key);
+    transaction
+}
+
+// utxo_set의 모든 UTXO(outpoint -> 가치)
+pub fn snapshot(utxo_set: &UtxoSet) -> BTreeMap<OutPoint, u64> {
+    utxo_set.iter().map(|(outpoint, utxo)| (*outpoint, utxo.value)).collect()
+}
+
+// key에게 50을 지급한 genesis만 연결된 chain. coinbase_maturity가 0이면 coinbase Output을 바로 사용할 수 있다.
+pub fn setup(coinbase_maturity: u32) -> (Privatekey, Blockchain, UtxoSet, Block) {
+    let key = Privatekey::new();
+    let mut blockchain = Blockchain::with_coinbase_maturity(coinbase_maturity);
+    let mut utxo_set = UtxoSet::new();
+    let genesis = genesis(&key);
+    blockchain.update_with_block(genesis.clone(), &mut utxo_set).unwrap();
+    (key, blockchain, utxo_set, genesis)
+}
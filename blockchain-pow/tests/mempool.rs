@@ -0,0 +1,169 @@
+mod common;
+
+use blockchainlib::*;
+use blockchainlib::mempool::MempoolErr;
+use blockchainlib::transaction::{Input, OutPoint, Txid};
+use common::*;
+
+// key에게 50짜리 coin n개(genesis와 그 위 block들의 coinbase)를 지급한 chain
+fn setup_coins(n: usize) -> (Privatekey, Blockchain, UtxoSet, Vec<(OutPoint, u64)>) {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let mut coins = vec![(outpoint(&genesis.transactions[0], 0), 50)];
+    let mut tip = genesis;
+    while coins.len() < n {
+        let block = child(&blockchain, &tip, &key.address(), vec![]);
+        blockchain.update_with_block(block.clone(), &mut utxo_set).unwrap();
+        coins.push((outpoint(&block.transactions[0], 0), 50));
+        tip = block;
+    }
+    (key, blockchain, utxo_set, coins)
+}
+
+// key로 잠긴 coin을 outputs 개수만큼 나누어 key에게 보내고 fee를 낸다. (coin.1 - fee)는 outputs로 나누어 떨어져야 한다.
+fn spend(key: &Privatekey, coin: &(OutPoint, u64), outputs: usize, fee: u64) -> Transaction {
+    let value = (coin.1 - fee) / outputs as u64;
+    let mut transaction = Transaction::new(
+        vec![Input::new(output(&key.address(), coin.1), coin.0)],
+        (0..outputs).map(|_| output(&key.address(), value)).collect(),
+    );
+    transaction.sign(key);
+    transaction
+}
+
+fn size(transaction: &Transaction) -> usize {
+    transaction.bytes().len()
+}
+
+fn txids(transactions: &[Transaction]) -> Vec<Txid> {
+    transactions.iter().map(|transaction| transaction.txid()).collect()
+}
+
+#[test]
+fn selects_transactions_by_fee_rate() {
+    let (key, blockchain, utxo_set, coins) = setup_coins(4);
+    let low = spend(&key, &coins[0], 1, 2);
+    let high = spend(&key, &coins[1], 1, 20);
+    let mid = spend(&key, &coins[2], 1, 10);
+    // fee는 mid보다 많지만 크기가 커서 fee per byte는 mid보다 낮다.
+    let large = spend(&key, &coins[3], 5, 15);
+    assert!(15 * size(&mid) < 10 * size(&large));
+    assert!(15 * size(&low) > 2 * size(&large));
+
+    let mut mempool = Mempool::default();
+    for transaction in [&low, &high, &mid, &large] {
+        mempool.add_transaction(transaction.clone(), &blockchain, &utxo_set).unwrap();
+    }
+    assert_eq!(mempool.len(), 4);
+    assert!(matches!(mempool.add_transaction(high.clone(), &blockchain, &utxo_set), Err(MempoolErr::AlreadyKnown)));
+    // 같은 output을 사용하면서 fee를 더 내지 않는 tx는 교체할 수 없다.
+    assert!(matches!(mempool.add_transaction(spend(&key, &coins[0], 2, 2), &blockchain, &utxo_set), Err(MempoolErr::InsufficientFee)));
+
+    let (transactions, total_fee) = mempool.select_transactions(usize::MAX);
+    assert_eq!(txids(&transactions), txids(&[high.clone(), mid.clone(), large.clone(), low.clone()]));
+    assert_eq!(total_fee, 2 + 20 + 10 + 15);
+
+    // 크기 제한을 넘는 tx는 건너뛰고, 남은 공간에 들어가는 다음 tx를 담는다.
+    let max_block_size = size(&high) + size(&mid) + size(&low);
+    let (transactions, total_fee) = mempool.select_transactions(max_block_size);
+    assert_eq!(txids(&transactions), txids(&[high, mid, low]));
+    assert_eq!(total_fee, 20 + 10 + 2);
+    assert!(transactions.iter().map(size).sum::<usize>() <= max_block_size);
+}
+
+#[test]
+fn evicts_lowest_fee_rate_when_full() {
+    let (key, blockchain, utxo_set, coins) = setup_coins(4);
+    let low = spend(&key, &coins[0], 1, 2);
+    let mid = spend(&key, &coins[1], 1, 6);
+    let high = spend(&key, &coins[2], 1, 10);
+    let lowest = spend(&key, &coins[3], 1, 0);
+
+    let mut mempool = Mempool::new(size(&low) + size(&mid));
+    mempool.add_transaction(low.clone(), &blockchain, &utxo_set).unwrap();
+    mempool.add_transaction(mid.clone(), &blockchain, &utxo_set).unwrap();
+
+    // 가득 찬 mempool에 fee per byte가 더 높은 tx가 들어오면 가장 낮은 tx가 밀려난다.
+    mempool.add_transaction(high.clone(), &blockchain, &utxo_set).unwrap();
+    assert_eq!(mempool.len(), 2);
+    assert!(!mempool.contains(&low.txid()));
+    assert!(mempool.total_size() <= size(&low) + size(&mid));
+
+    // 들어오는 tx가 가장 낮다면 그 tx가 밀려난다.
+    assert!(matches!(mempool.add_transaction(lowest.clone(), &blockchain, &utxo_set), Err(MempoolErr::MempoolFull)));
+    assert!(!mempool.contains(&lowest.txid()));
+    assert_eq!(txids(&mempool.select_transactions(usize::MAX).0), txids(&[high.clone(), mid]));
+
+    // 밀려난 tx가 사용하던 output은 다시 사용할 수 있다.
+    mempool.remove_with_descendants(&high.txid());
+    mempool.add_transaction(spend(&key, &coins[0], 1, 4), &blockchain, &utxo_set).unwrap();
+    assert_eq!(mempool.len(), 2);
+}
+
+#[test]
+fn selects_parents_before_children() {
+    let (key, blockchain, utxo_set, coins) = setup_coins(3);
+    // fee가 없는 parent와, parent의 output을 사용하며 fee를 많이 내는 child
+    let parent = spend(&key, &coins[0], 2, 0);
+    let child = spend(&key, &(outpoint(&parent, 0), 25), 1, 15);
+    let grandchild = spend(&key, &(outpoint(&child, 0), 10), 1, 10);
+    let other = spend(&key, &coins[1], 1, 6);
+    let low = spend(&key, &coins[2], 1, 1);
+
+    let mut mempool = Mempool::default();
+    for transaction in [&grandchild, &child] {
+        // parent가 mempool에 들어오기 전에는 사용할 output이 없다.
+        assert!(mempool.add_transaction(transaction.clone(), &blockchain, &utxo_set).is_err());
+    }
+    for transaction in [&parent, &child, &grandchild, &other, &low] {
+        mempool.add_transaction(transaction.clone(), &blockchain, &utxo_set).unwrap();
+    }
+
+    // parent + child + grandchild package(fee 25)는 other(fee 6)보다 fee per byte가 높다. parent는 항상 child보다 먼저 온다.
+    assert!(25 * size(&other) > 6 * (size(&parent) + size(&child) + size(&grandchild)));
+    let (transactions, total_fee) = mempool.select_transactions(usize::MAX);
+    assert_eq!(txids(&transactions), txids(&[parent.clone(), child.clone(), grandchild.clone(), other.clone(), low.clone()]));
+    assert_eq!(total_fee, 15 + 10 + 6 + 1);
+
+    // 세 tx를 모두 담을 공간이 없다면 package를 건너뛰고 parent와 child부터 담는다.
+    // 그 뒤 grandchild의 package는 grandchild 하나로 줄어들어 다시 비교된다.
+    let max_block_size = size(&parent) + size(&child) + size(&other);
+    let (transactions, total_fee) = mempool.select_transactions(max_block_size);
+    assert_eq!(txids(&transactions), txids(&[parent.clone(), child.clone(), grandchild.clone()]));
+    assert_eq!(total_fee, 15 + 10);
+    assert!(transactions.iter().map(size).sum::<usize>() <= max_block_size);
+
+    // parent를 제거하면 parent의 output에 의존하는 자손도 함께 제거된다.
+    mempool.remove_with_descendants(&parent.txid());
+    assert!(!mempool.contains(&child.txid()) && !mempool.contains(&grandchild.txid()));
+    assert_eq!(txids(&mempool.select_transactions(usize::MAX).0), txids(&[other, low]));
+}
+
+#[test]
+fn keeps_replaced_transactions_when_the_replacement_is_evicted() {
+    let (key, blockchain, utxo_set, coins) = setup_coins(2);
+    let original = spend(&key, &coins[0], 1, 2);
+    let other = spend(&key, &coins[1], 1, 40);
+    // original보다 fee도, fee per byte도 높지만 크기가 커서 가장 낮은 fee per byte로 밀려나는 교체 tx
+    let replacement = spend(&key, &coins[0], 4, 10);
+    assert!(10 * size(&original) > 2 * size(&replacement));
+    assert!(40 * size(&replacement) > 10 * size(&other));
+
+    let mut mempool = Mempool::new(size(&original) + size(&other));
+    let original_txid = mempool.add_transaction(original.clone(), &blockchain, &utxo_set).unwrap();
+    let other_txid = mempool.add_transaction(other.clone(), &blockchain, &utxo_set).unwrap();
+    let total_size = mempool.total_size();
+
+    assert!(matches!(mempool.add_transaction(replacement.clone(), &blockchain, &utxo_set), Err(MempoolErr::MempoolFull)));
+    assert_eq!(mempool.len(), 2);
+    assert!(mempool.contains(&original_txid) && mempool.contains(&other_txid));
+    assert!(!mempool.contains(&replacement.txid()));
+    assert_eq!(mempool.total_size(), total_size);
+
+    // 공간이 있다면 교체된다.
+    let mut mempool = Mempool::new(size(&replacement) + size(&other));
+    mempool.add_transaction(original, &blockchain, &utxo_set).unwrap();
+    mempool.add_transaction(other, &blockchain, &utxo_set).unwrap();
+    mempool.add_transaction(replacement.clone(), &blockchain, &utxo_set).unwrap();
+    assert_eq!(mempool.len(), 2);
+    assert!(!mempool.contains(&original_txid) && mempool.contains(&replacement.txid()));
+}
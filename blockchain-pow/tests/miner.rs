@@ -0,0 +1,83 @@
+mod common;
+
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use blockchainlib::*;
+use blockchainlib::block::{self, check_difficulty};
+use common::*;
+
+// target이 0이라 어떤 nonce로도 채굴할 수 없는 block
+fn unminable() -> Block {
+    let mut block = Block::new(1, 0, vec![0; 32], vec![], 0);
+    block.add_transaction(coinbase(1, "miner", 50));
+    block
+}
+
+#[test]
+fn cancels_a_running_search() {
+    let miner = Miner::new(2);
+    let handle = miner.cancel_handle();
+
+    let worker = thread::spawn(move || {
+        let mut block = unminable();
+        let found = miner.mine(&mut block);
+        (miner, found)
+    });
+    thread::sleep(Duration::from_millis(50));
+    handle.store(true, Ordering::Relaxed);
+    let (miner, found) = worker.join().unwrap();
+    assert!(!found);
+    assert!(miner.is_cancelled());
+
+    // cancel된 miner는 reset 전까지 바로 포기한다.
+    let mut block = genesis(&Privatekey::new());
+    assert!(!miner.mine(&mut block));
+}
+
+#[test]
+fn resets_for_a_new_tip() {
+    let miner = Miner::new(2);
+    miner.cancel();
+    let mut stale = unminable();
+    assert!(!miner.mine(&mut stale));
+
+    // 새로운 tip이 도착하면 reset하고 그 위의 block을 채굴한다.
+    let (_, blockchain, _, genesis) = setup(0);
+    let mut block = child(&blockchain, &genesis, "miner", vec![]);
+    block.header.nonce = 0;
+    block.hash = vec![];
+    miner.reset();
+    assert!(!miner.is_cancelled());
+    assert!(miner.mine(&mut block));
+    assert_eq!(block.hash, block.header.hash());
+    assert!(check_difficulty(&block.hash, block.header.bits));
+}
+
+#[test]
+fn rolls_over_the_extra_nonce() {
+    // target = 2^255. header 하나가 nonce 0으로 채굴될 확률은 1/2이다.
+    let bits = target::target_to_compact(U256::ONE << 255);
+    let miner = Miner::new(1).with_max_nonce(0);
+
+    // extra nonce 0, nonce 0으로는 target을 만족하지 못하는 block을 고른다.
+    let mut block = (0..)
+        .map(|timestamp| {
+            let mut block = Block::new(1, timestamp, vec![0; 32], vec![], bits);
+            block.add_transaction(coinbase(1, "miner", 50));
+            block
+        })
+        .find(|block| !check_difficulty(&block.header.hash(), bits))
+        .unwrap();
+    let merkle_root = block.header.merkle_root.clone();
+
+    assert!(miner.mine(&mut block));
+    assert_eq!(block.header.nonce, 0);
+    assert!(block.extra_nonce() > 0);
+    assert_eq!(block.transactions[0].coinbase_height(), Some(1));
+    assert_ne!(block.header.merkle_root, merkle_root);
+    let tx_hashes = block.transactions.iter().map(|tx| tx.hash()).collect::<Vec<_>>();
+    assert_eq!(block.header.merkle_root, block::merkle_root(&tx_hashes));
+    assert_eq!(block.hash, block.header.hash());
+    assert!(check_difficulty(&block.hash, bits));
+}
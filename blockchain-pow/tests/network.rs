@@ -0,0 +1,143 @@
+mod common;
+
+use std::{
+    net::{SocketAddr, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+use blockchainlib::*;
+use blockchainlib::coin_selection::{CoinSelector, Strategy};
+use blockchainlib::network::{read_message, write_message, Message, Node, Version, PROTOCOL_VERSION};
+use common::*;
+
+fn spawn_node() -> (Node, SocketAddr) {
+    let node = Node::new(Blockchain::with_coinbase_maturity(1), UtxoSet::new(), None, Miner::new(1));
+    let addr = node.listen("127.0.0.1:0").unwrap();
+    (node, addr)
+}
+
+fn mine(node: &Node, miner_addr: &str) -> Block {
+    loop {
+        if let Some(block) = node.mine_block(miner_addr.to_owned()) {
+            return block
+        }
+    }
+}
+
+fn wait_until(what: &str, condition: impl Fn() -> bool) {
+    let started = Instant::now();
+    while !condition() {
+        assert!(started.elapsed() < Duration::from_secs(30), "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn chain_len(node: &Node) -> usize {
+    node.state().blockchain.chain.len()
+}
+
+#[test]
+fn nodes_sync_and_converge() {
+    let key = Privatekey::new();
+    let (a, a_addr) = spawn_node();
+    a.submit_block(genesis(&key)).unwrap();
+    for _ in 0..3 {
+        mine(&a, "a");
+    }
+
+    // b는 a에게서, c는 b를 거쳐 chain을 받아 온다.
+    let (b, b_addr) = spawn_node();
+    let (c, _) = spawn_node();
+    b.connect(a_addr).unwrap();
+    c.connect(b_addr).unwrap();
+    wait_until("initial sync", || b.tip() == a.tip() && c.tip() == a.tip());
+    assert_eq!(chain_len(&c), 4);
+
+    // 세 node가 동시에 채굴한다. 같은 높이의 block이 경쟁하면 잠시 tip이 갈라질 수 있다.
+    let nodes = [a.clone(), b.clone(), c.clone()];
+    let miners = nodes
+        .iter()
+        .zip(["a", "b", "c"])
+        .map(|(node, miner_addr)| {
+            let node = node.clone();
+            thread::spawn(move || {
+                while chain_len(&node) < 12 {
+                    node.mine_block(miner_addr.to_owned());
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for miner in miners {
+        miner.join().unwrap();
+    }
+
+    // 모든 block이 전달된 뒤 한 node가 block을 하나 더 채굴하면 그 branch가 가장 무거워지므로 모두 같은 tip으로 모인다.
+    wait_until("equal heights", || nodes.iter().all(|node| chain_len(node) == chain_len(&a)));
+    mine(&a, "a");
+    wait_until("convergence", || nodes.iter().all(|node| node.tip() == a.tip()));
+
+    let chain_hashes = |node: &Node| node.state().blockchain.chain.iter().map(|block| block.hash.clone()).collect::<Vec<_>>();
+    let total_value = a.state().utxo_set.total_value();
+    for node in nodes.iter() {
+        assert_eq!(chain_hashes(node), chain_hashes(&a));
+        assert_eq!(node.state().utxo_set.total_value(), total_value);
+    }
+}
+
+#[test]
+fn transactions_are_relayed_and_mined() {
+    let key = Privatekey::new();
+    let (a, a_addr) = spawn_node();
+    a.submit_block(genesis(&key)).unwrap();
+
+    let (b, _) = spawn_node();
+    b.connect(a_addr).unwrap();
+    wait_until("initial sync", || b.tip() == a.tip());
+
+    let selector = CoinSelector::new(Strategy::LargestFirst, 1, 0);
+    let transaction = {
+        let state = b.state();
+        state.blockchain.create_transaction(&key, "bob".to_owned(), 20, &selector, &state.utxo_set).unwrap()
+    };
+    let txid = b.submit_transaction(transaction).unwrap();
+    wait_until("tx relay", || a.state().mempool.contains(&txid));
+
+    let block = mine(&a, "a");
+    assert!(block.transactions.iter().any(|transaction| transaction.txid() == txid));
+    wait_until("block relay", || b.tip() == a.tip());
+
+    let state = b.state();
+    assert_eq!(state.utxo_set.get_balance("bob"), Some(20));
+    assert!(state.mempool.is_empty());
+}
+
+#[test]
+fn refuses_self_connection() {
+    let (a, a_addr) = spawn_node();
+    a.connect(a_addr).unwrap();
+    wait_until("disconnect", || a.peers().is_empty());
+}
+
+#[test]
+fn disconnects_peer_sending_invalid_headers() {
+    let key = Privatekey::new();
+    let (a, a_addr) = spawn_node();
+    let genesis = genesis(&key);
+    a.submit_block(genesis.clone()).unwrap();
+
+    // handshake를 직접 진행한다. a는 Version, Verack, GetHeaders 순서로 보낸다.
+    let mut stream = TcpStream::connect(a_addr).unwrap();
+    let version = Version { version: PROTOCOL_VERSION, nonce: 0, height: 1, genesis: genesis.hash.clone() };
+    write_message(&mut stream, &Message::Version(version)).unwrap();
+    write_message(&mut stream, &Message::Verack).unwrap();
+    assert!(matches!(read_message(&mut stream).unwrap(), Message::Version(_)));
+    assert!(matches!(read_message(&mut stream).unwrap(), Message::Verack));
+    assert!(matches!(read_message(&mut stream).unwrap(), Message::GetHeaders { .. }));
+    wait_until("handshake", || a.peers().len() == 1);
+
+    // PoW는 만족하지만 timestamp가 median-time-past보다 이르다.
+    let invalid = child_at(&a.state().blockchain, &genesis, genesis.header.timestamp, "miner", vec![]);
+    write_message(&mut stream, &Message::Headers(vec![invalid.header])).unwrap();
+    wait_until("disconnect", || a.peers().is_empty());
+    assert!(!a.state().blockchain.headers.contains_key(&invalid.hash));
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use blockchainlib::*;
+use blockchainlib::blockchain::BlockValidationErr;
+use blockchainlib::script::{self, push_num, Op, Script, ScriptContext, ScriptErr};
+use blockchainlib::transaction::{Input, Output, LOCKTIME_THRESHOLD, SEQUENCE_FINAL};
+use common::*;
+
+const SIGHASH: &[u8] = b"sighash";
+
+fn context(lock_time: u64, sequence: u32) -> ScriptContext<'static> {
+    ScriptContext { sighash: SIGHASH, lock_time, sequence }
+}
+
+fn verify(script_sig: &Script, script_pubkey: &Script) -> Result<(), ScriptErr> {
+    script::verify(script_sig, script_pubkey, &context(0, SEQUENCE_FINAL))
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    crypto_hash::digest(crypto_hash::Algorithm::SHA256, data)
+}
+
+#[test]
+fn p2pkh() {
+    let key = Privatekey::new();
+    let other = Privatekey::new();
+    let script_pubkey = Script::p2pkh(&key.address());
+    assert_eq!(script_pubkey.p2pkh_address(), Some(key.address().as_str()));
+
+    verify(&Script::p2pkh_unlock(key.sign(SIGHASH), key.pubkey()), &script_pubkey).unwrap();
+
+    // 다른 digest에 대한 signature
+    let unlock = Script::p2pkh_unlock(key.sign(b"other"), key.pubkey());
+    assert!(matches!(verify(&unlock, &script_pubkey), Err(ScriptErr::EvalFalse)));
+    // address로 hashing되지 않는 pubkey
+    let unlock = Script::p2pkh_unlock(other.sign(SIGHASH), other.pubkey());
+    assert!(matches!(verify(&unlock, &script_pubkey), Err(ScriptErr::VerifyFailed)));
+    // pubkey는 맞지만 signature는 다른 key의 것
+    let unlock = Script::p2pkh_unlock(other.sign(SIGHASH), key.pubkey());
+    assert!(matches!(verify(&unlock, &script_pubkey), Err(ScriptErr::EvalFalse)));
+    // signature가 없음
+    let unlock = Script::new(vec![Op::Push(key.pubkey())]);
+    assert!(matches!(verify(&unlock, &script_pubkey), Err(ScriptErr::StackUnderflow)));
+    // script_sig에는 push만 올 수 있다.
+    let unlock = Script::new(vec![Op::Push(key.sign(SIGHASH)), Op::Push(key.pubkey()), Op::Dup]);
+    assert!(matches!(verify(&unlock, &script_pubkey), Err(ScriptErr::NonPushScriptSig)));
+}
+
+#[test]
+fn multisig() {
+    let keys = [Privatekey::new(), Privatekey::new(), Privatekey::new()];
+    let script_pubkey = Script::multisig(2, keys.iter().map(|key| key.pubkey()).collect());
+    let unlock = |signers: &[&Privatekey]| Script::multisig_unlock(signers.iter().map(|key| key.sign(SIGHASH)).collect());
+
+    for signers in [[&keys[0], &keys[1]], [&keys[0], &keys[2]], [&keys[1], &keys[2]]] {
+        verify(&unlock(&signers), &script_pubkey).unwrap();
+    }
+    // signature는 pubkey와 같은 순서여야 한다.
+    assert!(matches!(verify(&unlock(&[&keys[2], &keys[0]]), &script_pubkey), Err(ScriptErr::EvalFalse)));
+    // 같은 key의 signature를 두 번 쓸 수 없다.
+    assert!(matches!(verify(&unlock(&[&keys[0], &keys[0]]), &script_pubkey), Err(ScriptErr::EvalFalse)));
+    // 모자라거나 모르는 key의 signature
+    assert!(matches!(verify(&unlock(&[&keys[0]]), &script_pubkey), Err(ScriptErr::StackUnderflow)));
+    assert!(matches!(verify(&unlock(&[&keys[0], &Privatekey::new()]), &script_pubkey), Err(ScriptErr::EvalFalse)));
+
+    // required가 total보다 많거나 key가 MAX_MULTISIG_KEYS를 넘는 script는 사용할 수 없다.
+    let invalid = Script::multisig(4, keys.iter().map(|key| key.pubkey()).collect());
+    assert!(matches!(verify(&unlock(&[&keys[0], &keys[1]]), &invalid), Err(ScriptErr::InvalidMultisig)));
+    let too_many = Script::new(vec![push_num(1), push_num(script::MAX_MULTISIG_KEYS as u64 + 1), Op::CheckMultiSig]);
+    assert!(matches!(verify(&unlock(&[&keys[0]]), &too_many), Err(ScriptErr::InvalidMultisig)));
+}
+
+#[test]
+fn htlc() {
+    let (recipient, refund) = (Privatekey::new(), Privatekey::new());
+    let preimage = b"open sesame".to_vec();
+    let script_pubkey = Script::htlc(sha256(&preimage), &recipient.address(), 100, &refund.address());
+    let claim = |key: &Privatekey, preimage: &[u8]| Script::htlc_claim(key.sign(SIGHASH), key.pubkey(), preimage.to_vec());
+    let refund_sig = Script::htlc_refund(refund.sign(SIGHASH), refund.pubkey());
+
+    // preimage를 아는 recipient는 언제든 사용할 수 있다.
+    verify(&claim(&recipient, &preimage), &script_pubkey).unwrap();
+    assert!(matches!(verify(&claim(&recipient, b"wrong"), &script_pubkey), Err(ScriptErr::VerifyFailed)));
+    assert!(matches!(verify(&claim(&refund, &preimage), &script_pubkey), Err(ScriptErr::VerifyFailed)));
+
+    // refund는 tx의 lock_time이 100 이상이고 sequence가 SEQUENCE_FINAL이 아닐 때만 가능하다.
+    let refund_at = |lock_time: u64, sequence: u32| script::verify(&refund_sig, &script_pubkey, &context(lock_time, sequence));
+    refund_at(100, 0).unwrap();
+    refund_at(150, SEQUENCE_FINAL - 1).unwrap();
+    assert!(matches!(refund_at(99, 0), Err(ScriptErr::LockTimeNotReached)));
+    assert!(matches!(refund_at(0, 0), Err(ScriptErr::LockTimeNotReached)));
+    assert!(matches!(refund_at(100, SEQUENCE_FINAL), Err(ScriptErr::LockTimeNotReached)));
+    // 높이와 시각은 비교할 수 없다.
+    assert!(matches!(refund_at(LOCKTIME_THRESHOLD + 100, 0), Err(ScriptErr::LockTimeNotReached)));
+    // recipient의 key로는 refund할 수 없다.
+    let wrong = Script::htlc_refund(recipient.sign(SIGHASH), recipient.pubkey());
+    assert!(matches!(script::verify(&wrong, &script_pubkey, &context(100, 0)), Err(ScriptErr::VerifyFailed)));
+
+    // 분기를 고르는 값이 없거나 IF/ENDIF가 맞지 않는 script
+    let unlock = Script::new(vec![]);
+    assert!(matches!(verify(&unlock, &script_pubkey), Err(ScriptErr::StackUnderflow)));
+    let unbalanced = Script::new(vec![Op::If, push_num(1)]);
+    assert!(matches!(verify(&Script::new(vec![push_num(1)]), &unbalanced), Err(ScriptErr::UnbalancedConditional)));
+}
+
+#[test]
+fn blockchain_checks_input_scripts() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let keys = [Privatekey::new(), Privatekey::new()];
+
+    // P2PKH coin을 2-of-2 multisig로 옮긴다.
+    let multisig = Script::multisig(2, keys.iter().map(|key| key.pubkey()).collect());
+    let mut fund = Transaction::new(
+        vec![Input::new(output(&key.address(), 50), outpoint(&genesis.transactions[0], 0))],
+        vec![Output::with_script(multisig.clone(), 50)],
+    );
+    fund.sign(&key);
+    let funded = child(&blockchain, &genesis, "miner", vec![fund.clone()]);
+    blockchain.update_with_block(funded.clone(), &mut utxo_set).unwrap();
+
+    let spend = |signers: &[&Privatekey]| {
+        let mut transaction = Transaction::new(
+            vec![Input::new(Output::with_script(multisig.clone(), 50), outpoint(&fund, 0))],
+            vec![output(&key.address(), 50)],
+        );
+        let sighash = transaction.signature_hash();
+        transaction.inputs[0].script_sig = Script::multisig_unlock(signers.iter().map(|key| key.sign(&sighash)).collect());
+        transaction
+    };
+    // P2PKH signature로는 multisig output을 사용할 수 없다.
+    let mut p2pkh = spend(&[]);
+    p2pkh.sign(&keys[0]);
+    for transaction in [spend(&[&keys[0]]), spend(&[&keys[1], &keys[0]]), p2pkh] {
+        let result = blockchain.update_with_block(child(&blockchain, &funded, "miner", vec![transaction]), &mut utxo_set);
+        assert!(matches!(result, Err(BlockValidationErr::InvalidScript(_))), "{:?}", result);
+    }
+    blockchain.update_with_block(child(&blockchain, &funded, "miner", vec![spend(&[&keys[0], &keys[1]])]), &mut utxo_set).unwrap();
+    assert_eq!(utxo_set.get_balance(&key.address()), Some(50));
+}
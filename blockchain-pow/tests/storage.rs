@@ -0,0 +1,100 @@
+mod common;
+
+use std::path::PathBuf;
+use blockchainlib::*;
+use blockchainlib::storage::BlockStore;
+use common::*;
+use redb::{Database, TableDefinition};
+
+// test마다 다른 임시 directory
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("storage-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn load(dir: &PathBuf) -> (Blockchain, UtxoSet) {
+    let store = BlockStore::open(dir).unwrap();
+    let mut blockchain = Blockchain::with_coinbase_maturity(0);
+    let mut utxo_set = UtxoSet::new();
+    store.load(&mut blockchain, &mut utxo_set).unwrap();
+    (blockchain, utxo_set)
+}
+
+#[test]
+fn syncs_and_loads_across_reorgs() {
+    let dir = temp_dir("reorg");
+    let (_, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let mut store = BlockStore::open(&dir).unwrap();
+
+    // genesis - a1 - a2
+    let a1 = child(&blockchain, &genesis, "a", vec![]);
+    blockchain.update_with_block(a1.clone(), &mut utxo_set).unwrap();
+    store.sync(&blockchain, &utxo_set).unwrap();
+    let a2 = child(&blockchain, &a1, "a", vec![]);
+    blockchain.update_with_block(a2.clone(), &mut utxo_set).unwrap();
+    store.sync(&blockchain, &utxo_set).unwrap();
+    assert_eq!(store.height().unwrap(), 3);
+
+    // genesis - b1 - b2 - b3로 reorg. 한 번도 active chain이 되지 않은 c1은 저장하지 않는다.
+    let b1 = child(&blockchain, &genesis, "b", vec![]);
+    blockchain.update_with_block(b1.clone(), &mut utxo_set).unwrap();
+    let c1 = child(&blockchain, &genesis, "c", vec![]);
+    blockchain.update_with_block(c1.clone(), &mut utxo_set).unwrap();
+    let b2 = child(&blockchain, &b1, "b", vec![]);
+    blockchain.update_with_block(b2.clone(), &mut utxo_set).unwrap();
+    let b3 = child(&blockchain, &b2, "b", vec![]);
+    blockchain.update_with_block(b3.clone(), &mut utxo_set).unwrap();
+    assert_eq!(blockchain.tip, b3.hash);
+    store.sync(&blockchain, &utxo_set).unwrap();
+
+    assert_eq!(store.tip().unwrap(), Some(b3.hash.clone()));
+    assert_eq!(store.height().unwrap(), 4);
+    for block in [&genesis, &b1, &b2, &b3] {
+        assert_eq!(store.get_block_by_height(block.index).unwrap().unwrap().hash, block.hash);
+    }
+    // 떨어져 나간 block은 남는다.
+    assert!(store.get_block(&a2.hash).unwrap().is_some());
+    assert!(store.get_block(&c1.hash).unwrap().is_none());
+
+    // 짧은 chain으로 돌아가도 높이가 줄어든 만큼 지운다.
+    blockchain.disconnect_tip(&mut utxo_set).unwrap();
+    store.sync(&blockchain, &utxo_set).unwrap();
+    assert_eq!(store.height().unwrap(), 3);
+    assert!(store.get_block_by_height(3).unwrap().is_none());
+    drop(store);
+
+    let (loaded, loaded_utxo_set) = load(&dir);
+    assert_eq!(loaded.tip, b2.hash);
+    assert_eq!(loaded.chain.len(), 3);
+    assert_eq!(snapshot(&loaded_utxo_set), snapshot(&utxo_set));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rebuilds_a_mismatched_utxo_set() {
+    let dir = temp_dir("rebuild");
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let mut store = BlockStore::open(&dir).unwrap();
+    let spend = pay(&key, (outpoint(&genesis.transactions[0], 0), 50), "x", 50);
+    let block = child(&blockchain, &genesis, "miner", vec![spend]);
+    blockchain.update_with_block(block, &mut utxo_set).unwrap();
+    store.sync(&blockchain, &utxo_set).unwrap();
+    drop(store);
+
+    // chainstate의 UTXO 하나를 지우면 저장된 UTXO 수와 맞지 않게 된다.
+    let utxos: TableDefinition<&[u8], &[u8]> = TableDefinition::new("utxos");
+    let db = Database::open(dir.join("chainstate.redb")).unwrap();
+    let write = db.begin_write().unwrap();
+    assert!(write.open_table(utxos).unwrap().pop_first().unwrap().is_some());
+    write.commit().unwrap();
+    drop(db);
+
+    // blocks.dat의 block으로 다시 만들고, 다시 만든 UTXO set을 저장한다.
+    let (loaded, loaded_utxo_set) = load(&dir);
+    assert_eq!(loaded.tip, blockchain.tip);
+    assert_eq!(snapshot(&loaded_utxo_set), snapshot(&utxo_set));
+    let (_, reloaded_utxo_set) = load(&dir);
+    assert_eq!(snapshot(&reloaded_utxo_set), snapshot(&utxo_set));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
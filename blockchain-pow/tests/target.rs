@@ -0,0 +1,134 @@
+use blockchainlib::target::{compact_to_target, target_to_compact, target_to_work, U256};
+
+// 1 << n
+fn pow2(n: u32) -> U256 {
+    U256::ONE << n
+}
+
+#[test]
+fn shifts_across_limb_boundaries() {
+    let value = U256([0x8000_0000_0000_0001, 0, 0, 0]);
+    assert_eq!(value << 1, U256([2, 1, 0, 0]));
+    assert_eq!(value << 64, U256([0, 0x8000_0000_0000_0001, 0, 0]));
+    assert_eq!(value << 127, U256([0, 0x8000_0000_0000_0000, 0x4000_0000_0000_0000, 0]));
+    assert_eq!(value << 255, U256([0, 0, 0, 0x8000_0000_0000_0000]));
+    assert_eq!(value << 256, U256::ZERO);
+    assert_eq!(value << 0, value);
+
+    let value = U256([0, 0, 0, 0x8000_0000_0000_0001]);
+    assert_eq!(value >> 1, U256([0, 0, 0x8000_0000_0000_0000, 0x4000_0000_0000_0000]));
+    assert_eq!(value >> 64, U256([0, 0, 0x8000_0000_0000_0001, 0]));
+    assert_eq!(value >> 191, U256([2, 1, 0, 0]));
+    assert_eq!(value >> 255, U256::ONE);
+    assert_eq!(value >> 256, U256::ZERO);
+
+    for n in [0, 1, 63, 64, 65, 127, 128, 191, 192, 255] {
+        assert_eq!(pow2(n).bits(), n + 1);
+        assert_eq!(pow2(n) >> n, U256::ONE);
+    }
+}
+
+#[test]
+fn divides() {
+    assert_eq!(U256::MAX / U256::ONE, U256::MAX);
+    assert_eq!(U256::MAX / U256::MAX, U256::ONE);
+    assert_eq!(U256::ONE / U256::MAX, U256::ZERO);
+    assert_eq!(pow2(200) / pow2(70), pow2(130));
+    assert_eq!(U256::MAX / pow2(192), U256::from_u64(u64::MAX));
+    // 나머지는 버린다.
+    assert_eq!(U256::from_u64(100) / U256::from_u64(7), U256::from_u64(14));
+    assert_eq!(pow2(130).saturating_add(U256::from_u64(5)) / pow2(64), pow2(66));
+
+    assert_eq!(pow2(200).div_u64(1 << 10), pow2(190));
+    assert_eq!(pow2(200).saturating_add(U256::from_u64(3)).div_rem_u64(1 << 10), (pow2(190), 3));
+    assert_eq!(U256::MAX.div_rem_u64(u64::MAX), (U256([1, 1, 1, 1]), 0));
+}
+
+#[test]
+#[should_panic(expected = "division by zero")]
+fn rejects_division_by_zero() {
+    let _ = U256::ONE / U256::ZERO;
+}
+
+#[test]
+fn multiplies_and_adds_with_overflow_checks() {
+    assert_eq!(pow2(63).checked_mul_u64(2), Some(pow2(64)));
+    assert_eq!(U256::from_u64(u64::MAX).checked_mul_u64(u64::MAX), Some(U256([1, u64::MAX - 1, 0, 0])));
+    assert_eq!(pow2(255).checked_mul_u64(2), None);
+    assert_eq!(pow2(255).saturating_mul_u64(2), U256::MAX);
+    assert_eq!(U256::MAX.overflowing_add(U256::ONE), (U256::ZERO, true));
+    assert_eq!(U256::MAX.saturating_add(U256::ONE), U256::MAX);
+    assert_eq!(U256([u64::MAX, u64::MAX, 0, 0]).saturating_add(U256::ONE), pow2(128));
+}
+
+#[test]
+fn compact_round_trips() {
+    for bits in [0x1f0fffff, 0x1d00ffff, 0x1b0404cb, 0x04123456, 0x0300ffff, 0x02008000, 0x2100ffff] {
+        let target = compact_to_target(bits).unwrap();
+        assert_eq!(target_to_compact(target), bits, "{:#010x}", bits);
+    }
+
+    // btc 문서의 예제
+    assert_eq!(compact_to_target(0x1b0404cb).unwrap(), U256::from_u64(0x0404cb) << (8 * (0x1b - 3)));
+    assert_eq!(compact_to_target(0x1d00ffff).unwrap(), U256::from_u64(0xffff) << 208);
+
+    // exponent가 3 이하이면 mantissa를 오른쪽으로 민다.
+    assert_eq!(compact_to_target(0x03123456).unwrap(), U256::from_u64(0x123456));
+    assert_eq!(compact_to_target(0x02123456).unwrap(), U256::from_u64(0x1234));
+    assert_eq!(compact_to_target(0x01123456).unwrap(), U256::from_u64(0x12));
+    assert_eq!(compact_to_target(0x00123456).unwrap(), U256::ZERO);
+    assert_eq!(target_to_compact(U256::from_u64(0x12)), 0x01120000);
+    assert_eq!(target_to_compact(U256::from_u64(0x1234)), 0x02123400);
+    assert_eq!(target_to_compact(U256::ZERO), 0);
+    assert_eq!(compact_to_target(0).unwrap(), U256::ZERO);
+
+    // mantissa의 최상위 bit가 켜질 target은 한 byte 밀어서 exponent를 늘린다.
+    assert_eq!(target_to_compact(U256::from_u64(0x80)), 0x02008000);
+    assert_eq!(target_to_compact(U256::from_u64(0x800000)), 0x04008000);
+    assert_eq!(target_to_compact(U256::from_u64(0xff) << 200), 0x1b00ff00);
+    for target in [U256::from_u64(0x80), U256::from_u64(0x800000), pow2(255)] {
+        assert_eq!(compact_to_target(target_to_compact(target)).unwrap(), target);
+    }
+
+    // 압축은 하위 byte를 버리므로 원래 target보다 커지지 않는다.
+    let target = U256::from_u64(0x123456789a);
+    assert_eq!(target_to_compact(target), 0x05123456);
+    assert_eq!(compact_to_target(0x05123456).unwrap(), U256::from_u64(0x1234560000));
+    assert_eq!(target_to_compact(U256::MAX), 0x2100ffff);
+    assert!(compact_to_target(target_to_compact(U256::MAX)).unwrap() <= U256::MAX);
+}
+
+#[test]
+fn rejects_negative_and_overflowing_bits() {
+    // 부호 bit가 켜진 음수 target
+    assert_eq!(compact_to_target(0x1d800001), None);
+    assert_eq!(compact_to_target(0x04923456), None);
+    assert_eq!(compact_to_target(0x01fedcba), None);
+    // 부호 bit만 켜지고 mantissa가 0이면 -0, 즉 0이다.
+    assert_eq!(compact_to_target(0x1d800000), Some(U256::ZERO));
+
+    // 256bit를 넘는 target
+    assert_eq!(compact_to_target(0x2101ffff), None);
+    assert_eq!(compact_to_target(0x22010000), None);
+    assert_eq!(compact_to_target(0xff123456), None);
+    // mantissa가 작으면 exponent가 32(0x20)를 넘어도 256bit 안에 들어갈 수 있다.
+    assert_eq!(compact_to_target(0x2100ffff), Some(U256::from_u64(0xffff) << 240));
+    assert_eq!(compact_to_target(0x22000001), Some(pow2(248)));
+}
+
+#[test]
+fn computes_work_at_the_edges() {
+    // 2^256 / (target + 1)
+    assert_eq!(target_to_work(U256::MAX), U256::ONE);
+    assert_eq!(target_to_work(U256::MAX >> 1), U256::from_u64(2));
+    assert_eq!(target_to_work(pow2(255)), U256::ONE);
+    assert_eq!(target_to_work(U256::MAX >> 64), pow2(64));
+    assert_eq!(target_to_work(U256::ONE), pow2(255));
+    // 2^256은 표현할 수 없으므로 target이 0이면 U256::MAX로 포화한다.
+    assert_eq!(target_to_work(U256::ZERO), U256::MAX);
+
+    // target이 작을수록 work가 크다.
+    let easy = compact_to_target(0x1f0fffff).unwrap();
+    let hard = compact_to_target(0x1d00ffff).unwrap();
+    assert!(target_to_work(hard) > target_to_work(easy));
+}
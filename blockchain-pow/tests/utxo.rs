@@ -0,0 +1,73 @@
+mod common;
+
+use std::collections::BTreeMap;
+use blockchainlib::*;
+use blockchainlib::transaction::{Input, OutPoint};
+use common::*;
+
+// by_address index가 utxos와 같은 UTXO를 가리키는지 확인하고, address별 잔액(UTXO가 없는 address는 제외)을 반환한다.
+fn balances(utxo_set: &UtxoSet) -> BTreeMap<String, u64> {
+    let mut expected = BTreeMap::<String, Vec<OutPoint>>::new();
+    for (outpoint, utxo) in utxo_set.iter() {
+        let address = utxo.address().expect("test UTXOs are P2PKH");
+        expected.entry(address.to_owned()).or_default().push(*outpoint);
+    }
+
+    let addresses = utxo_set.addresses().cloned().collect::<Vec<_>>();
+    assert_eq!(addresses.len(), expected.len());
+    let mut balances = BTreeMap::new();
+    for (address, mut outpoints) in expected {
+        outpoints.sort();
+        let unspent = utxo_set.list_unspent(&address);
+        assert_eq!(unspent.iter().map(|(outpoint, _)| *outpoint).collect::<Vec<_>>(), outpoints);
+        assert!(unspent.iter().all(|(outpoint, utxo)| utxo.address() == Some(address.as_str()) && utxo_set.get(outpoint).is_some()));
+        balances.insert(address.clone(), utxo_set.get_balance(&address).unwrap());
+    }
+    assert_eq!(balances.values().sum::<u64>(), utxo_set.total_value().unwrap());
+    balances
+}
+
+fn expected(balances: &[(&str, u64)]) -> BTreeMap<String, u64> {
+    balances.iter().map(|(address, value)| (address.to_string(), *value)).collect()
+}
+
+#[test]
+fn indexes_utxos_by_address() {
+    let (key, mut blockchain, mut utxo_set, genesis) = setup(0);
+    let owner = key.address();
+    let before = balances(&utxo_set);
+    assert_eq!(before, expected(&[(&owner, 50)]));
+
+    // 같은 block 안에서 owner에게 돌아온 output을 다시 x에게 보낸다.
+    let mut split = Transaction::new(
+        vec![Input::new(output(&owner, 50), outpoint(&genesis.transactions[0], 0))],
+        vec![output("x", 30), output(&owner, 20)],
+    );
+    split.sign(&key);
+    let forward = pay(&key, (outpoint(&split, 1), 20), "x", 20);
+    let a1 = child(&blockchain, &genesis, "a1", vec![split.clone(), forward.clone()]);
+    blockchain.update_with_block(a1.clone(), &mut utxo_set).unwrap();
+    assert_eq!(balances(&utxo_set), expected(&[("a1", 50), ("x", 50)]));
+    assert!(utxo_set.list_unspent(&owner).is_empty());
+    assert_eq!(utxo_set.get_balance(&owner), Some(0));
+    let mut unspent = vec![outpoint(&split, 0), outpoint(&forward, 0)];
+    unspent.sort();
+    assert_eq!(utxo_set.list_unspent("x").iter().map(|(outpoint, _)| *outpoint).collect::<Vec<_>>(), unspent);
+
+    // reorg로 a1의 tx가 되돌려지면 사용된 UTXO는 원래 address로 돌아가고, 생성된 UTXO는 index에서도 사라진다.
+    let b1 = child(&blockchain, &genesis, "b1", vec![pay(&key, (outpoint(&genesis.transactions[0], 0), 50), "y", 50)]);
+    blockchain.update_with_block(b1.clone(), &mut utxo_set).unwrap();
+    assert_eq!(balances(&utxo_set), expected(&[("a1", 50), ("x", 50)]));
+    let b2 = child(&blockchain, &b1, "b2", vec![]);
+    blockchain.update_with_block(b2.clone(), &mut utxo_set).unwrap();
+    assert_eq!(blockchain.tip, b2.hash);
+    assert_eq!(balances(&utxo_set), expected(&[("b1", 50), ("b2", 50), ("y", 50)]));
+    assert!(utxo_set.list_unspent("x").is_empty() && utxo_set.list_unspent("a1").is_empty());
+
+    // undo로 b branch를 떼어내면 genesis만 연결된 상태로 돌아간다.
+    blockchain.disconnect_tip(&mut utxo_set).unwrap();
+    assert_eq!(balances(&utxo_set), expected(&[("b1", 50), ("y", 50)]));
+    blockchain.disconnect_tip(&mut utxo_set).unwrap();
+    assert_eq!(balances(&utxo_set), before);
+    assert!(utxo_set.addresses().all(|address| address == &owner));
+}
@@ -0,0 +1,134 @@
+use blockchainlib::*;
+use blockchainlib::blockchain::GENESIS_BITS;
+use blockchainlib::coin_selection::{CoinSelector, Strategy};
+use blockchainlib::key;
+use blockchainlib::transaction::Output;
+use blockchainlib::wallet::{ExtendedKey, Wallet, WalletErr, HARDENED};
+
+const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+fn mine(blockchain: &mut Blockchain, utxo_set: &mut UtxoSet, mempool: &Mempool, miner_addr: &str) -> Block {
+    let mut block = if blockchain.chain.is_empty() {
+        let mut block = Block::new(0, now(), vec![0; 32], vec![], GENESIS_BITS);
+        block.add_transaction(Transaction::coinbase(0, vec![Output::new(miner_addr, 50)]));
+        block
+    } else {
+        blockchain.block_template(mempool, miner_addr.to_owned())
+    };
+    block.check_merkle_and_mining().unwrap();
+    blockchain.update_with_block(block.clone(), utxo_set).unwrap();
+    block
+}
+
+// SLIP-0010 test vector 1 for ed25519
+#[test]
+fn derives_slip10_test_vector() {
+    let master = ExtendedKey::master(&hex::decode("000102030405060708090a0b0c0d0e0f").unwrap());
+    assert_eq!(hex::encode(master.key()), "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7");
+    assert_eq!(hex::encode(master.chain_code()), "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb");
+
+    let child = master.derive_child(0);
+    assert_eq!(hex::encode(child.key()), "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3");
+    assert_eq!(hex::encode(child.chain_code()), "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69");
+
+    // index는 항상 hardened로 유도되므로 hardened bit를 붙여도 같다.
+    assert_eq!(master.derive_child(HARDENED).key(), child.key());
+    assert_eq!(master.derive_path(&[0, 1]).key(), child.derive_child(1).key());
+}
+
+#[test]
+fn addresses_are_checksummed() {
+    let address = Privatekey::new().address();
+    assert!(key::is_valid_address(&address));
+
+    let mut typo = address.clone().into_bytes();
+    let last = typo.len() - 1;
+    typo[last] = if typo[last] == b'2' { b'3' } else { b'2' };
+    assert!(!key::is_valid_address(std::str::from_utf8(&typo).unwrap()));
+    assert!(!key::is_valid_address("bob"));
+    assert!(!key::is_valid_address(""));
+}
+
+#[test]
+fn same_mnemonic_derives_same_addresses() {
+    let mut a = Wallet::from_mnemonic(MNEMONIC, "").unwrap();
+    let mut b = Wallet::from_mnemonic(MNEMONIC, "").unwrap();
+    let mut c = Wallet::from_mnemonic(MNEMONIC, "passphrase").unwrap();
+    let first = a.new_address();
+    assert_eq!(first, b.new_address());
+    assert_ne!(first, c.new_address());
+    assert_ne!(first, a.new_address());
+    assert!(matches!(Wallet::from_mnemonic("abandon abandon", ""), Err(WalletErr::InvalidMnemonic(_))));
+}
+
+#[test]
+fn tracks_and_spends_utxos() {
+    let mut blockchain = Blockchain::with_coinbase_maturity(1);
+    let mut utxo_set = UtxoSet::new();
+    let mut mempool = Mempool::default();
+    let mut sender = Wallet::generate("");
+    let mut recipient = Wallet::generate("");
+
+    // receive_address는 coin을 받고 sync하기 전까지 바뀌지 않는다.
+    let address = sender.receive_address();
+    assert_eq!(sender.receive_address(), address);
+    mine(&mut blockchain, &mut utxo_set, &mempool, &address);
+    sender.sync(&utxo_set);
+    assert_ne!(sender.receive_address(), address);
+    mine(&mut blockchain, &mut utxo_set, &mempool, &sender.receive_address());
+    sender.sync(&utxo_set);
+    assert_eq!(sender.balance(), 100);
+
+    let selector = CoinSelector::new(Strategy::LargestFirst, 1, 1);
+    assert!(matches!(
+        sender.create_transaction(&blockchain, "bob", 10, &selector),
+        Err(WalletErr::InvalidAddress(_))
+    ));
+
+    // 서로 다른 address의 UTXO 두 개를 사용해야 하는 금액
+    let transaction = sender.create_transaction(&blockchain, &recipient.new_address(), 70, &selector).unwrap();
+    assert_eq!(transaction.inputs.len(), 2);
+    // 사용한 UTXO는 block에 포함되기 전에도 다시 고르지 않는다.
+    assert_eq!(sender.balance(), 0);
+    assert!(sender.create_transaction(&blockchain, &recipient.new_address(), 1, &selector).is_err());
+
+    mempool.add_transaction(transaction, &blockchain, &utxo_set).unwrap();
+    let block = mine(&mut blockchain, &mut utxo_set, &mempool, "miner");
+    mempool.remove_for_block(&block);
+
+    sender.sync(&utxo_set);
+    recipient.sync(&utxo_set);
+    assert_eq!(recipient.balance(), 70);
+    assert_eq!(sender.balance(), 100 - 70 - 3);
+
+    // mnemonic만으로 복구해도 receive, change address의 coin을 모두 찾는다.
+    let mut restored = Wallet::from_mnemonic(&sender.mnemonic(), "").unwrap();
+    restored.sync(&utxo_set);
+    assert_eq!(restored.balance(), sender.balance());
+}
+
+#[test]
+fn save_and_load() {
+    let path = std::env::temp_dir().join(format!("wallet-test-{}.wallet", std::process::id()));
+    let mut wallet = Wallet::generate("passphrase");
+    let used = [wallet.new_address(), wallet.new_address()];
+    wallet.save(&path).unwrap();
+    // mnemonic이 저장되므로 소유자만 읽을 수 있어야 한다.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    // passphrase는 저장되지 않으며, 다른 passphrase로는 열 수 없다.
+    let contents = std::fs::read(&path).unwrap();
+    assert!(!contents.windows(b"passphrase".len()).any(|window| window == b"passphrase"));
+    assert!(matches!(Wallet::load(&path, ""), Err(WalletErr::WrongPassphrase)));
+    let mut loaded = Wallet::load(&path, "passphrase").unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.fingerprint(), wallet.fingerprint());
+    assert_eq!(loaded.mnemonic(), wallet.mnemonic());
+    assert_eq!(loaded.addresses(), &used);
+    assert!(loaded.owns(&used[0]));
+    assert_eq!(loaded.new_address(), wallet.new_address());
+}